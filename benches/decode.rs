@@ -0,0 +1,68 @@
+//! Measures the speedup of decoding the DATA segment in parallel.
+//!
+//! Run with `cargo bench --features parallel --bench decode`. The `serial` benchmark decodes the
+//! file with the serial decoder that is used without the `parallel` feature. The `threads`
+//! benchmarks decode the same file with the parallel decoder in rayon thread pools of different
+//! sizes, so the 1 thread case measures the overhead of splitting the work.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use fcs_rs::data::{decode_events, decode_events_serial, ByteOrder};
+use fcs_rs::text::read_metadata;
+use fcs_rs::writer::{write_fcs, WriteOptions};
+use fcs_rs::{FcsError, FcsFile};
+use std::collections::HashMap;
+use std::io::{BufReader, Cursor};
+
+const EXAMPLE_FILE: &str = "./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs";
+const EVENTS: usize = 1_000_000;
+
+/// The signature shared by `decode_events` and `decode_events_serial`.
+type Decoder = fn(&[u8], &str, &ByteOrder, usize, usize, &HashMap<String, String>) -> Result<Vec<Vec<f64>>, FcsError>;
+
+/// Writes a file with `EVENTS` events, repeating the events of the example file.
+fn large_file() -> Vec<u8> {
+    let mut flow_sample = FcsFile::open(EXAMPLE_FILE).unwrap().read().unwrap();
+    let example = flow_sample.data.clone();
+    while flow_sample.data.height() < EVENTS {
+        flow_sample.data.vstack_mut(&example).unwrap();
+    }
+    flow_sample.data = flow_sample.data.slice(0, EVENTS);
+    flow_sample.data.align_chunks();
+
+    let mut bytes = Vec::new();
+    write_fcs(&mut bytes, &flow_sample, &WriteOptions::default()).unwrap();
+    bytes
+}
+
+fn decode(c: &mut Criterion) {
+    let bytes = large_file();
+    let metadata = read_metadata(&mut BufReader::new(Cursor::new(&bytes))).unwrap();
+    let begin = metadata["$BEGINDATA"].trim().parse::<usize>().unwrap();
+    let end = metadata["$ENDDATA"].trim().parse::<usize>().unwrap();
+    let data = &bytes[begin..=end];
+    let n_params = metadata["$PAR"].trim().parse::<usize>().unwrap();
+    let decode = |decoder: Decoder| {
+        decoder(data, "F", &ByteOrder::LittleEndian, EVENTS, n_params, &metadata).unwrap()
+    };
+
+    let mut group = c.benchmark_group("decode");
+    group.sample_size(20);
+    group.throughput(Throughput::Bytes(data.len() as u64));
+
+    group.bench_function("serial", |b| b.iter(|| decode(decode_events_serial)));
+
+    let mut threads = vec![1, 2, 4];
+    threads.push(rayon::current_num_threads());
+    threads.sort_unstable();
+    threads.dedup();
+    for n_threads in threads {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(n_threads).build().unwrap();
+        group.bench_with_input(BenchmarkId::new("threads", n_threads), &n_threads, |b, _| {
+            b.iter(|| pool.install(|| decode(decode_events)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, decode);
+criterion_main!(benches);
@@ -0,0 +1,97 @@
+//! Compares reading the DATA segment of a large FCS file with the decoder this crate used
+//! before DATA was decoded event by event, and with the current decoder through `parse_data`,
+//! `FcsFile::open` and `FcsFile::open_mapped`.
+//!
+//! Run with `cargo bench --bench parse_data`. The `baseline` benchmark is a copy of the old
+//! decoder, kept here only as a reference: it made one pass over the segment per parameter,
+//! reading `$TOT` values into a buffer of its own and a `HashMap` column each time. It reads
+//! the same number of bytes as the current decoder but, like the old code, assigns them to the
+//! wrong parameters, so its values are not checked.
+
+use byteorder::{ByteOrder, LittleEndian};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use fcs_rs::data::{create_dataframe, parse_data};
+use fcs_rs::text::read_metadata;
+use fcs_rs::writer::{write_fcs_file, WriteOptions};
+use fcs_rs::FcsFile;
+use polars::prelude::DataFrame;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+
+const EXAMPLE_FILE: &str = "./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs";
+const EVENTS: usize = 1_000_000;
+
+/// Writes a little endian float file with `EVENTS` events to `path`, repeating the events of
+/// the example file.
+fn write_large_file(path: &str) {
+    let mut flow_sample = FcsFile::open(EXAMPLE_FILE).unwrap().read().unwrap();
+    let example = flow_sample.data.clone();
+    while flow_sample.data.height() < EVENTS {
+        flow_sample.data.vstack_mut(&example).unwrap();
+    }
+    flow_sample.data = flow_sample.data.slice(0, EVENTS);
+    flow_sample.data.align_chunks();
+
+    write_fcs_file(path, &flow_sample, &WriteOptions::default()).unwrap();
+}
+
+/// The old per-parameter decoder, for little endian float data.
+fn baseline(reader: &mut BufReader<File>, metadata: &HashMap<String, String>) -> DataFrame {
+    let n_params = metadata["$PAR"].trim().parse::<usize>().unwrap();
+    let n_events = metadata["$TOT"].trim().parse::<usize>().unwrap();
+    let data_start = metadata["$BEGINDATA"].trim().parse::<u64>().unwrap();
+
+    reader.seek(SeekFrom::Start(data_start)).unwrap();
+    let mut parameters: HashMap<String, Vec<f64>> = HashMap::new();
+    for i in 1..=n_params {
+        let mut float_buffer = vec![0; n_events * std::mem::size_of::<f32>()];
+        reader.read_exact(&mut float_buffer).unwrap();
+        let mut events = Vec::with_capacity(n_events);
+        for i in 0..n_events {
+            events.push(LittleEndian::read_f32(&float_buffer[i * 4..(i + 1) * 4]) as f64);
+        }
+        parameters.insert(metadata[&format!("$P{}N", i)].clone(), events);
+    }
+
+    let column_titles = parameters.keys().cloned().collect::<Vec<_>>();
+    let data = parameters.values().cloned().collect::<Vec<_>>();
+    create_dataframe(&column_titles, &data).unwrap()
+}
+
+fn read_data(c: &mut Criterion) {
+    let path = std::env::temp_dir().join("fcs_rs_bench_parse_data.fcs");
+    let path = path.to_str().unwrap();
+    write_large_file(path);
+
+    let mut group = c.benchmark_group("parse_data");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(EVENTS as u64));
+
+    group.bench_function("baseline", |b| {
+        b.iter(|| {
+            let mut reader = BufReader::new(File::open(path).unwrap());
+            let metadata = read_metadata(&mut reader).unwrap();
+            assert_eq!(baseline(&mut reader, &metadata).height(), EVENTS);
+        })
+    });
+    group.bench_function("parse_data", |b| {
+        b.iter(|| {
+            let mut reader = BufReader::new(File::open(path).unwrap());
+            let metadata = read_metadata(&mut reader).unwrap();
+            assert_eq!(parse_data(&mut reader, &metadata).unwrap().data.height(), EVENTS);
+        })
+    });
+    group.bench_function("FcsFile::open", |b| {
+        b.iter(|| assert_eq!(FcsFile::open(path).unwrap().read().unwrap().data.height(), EVENTS))
+    });
+    group.bench_function("FcsFile::open_mapped", |b| {
+        b.iter(|| assert_eq!(FcsFile::open_mapped(path).unwrap().read().unwrap().data.height(), EVENTS))
+    });
+    group.finish();
+
+    std::fs::remove_file(path).unwrap();
+}
+
+criterion_group!(benches, read_data);
+criterion_main!(benches);
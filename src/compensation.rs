@@ -0,0 +1,559 @@
+//! Spillover compensation of flow cytometry samples.
+//!
+//! The spillover matrix is read from the `$SPILLOVER` keyword (FCS 3.1), the `SPILL` keyword
+//! written by many instruments, or the `$COMP` keyword (FCS 2.0 and FCS 3.0). Row `i` of the
+//! matrix holds the fraction of the signal of channel `i` that is detected in every channel,
+//! so observed values are the true values multiplied by the matrix. Compensation multiplies
+//! the observed values by its inverse.
+
+use polars::prelude::*;
+use crate::{FcsError, HashMap};
+use crate::data::FlowSample;
+
+/// The keywords that may hold a spillover matrix, in the order they are looked up.
+pub const SPILLOVER_KEYWORDS: [&str; 3] = ["$SPILLOVER", "SPILL", "$COMP"];
+
+/// A square spillover matrix and the channels it applies to.
+///
+/// # Fields
+///
+/// * `channels` - The names of the channels, in matrix order. These are matched to the `$PnN`
+///   names of a sample, or to its `$PnS` labels if no name matches.
+/// * `values` - The matrix in row-major order. `values[i][j]` is the fraction of the signal of
+///   `channels[i]` that is detected in `channels[j]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpilloverMatrix {
+    pub channels: Vec<String>,
+    pub values: Vec<Vec<f64>>,
+}
+
+impl SpilloverMatrix {
+    /// Creates a spillover matrix from channel names and rows of values.
+    ///
+    /// # Arguments
+    ///
+    /// * `channels` - The names of the channels, in matrix order.
+    /// * `values` - One row of values per channel, each with one value per channel.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the SpilloverMatrix or an FcsError.
+    ///
+    /// # Errors
+    ///
+    /// Returns `FcsError::InvalidData` if the matrix is not square with one row per channel, or
+    /// if a channel appears more than once.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fcs_rs::compensation::SpilloverMatrix;
+    ///
+    /// let matrix = SpilloverMatrix::new(
+    ///     vec!["FL1-A".to_string(), "FL2-A".to_string()],
+    ///     vec![vec![1.0, 0.1], vec![0.05, 1.0]],
+    /// ).unwrap();
+    /// assert_eq!(matrix.values[0][1], 0.1);
+    /// ```
+    pub fn new(channels: Vec<String>, values: Vec<Vec<f64>>) -> Result<Self, FcsError> {
+        let n = channels.len();
+        if n == 0 || values.len() != n || values.iter().any(|row| row.len() != n) {
+            return Err(FcsError::InvalidData(format!(
+                "Spillover matrix must have one row and one column per channel for {} channels", n
+            )));
+        }
+        for (i, channel) in channels.iter().enumerate() {
+            if channels[..i].contains(channel) {
+                return Err(FcsError::InvalidData(format!("Duplicate channel {} in spillover matrix", channel)));
+            }
+        }
+
+        Ok(SpilloverMatrix { channels, values })
+    }
+
+    /// Parses the value of a `$SPILLOVER` or `SPILL` keyword.
+    ///
+    /// The value holds the number of channels `n`, followed by `n` channel names and the
+    /// `n * n` matrix values in row-major order, all separated by commas.
+    ///
+    /// # Arguments
+    ///
+    /// * `keyword` - The keyword the value was read from, used in error messages.
+    /// * `value` - The value of the keyword.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the SpilloverMatrix or an FcsError.
+    ///
+    /// # Errors
+    ///
+    /// Returns `FcsError::InvalidKeyword` if the value does not hold a well-formed matrix.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fcs_rs::compensation::SpilloverMatrix;
+    ///
+    /// let matrix = SpilloverMatrix::parse("$SPILLOVER", "2,FL1-A,FL2-A,1,0.1,0.05,1").unwrap();
+    /// assert_eq!(matrix.channels, vec!["FL1-A", "FL2-A"]);
+    /// assert_eq!(matrix.values, vec![vec![1.0, 0.1], vec![0.05, 1.0]]);
+    /// ```
+    pub fn parse(keyword: &str, value: &str) -> Result<Self, FcsError> {
+        let invalid = || FcsError::InvalidKeyword { keyword: keyword.to_string(), value: value.to_string() };
+
+        let fields: Vec<&str> = value.split(',').map(|field| field.trim()).collect();
+        let n = fields[0].parse::<usize>().map_err(|_| invalid())?;
+        let n_fields = n.checked_mul(n).and_then(|n_values| n_values.checked_add(n + 1));
+        if n == 0 || n_fields != Some(fields.len()) {
+            return Err(invalid());
+        }
+
+        let channels = fields[1..=n].iter().map(|name| name.to_string()).collect();
+        let values = parse_rows(&fields[1 + n..], n).ok_or_else(invalid)?;
+
+        Self::new(channels, values).map_err(|_| invalid())
+    }
+
+    /// Reads the spillover matrix of a sample from its metadata.
+    ///
+    /// The keywords in `SPILLOVER_KEYWORDS` are tried in order. `$COMP` holds `n` followed by
+    /// the `n * n` matrix values in row-major order, without channel names; it applies to
+    /// parameters 1 to `n`, so their `$PnN` names are used as channel names.
+    ///
+    /// # Arguments
+    ///
+    /// * `metadata` - A reference to a HashMap containing metadata from the FCS file.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the SpilloverMatrix, `None` if the metadata has no spillover
+    /// matrix, or an FcsError.
+    ///
+    /// # Errors
+    ///
+    /// Returns `FcsError::InvalidKeyword` if the keyword does not hold a well-formed matrix.
+    pub fn from_metadata(metadata: &HashMap<String, String>) -> Result<Option<Self>, FcsError> {
+        read_spillover(metadata, |index| metadata.get(&format!("$P{}N", index)).cloned())
+    }
+
+    /// Returns the inverse of the matrix, computed by Gauss-Jordan elimination with partial
+    /// pivoting.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the rows of the inverse matrix or an FcsError.
+    ///
+    /// # Errors
+    ///
+    /// Returns `FcsError::SingularMatrix` if the matrix cannot be inverted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fcs_rs::compensation::SpilloverMatrix;
+    ///
+    /// let matrix = SpilloverMatrix::parse("SPILL", "2,A,B,2,0,0,4").unwrap();
+    /// assert_eq!(matrix.invert().unwrap(), vec![vec![0.5, 0.0], vec![0.0, 0.25]]);
+    /// ```
+    pub fn invert(&self) -> Result<Vec<Vec<f64>>, FcsError> {
+        let n = self.channels.len();
+        let mut left = self.values.clone();
+        let mut inverse: Vec<Vec<f64>> = (0..n)
+            .map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect())
+            .collect();
+
+        for col in 0..n {
+            let pivot = (col..n)
+                .max_by(|&a, &b| left[a][col].abs().total_cmp(&left[b][col].abs()))
+                .unwrap_or(col);
+            if left[pivot][col].is_nan() || left[pivot][col].abs() <= 1e-12 {
+                return Err(FcsError::SingularMatrix);
+            }
+            left.swap(col, pivot);
+            inverse.swap(col, pivot);
+
+            let scale = left[col][col];
+            for j in 0..n {
+                left[col][j] /= scale;
+                inverse[col][j] /= scale;
+            }
+
+            for row in 0..n {
+                let factor = left[row][col];
+                if row == col || factor == 0.0 {
+                    continue;
+                }
+                for j in 0..n {
+                    left[row][j] -= factor * left[col][j];
+                    inverse[row][j] -= factor * inverse[col][j];
+                }
+            }
+        }
+
+        Ok(inverse)
+    }
+}
+
+/// Parses `n * n` numbers into `n` rows, or returns `None` if one of them is not a number.
+fn parse_rows(fields: &[&str], n: usize) -> Option<Vec<Vec<f64>>> {
+    fields.chunks(n)
+        .map(|row| row.iter().map(|field| field.parse::<f64>().ok()).collect())
+        .collect()
+}
+
+/// Reads the spillover matrix from the first of `SPILLOVER_KEYWORDS` in `keywords`. `name`
+/// returns the name of the parameter with a given index, which names the channels of `$COMP`.
+fn read_spillover(
+    keywords: &HashMap<String, String>,
+    name: impl Fn(usize) -> Option<String>,
+) -> Result<Option<SpilloverMatrix>, FcsError> {
+    for keyword in SPILLOVER_KEYWORDS {
+        let Some(value) = keywords.get(keyword) else {
+            continue;
+        };
+
+        let matrix = if keyword == "$COMP" {
+            parse_comp(value, name)?
+        } else {
+            SpilloverMatrix::parse(keyword, value)?
+        };
+        return Ok(Some(matrix));
+    }
+
+    Ok(None)
+}
+
+/// Parses the value of a `$COMP` keyword, naming its channels by the names of parameters 1 to
+/// `n`.
+fn parse_comp(value: &str, name: impl Fn(usize) -> Option<String>) -> Result<SpilloverMatrix, FcsError> {
+    let invalid = || FcsError::InvalidKeyword { keyword: "$COMP".to_string(), value: value.to_string() };
+
+    let fields: Vec<&str> = value.split(',').map(|field| field.trim()).collect();
+    let n = fields[0].parse::<usize>().map_err(|_| invalid())?;
+    let n_fields = n.checked_mul(n).and_then(|n_values| n_values.checked_add(1));
+    if n == 0 || n_fields != Some(fields.len()) {
+        return Err(invalid());
+    }
+
+    let channels = (1..=n)
+        .map(|index| name(index).ok_or_else(invalid))
+        .collect::<Result<Vec<_>, _>>()?;
+    let values = parse_rows(&fields[1..], n).ok_or_else(invalid)?;
+
+    SpilloverMatrix::new(channels, values).map_err(|_| invalid())
+}
+
+impl FlowSample {
+    /// Returns the spillover matrix stored in the metadata of the sample, if any.
+    ///
+    /// See `SpilloverMatrix::from_metadata` for the keywords that are read. The channels of
+    /// `$COMP` are named by the parameters of `metadata`.
+    pub fn spillover(&self) -> Result<Option<SpilloverMatrix>, FcsError> {
+        let metadata = &self.metadata;
+        read_spillover(&metadata.keywords, |index| {
+            metadata.parameters.iter()
+                .find(|parameter| parameter.index == index)
+                .map(|parameter| parameter.name.clone())
+        })
+    }
+
+    /// Compensates the sample with the spillover matrix stored in its metadata.
+    ///
+    /// # Returns
+    ///
+    /// A Result indicating success or an FcsError.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an FcsError if:
+    /// - The metadata has no spillover matrix (`FcsError::MissingSpillover`).
+    /// - The spillover keyword does not hold a well-formed matrix.
+    /// - See `compensate_with` for the other errors.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fcs_rs::FcsFile;
+    ///
+    /// let fcs_file = FcsFile::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+    /// let mut flow_sample = fcs_file.read().unwrap();
+    /// flow_sample.compensate().unwrap();
+    /// println!("{:?}", flow_sample.data);
+    /// ```
+    pub fn compensate(&mut self) -> Result<(), FcsError> {
+        let matrix = self.spillover()?.ok_or(FcsError::MissingSpillover)?;
+        self.compensate_with(&matrix)
+    }
+
+    /// Compensates the sample with the given spillover matrix and records it in `compensation`.
+    ///
+    /// Every channel of the matrix is matched to a column by its `$PnN` name, or by its `$PnS`
+    /// label if no name matches and exactly one channel has that label. The matched columns are
+    /// replaced by their compensated values. An event with a null value in any matched column
+    /// gets null values in all of them.
+    ///
+    /// # Arguments
+    ///
+    /// * `matrix` - The spillover matrix to compensate with.
+    ///
+    /// # Returns
+    ///
+    /// A Result indicating success or an FcsError.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an FcsError if:
+    /// - A channel of the matrix does not match any column (`FcsError::UnmatchedChannel`).
+    /// - The matrix cannot be inverted (`FcsError::SingularMatrix`).
+    /// - A matched column cannot be read as floating point values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fcs_rs::FcsFile;
+    /// use fcs_rs::compensation::SpilloverMatrix;
+    ///
+    /// let fcs_file = FcsFile::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+    /// let mut flow_sample = fcs_file.read().unwrap();
+    /// let matrix = SpilloverMatrix::parse("SPILL", "2,BL1-A,YL1-A,1,0.02,0.1,1").unwrap();
+    /// flow_sample.compensate_with(&matrix).unwrap();
+    /// ```
+    pub fn compensate_with(&mut self, matrix: &SpilloverMatrix) -> Result<(), FcsError> {
+        let names = matrix.channels.iter()
+            .map(|channel| self.match_column(channel))
+            .collect::<Result<Vec<_>, _>>()?;
+        let inverse = matrix.invert()?;
+
+        let to_fcs_error = |err: PolarsError| FcsError::InvalidData(err.to_string());
+        let mut observed = Vec::with_capacity(names.len());
+        for name in &names {
+            let series = self.data.column(name).map_err(to_fcs_error)?
+                .cast(&DataType::Float64).map_err(to_fcs_error)?;
+            let values: Vec<Option<f64>> = series.f64().map_err(to_fcs_error)?.into_iter().collect();
+            observed.push(values);
+        }
+
+        let n_events = self.data.height();
+        let mut compensated: Vec<Vec<Option<f64>>> = vec![Vec::with_capacity(n_events); names.len()];
+        let mut event = vec![0.0; names.len()];
+        for row in 0..n_events {
+            let complete = observed.iter()
+                .zip(event.iter_mut())
+                .all(|(column, value)| column[row].map(|v| *value = v).is_some());
+
+            for (j, column) in compensated.iter_mut().enumerate() {
+                let value = complete.then(|| {
+                    event.iter().zip(&inverse).map(|(value, inverse_row)| value * inverse_row[j]).sum()
+                });
+                column.push(value);
+            }
+        }
+
+        for (name, values) in names.iter().zip(compensated) {
+            self.data.with_column(Series::new(name, values)).map_err(to_fcs_error)?;
+        }
+        self.compensation = Some(matrix.clone());
+
+        Ok(())
+    }
+
+    /// Returns the name of the column a spillover channel applies to.
+    fn match_column(&self, channel: &str) -> Result<String, FcsError> {
+        if let Some(matched) = self.channels.iter().find(|candidate| candidate.name == channel) {
+            return Ok(matched.name.clone());
+        }
+
+        let mut labelled = self.channels.iter().filter(|candidate| candidate.label.as_deref() == Some(channel));
+        match (labelled.next(), labelled.next()) {
+            (Some(matched), None) => Ok(matched.name.clone()),
+            _ => Err(FcsError::UnmatchedChannel(channel.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FcsFile;
+    use crate::data::{create_dataframe, Channel};
+    use crate::metadata::Metadata;
+
+    const EXAMPLE_FILE: &str = "./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs";
+
+    /// Builds a sample with columns A, B and C whose true values are spilled by `spill`.
+    fn spilled_sample(spill: &[Vec<f64>], keywords: &[(&str, &str)]) -> FlowSample {
+        let truth = [vec![100.0, 0.0, 50.0], vec![0.0, 200.0, 10.0], vec![30.0, 30.0, 30.0]];
+        let mut columns = vec![Vec::new(), Vec::new(), Vec::new()];
+        for event in &truth {
+            for (j, column) in columns.iter_mut().enumerate().take(spill.len()) {
+                column.push((0..spill.len()).map(|i| event[i] * spill[i][j]).sum());
+            }
+            for column in columns.iter_mut().skip(spill.len()) {
+                column.push(event[2]);
+            }
+        }
+
+        let names = vec!["A".to_string(), "B".to_string(), "C".to_string()];
+        let mut parameters: HashMap<String, String> = keywords.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        for (i, name) in names.iter().enumerate() {
+            parameters.insert(format!("$P{}N", i + 1), name.clone());
+        }
+
+        FlowSample {
+            data: create_dataframe(&names, &columns).unwrap(),
+            metadata: Metadata::from_keywords(parameters),
+            channels: names.iter().enumerate()
+                .map(|(i, name)| Channel { index: i + 1, name: name.clone(), label: Some(format!("CD{}", i + 1)) })
+                .collect(),
+            transforms: HashMap::new(),
+            compensation: None,
+            analysis: HashMap::new(),
+        }
+    }
+
+    fn column(sample: &FlowSample, name: &str) -> Vec<f64> {
+        sample.data.column(name).unwrap().f64().unwrap().into_no_null_iter().collect()
+    }
+
+    fn assert_close(actual: &[f64], expected: &[f64]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-9, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_parse_spillover() {
+        let matrix = SpilloverMatrix::parse("$SPILLOVER", "3,BL1-A,YL1-A,RL1-A,1,0,0,0,1,0,0,0,1").unwrap();
+        assert_eq!(matrix.channels, vec!["BL1-A", "YL1-A", "RL1-A"]);
+        assert_eq!(matrix.values[1], vec![0.0, 1.0, 0.0]);
+
+        let matrix = SpilloverMatrix::parse("SPILL", " 2, FL1 , FL2 ,1, 1e-2, 0.3 ,1").unwrap();
+        assert_eq!(matrix.channels, vec!["FL1", "FL2"]);
+        assert_eq!(matrix.values, vec![vec![1.0, 0.01], vec![0.3, 1.0]]);
+    }
+
+    #[test]
+    fn test_parse_spillover_invalid() {
+        for value in ["", "x,A,1", "0", "2,A,B,1,0,0", "2,A,B,1,0,0,1,5", "2,A,B,1,x,0,1", "2,A,A,1,0,0,1", "4294967296,A"] {
+            let result = SpilloverMatrix::parse("$SPILLOVER", value);
+            assert!(
+                matches!(result, Err(FcsError::InvalidKeyword { ref keyword, .. }) if keyword == "$SPILLOVER"),
+                "Unexpected result {:?} for {:?}", result, value
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_metadata() {
+        let metadata = HashMap::from([
+            ("$P1N".to_string(), "FL1".to_string()),
+            ("$P2N".to_string(), "FL2".to_string()),
+            ("$COMP".to_string(), "2,1,0.5,0,1".to_string()),
+        ]);
+        let matrix = SpilloverMatrix::from_metadata(&metadata).unwrap().unwrap();
+        assert_eq!(matrix.channels, vec!["FL1", "FL2"]);
+        assert_eq!(matrix.values, vec![vec![1.0, 0.5], vec![0.0, 1.0]]);
+
+        // $SPILLOVER takes precedence over the vendor keywords
+        let mut metadata = metadata;
+        metadata.insert("SPILL".to_string(), "1,FL2,1".to_string());
+        metadata.insert("$SPILLOVER".to_string(), "1,FL1,1".to_string());
+        let matrix = SpilloverMatrix::from_metadata(&metadata).unwrap().unwrap();
+        assert_eq!(matrix.channels, vec!["FL1"]);
+
+        assert_eq!(SpilloverMatrix::from_metadata(&HashMap::new()).unwrap(), None);
+
+        let metadata = HashMap::from([("$COMP".to_string(), "4294967296,1".to_string())]);
+        let result = SpilloverMatrix::from_metadata(&metadata);
+        assert!(matches!(result, Err(FcsError::InvalidKeyword { ref keyword, .. }) if keyword == "$COMP"), "{:?}", result);
+    }
+
+    #[test]
+    fn test_invert() {
+        let matrix = SpilloverMatrix::parse("SPILL", "3,A,B,C,0,1,0,1,0,0,0.2,0.1,1").unwrap();
+        let inverse = matrix.invert().unwrap();
+        for (i, row) in matrix.values.iter().enumerate() {
+            for j in 0..3 {
+                let product: f64 = row.iter().zip(&inverse).map(|(value, inverse_row)| value * inverse_row[j]).sum();
+                assert!((product - if i == j { 1.0 } else { 0.0 }).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn test_invert_singular() {
+        let matrix = SpilloverMatrix::parse("SPILL", "2,A,B,1,2,2,4").unwrap();
+        assert!(matches!(matrix.invert(), Err(FcsError::SingularMatrix)));
+
+        let matrix = SpilloverMatrix::parse("SPILL", "2,A,B,0,0,0,0").unwrap();
+        assert!(matches!(matrix.invert(), Err(FcsError::SingularMatrix)));
+    }
+
+    #[test]
+    fn test_compensate() {
+        let spill = vec![vec![1.0, 0.1], vec![0.25, 1.0]];
+        let mut sample = spilled_sample(&spill, &[("$SPILLOVER", "2,A,B,1,0.1,0.25,1")]);
+        assert_close(&column(&sample, "B"), &[10.0, 200.0, 33.0]);
+        assert_eq!(sample.compensation, None);
+
+        sample.compensate().unwrap();
+        assert_eq!(sample.compensation, Some(SpilloverMatrix::new(vec!["A".to_string(), "B".to_string()], spill).unwrap()));
+        assert_close(&column(&sample, "A"), &[100.0, 0.0, 30.0]);
+        assert_close(&column(&sample, "B"), &[0.0, 200.0, 30.0]);
+        assert_eq!(column(&sample, "C"), vec![50.0, 10.0, 30.0]);
+        assert_eq!(sample.get_dataframe_columns(), vec!["A", "B", "C"]);
+    }
+
+    #[test]
+    fn test_compensate_with_labels() {
+        let spill = vec![vec![1.0, 0.5], vec![0.0, 1.0]];
+        let mut sample = spilled_sample(&spill, &[]);
+        let matrix = SpilloverMatrix::new(vec!["CD1".to_string(), "B".to_string()], spill).unwrap();
+
+        sample.compensate_with(&matrix).unwrap();
+        assert_close(&column(&sample, "A"), &[100.0, 0.0, 30.0]);
+        assert_close(&column(&sample, "B"), &[0.0, 200.0, 30.0]);
+    }
+
+    #[test]
+    fn test_compensate_nulls() {
+        let mut sample = spilled_sample(&[vec![1.0, 0.0], vec![0.0, 1.0]], &[]);
+        sample.data.with_column(Series::new("A", &[Some(1.0), None, Some(3.0)])).unwrap();
+        let matrix = SpilloverMatrix::parse("SPILL", "2,A,B,1,0,0.5,1").unwrap();
+
+        sample.compensate_with(&matrix).unwrap();
+        let b: Vec<Option<f64>> = sample.data.column("B").unwrap().f64().unwrap().into_iter().collect();
+        assert_eq!(b[1], None);
+        assert!(b[0].is_some() && b[2].is_some());
+    }
+
+    #[test]
+    fn test_compensate_errors() {
+        let mut sample = spilled_sample(&[vec![1.0]], &[]);
+        assert!(matches!(sample.compensate(), Err(FcsError::MissingSpillover)));
+
+        let matrix = SpilloverMatrix::parse("SPILL", "2,A,FL9,1,0,0,1").unwrap();
+        let result = sample.compensate_with(&matrix);
+        assert!(matches!(result, Err(FcsError::UnmatchedChannel(ref name)) if name == "FL9"));
+
+        let matrix = SpilloverMatrix::parse("SPILL", "2,A,B,1,1,1,1").unwrap();
+        assert!(matches!(sample.compensate_with(&matrix), Err(FcsError::SingularMatrix)));
+        assert_eq!(column(&sample, "A"), vec![100.0, 0.0, 30.0]);
+    }
+
+    #[test]
+    fn test_compensate_example_file() {
+        let fcs_file = FcsFile::open(EXAMPLE_FILE).unwrap();
+        let original = fcs_file.read().unwrap();
+        let mut sample = fcs_file.read().unwrap();
+
+        let matrix = sample.spillover().unwrap().unwrap();
+        assert_eq!(matrix.channels, vec!["BL1-A", "YL1-A", "RL1-A"]);
+
+        sample.compensate().unwrap();
+        assert_eq!(sample.data, original.data);
+    }
+}
@@ -0,0 +1,137 @@
+//! CRC-16 checksums of FCS 3.1 datasets.
+//!
+//! FCS 3.1 stores a CRC-CCITT checksum of every byte from the start of the HEADER through the
+//! last byte of the last segment of a dataset, written directly after that segment as 8 ASCII
+//! digits. A CRC of `00000000` means the writer did not compute one.
+
+/// The CRC-CCITT polynomial x^16 + x^12 + x^5 + 1.
+const POLYNOMIAL: u16 = 0x1021;
+
+/// Number of ASCII characters of a stored CRC.
+pub const CRC_LEN: usize = 8;
+
+/// The CRC of every possible value of the byte that is shifted in.
+const TABLE: [u16; 256] = {
+    let mut table = [0u16; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u16) << 8;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ POLYNOMIAL } else { crc << 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Computes a CRC-CCITT checksum incrementally, so a dataset can be checked without holding it
+/// in memory.
+///
+/// The CRC starts at 0, is computed most significant bit first and is not inverted at the end.
+///
+/// # Examples
+///
+/// ```
+/// use fcs_rs::crc::{crc16, Crc16};
+///
+/// let mut crc = Crc16::new();
+/// crc.update(b"1234");
+/// crc.update(b"56789");
+/// assert_eq!(crc.value(), crc16(b"123456789"));
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Crc16 {
+    value: u16,
+}
+
+impl Crc16 {
+    /// Creates a CRC of no bytes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `bytes` to the checksum.
+    pub fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.value = (self.value << 8) ^ TABLE[((self.value >> 8) as u8 ^ byte) as usize];
+        }
+    }
+
+    /// Returns the checksum of the bytes added so far.
+    pub fn value(&self) -> u16 {
+        self.value
+    }
+}
+
+/// Returns the CRC-CCITT checksum of `bytes`.
+///
+/// # Examples
+///
+/// ```
+/// use fcs_rs::crc::crc16;
+///
+/// assert_eq!(crc16(b"123456789"), 0x31C3);
+/// ```
+pub fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc = Crc16::new();
+    crc.update(bytes);
+    crc.value()
+}
+
+/// Formats a CRC as it is stored in an FCS file: 8 zero-padded decimal digits.
+pub fn format_crc(crc: u16) -> String {
+    format!("{:0width$}", crc, width = CRC_LEN)
+}
+
+/// Parses a stored CRC.
+///
+/// Returns `Ok(None)` if the dataset has no CRC, i.e. the stored value is all zeros or blank,
+/// the file ends before it, or it holds other bytes than digits and spaces, such as the HEADER
+/// of the next dataset or padding. Otherwise returns the stored value, or the text itself if it
+/// is not a 16 bit decimal number.
+pub fn parse_crc(stored: &[u8]) -> Result<Option<u16>, String> {
+    if stored.len() < CRC_LEN
+        || stored.iter().all(|&byte| byte == b'0' || byte == b' ')
+        || !stored.iter().all(|&byte| byte.is_ascii_digit() || byte == b' ')
+    {
+        return Ok(None);
+    }
+
+    let text = String::from_utf8_lossy(stored);
+    text.trim().parse::<u16>().map(Some).map_err(|_| text.into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc16() {
+        assert_eq!(crc16(b""), 0);
+        assert_eq!(crc16(b"123456789"), 0x31C3);
+        assert_eq!(crc16(b"A"), 0x58E5);
+
+        let mut crc = Crc16::new();
+        for byte in b"123456789" {
+            crc.update(&[*byte]);
+        }
+        assert_eq!(crc.value(), 0x31C3);
+    }
+
+    #[test]
+    fn test_format_and_parse_crc() {
+        assert_eq!(format_crc(0x31C3), "00012739");
+        assert_eq!(parse_crc(b"00012739"), Ok(Some(0x31C3)));
+        assert_eq!(parse_crc(b"   12739"), Ok(Some(0x31C3)));
+        assert_eq!(parse_crc(b"00000000"), Ok(None));
+        assert_eq!(parse_crc(b"0001"), Ok(None));
+        assert_eq!(parse_crc(b""), Ok(None));
+        assert_eq!(parse_crc(b"        "), Ok(None));
+        assert_eq!(parse_crc(b"00099999"), Err("00099999".to_string()));
+        assert_eq!(parse_crc(b"0000ABCD"), Ok(None));
+        assert_eq!(parse_crc(b"FCS3.0  "), Ok(None));
+    }
+}
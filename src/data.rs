@@ -1,41 +1,526 @@
 use byteorder::{
-    BigEndian, LittleEndian,
+    ByteOrder as _, LittleEndian,
 };
+use chrono::{DateTime, FixedOffset};
 use std::fmt;
 use std::io::{Read, Seek};
-use crate::{FcsError, HashMap, File, BufReader, SeekFrom};
+use std::ops::{Range, RangeInclusive};
+use crate::{FcsError, HashMap, BufReader, Segment, SeekFrom};
+use crate::compensation::SpilloverMatrix;
+use crate::header::{check_offsets, Header};
+use crate::metadata::Metadata;
 use polars::prelude::*;
 
+pub mod transform;
+
+pub use self::transform::Transform;
+
 /// Store the names of the parameters in the FCS file.
 #[derive(Debug)]
 pub struct ColumnNames {
     keys_vec: Vec<String>,
 }
 
+/// A measured parameter of a flow cytometry sample.
+///
+/// # Fields
+///
+/// * `index` - The one-based parameter index `n` used by the `$Pn*` keywords.
+/// * `name` - The short name from `$PnN`. This is also the name of the DataFrame column.
+/// * `label` - The optional label from `$PnS`, e.g. the marker or fluorochrome.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Channel {
+    pub index: usize,
+    pub name: String,
+    pub label: Option<String>,
+}
+
+/// The type of the values stored in the data segment, given by `$DATATYPE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataType {
+    /// `F`: 32-bit IEEE floating point.
+    Float,
+    /// `D`: 64-bit IEEE floating point.
+    Double,
+    /// `I`: unsigned binary integer with the width given by `$PnB`.
+    Integer,
+    /// `A`: ASCII encoded number, either `$PnB` characters wide or, when `$PnB` is `*`,
+    /// separated from the next value by delimiters.
+    Ascii,
+}
+
+impl DataType {
+    /// Parses a `$DATATYPE` value.
+    pub fn from_keyword(value: &str) -> Result<Self, FcsError> {
+        match value.trim() {
+            "F" => Ok(DataType::Float),
+            "D" => Ok(DataType::Double),
+            "I" => Ok(DataType::Integer),
+            "A" => Ok(DataType::Ascii),
+            _ => Err(FcsError::InvalidData("FCS data type not supported. Must be F, D, I or A".to_string())),
+        }
+    }
+
+    /// Returns the `$DATATYPE` value for this data type.
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            DataType::Float => "F",
+            DataType::Double => "D",
+            DataType::Integer => "I",
+            DataType::Ascii => "A",
+        }
+    }
+}
+
+/// The byte order of the values stored in the data segment, given by `$BYTEORD`.
+///
+/// `$BYTEORD` lists, for each byte of a stored value, its significance, where 1 is the least
+/// significant byte. Little and big endian orders apply to values of any width. Any other
+/// permutation, such as `3,4,1,2`, only applies to values as wide as the permutation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ByteOrder {
+    /// `1,2,3,4` (or `1,2`, `1,2,3,4,5,6,7,8`, ...): least significant byte first.
+    LittleEndian,
+    /// `4,3,2,1` (or `2,1`, `8,7,6,5,4,3,2,1`, ...): most significant byte first.
+    BigEndian,
+    /// Any other order, holding the significance of each stored byte from 1 to the value width.
+    Permutation(Vec<u8>),
+}
+
+impl ByteOrder {
+    /// Parses a `$BYTEORD` value.
+    ///
+    /// Returns `FcsError::InvalidKeyword` if the value is not a permutation of `1..=n` for a
+    /// width `n` of 1 to 16 bytes.
+    pub fn from_keyword(value: &str) -> Result<Self, FcsError> {
+        let invalid = || FcsError::InvalidKeyword { keyword: "$BYTEORD".to_string(), value: value.to_string() };
+
+        let order = value.trim()
+            .split(',')
+            .map(|byte| byte.trim().parse::<u8>().map_err(|_| invalid()))
+            .collect::<Result<Vec<u8>, FcsError>>()?;
+        let width = order.len();
+        let mut sorted = order.clone();
+        sorted.sort_unstable();
+        if width > 16 || !sorted.iter().enumerate().all(|(i, &byte)| byte as usize == i + 1) {
+            return Err(invalid());
+        }
+
+        if order.iter().enumerate().all(|(i, &byte)| byte as usize == i + 1) {
+            Ok(ByteOrder::LittleEndian)
+        } else if order.iter().enumerate().all(|(i, &byte)| byte as usize == width - i) {
+            Ok(ByteOrder::BigEndian)
+        } else {
+            Ok(ByteOrder::Permutation(order))
+        }
+    }
+
+    /// Returns the `$BYTEORD` value for this byte order.
+    pub fn keyword(&self) -> String {
+        match self {
+            ByteOrder::LittleEndian => "1,2,3,4".to_string(),
+            ByteOrder::BigEndian => "4,3,2,1".to_string(),
+            ByteOrder::Permutation(order) => order.iter()
+                .map(|byte| byte.to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+        }
+    }
+
+    /// Reorders the bytes of a value stored in this byte order so the least significant byte
+    /// comes first.
+    ///
+    /// Returns an FcsError if this is a permutation of a different width than the value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fcs_rs::data::ByteOrder;
+    ///
+    /// let byte_order = ByteOrder::from_keyword("3,4,1,2").unwrap();
+    /// let mut bytes = [0x34, 0x12, 0x78, 0x56];
+    /// byte_order.to_little_endian(&mut bytes).unwrap();
+    /// assert_eq!(u32::from_le_bytes(bytes), 0x12345678);
+    /// ```
+    pub fn to_little_endian(&self, bytes: &mut [u8]) -> Result<(), FcsError> {
+        match self {
+            ByteOrder::LittleEndian => {},
+            ByteOrder::BigEndian => bytes.reverse(),
+            ByteOrder::Permutation(_) if bytes.len() == 1 => {},
+            ByteOrder::Permutation(order) => {
+                self.check_width(order, bytes.len())?;
+                let stored = bytes.to_vec();
+                for (&byte, &significance) in stored.iter().zip(order) {
+                    bytes[significance as usize - 1] = byte;
+                }
+            },
+        }
+        Ok(())
+    }
+
+    /// Reorders the bytes of a value from least significant byte first to this byte order. This
+    /// is the inverse of `to_little_endian`.
+    ///
+    /// Returns an FcsError if this is a permutation of a different width than the value.
+    pub fn from_little_endian(&self, bytes: &mut [u8]) -> Result<(), FcsError> {
+        match self {
+            ByteOrder::LittleEndian => {},
+            ByteOrder::BigEndian => bytes.reverse(),
+            ByteOrder::Permutation(_) if bytes.len() == 1 => {},
+            ByteOrder::Permutation(order) => {
+                self.check_width(order, bytes.len())?;
+                let little_endian = bytes.to_vec();
+                for (byte, &significance) in bytes.iter_mut().zip(order) {
+                    *byte = little_endian[significance as usize - 1];
+                }
+            },
+        }
+        Ok(())
+    }
+
+    fn check_width(&self, order: &[u8], width: usize) -> Result<(), FcsError> {
+        if order.len() != width {
+            return Err(FcsError::InvalidData(format!(
+                "$BYTEORD {} cannot be applied to {} byte values", self.keyword(), width
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Whether the values of a parameter are converted from the raw values stored in the data segment.
+///
+/// * `Scaled` - Values are converted to a linear scale with `Scale`.
+/// * `Raw` - Values are kept exactly as they are stored in the data segment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ValueMode {
+    #[default]
+    Scaled,
+    Raw,
+}
+
+/// A channel of an FCS file, selected by its `$PnN` name or by its parameter index `n`, which
+/// starts at 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChannelSelector {
+    Name(String),
+    Index(usize),
+}
+
+impl From<&str> for ChannelSelector {
+    fn from(name: &str) -> Self {
+        ChannelSelector::Name(name.to_string())
+    }
+}
+
+impl From<String> for ChannelSelector {
+    fn from(name: String) -> Self {
+        ChannelSelector::Name(name)
+    }
+}
+
+impl From<usize> for ChannelSelector {
+    fn from(index: usize) -> Self {
+        ChannelSelector::Index(index)
+    }
+}
+
+impl fmt::Display for ChannelSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChannelSelector::Name(name) => write!(f, "{}", name),
+            ChannelSelector::Index(index) => write!(f, "{}", index),
+        }
+    }
+}
+
+/// Options selecting which channels and events `FcsFile::read_with` decodes.
+///
+/// By default every channel and every event is read, with channel values as `FcsFile::read`
+/// returns them. Channels that are not selected are skipped while decoding, and only the bytes
+/// of the selected event range are read.
+///
+/// # Examples
+///
+/// ```
+/// use fcs_rs::FcsFile;
+/// use fcs_rs::data::ReadOptions;
+///
+/// let fcs_file = FcsFile::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+/// let options = ReadOptions::new()
+///     .channels(["FSC-A", "SSC-A"])
+///     .events(100..1100)
+///     .step(10);
+/// let flow_sample = fcs_file.read_with(&options).unwrap();
+/// assert_eq!(flow_sample.data.shape(), (100, 2));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadOptions {
+    channels: Option<Vec<ChannelSelector>>,
+    start: usize,
+    end: Option<usize>,
+    limit: Option<usize>,
+    step: usize,
+    mode: ValueMode,
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        Self {
+            channels: None,
+            start: 0,
+            end: None,
+            limit: None,
+            step: 1,
+            mode: ValueMode::Scaled,
+        }
+    }
+}
+
+impl ReadOptions {
+    /// Creates options that read every channel and every event.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads only the given channels, in the given order, selected by `$PnN` name or by
+    /// parameter index.
+    ///
+    /// Reading fails with `FcsError::UnknownChannel` if a channel does not exist, and with
+    /// `FcsError::InvalidData` if a channel is selected twice.
+    pub fn channels<I, C>(mut self, channels: I) -> Self
+    where
+        I: IntoIterator<Item = C>,
+        C: Into<ChannelSelector>,
+    {
+        self.channels = Some(channels.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Reads only the events in `range`, counted from 0. The range is cut short at the last
+    /// event of the file.
+    pub fn events(mut self, range: Range<usize>) -> Self {
+        self.start = range.start;
+        self.end = Some(range.end);
+        self
+    }
+
+    /// Reads at most `limit` events.
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Reads every `step`th event, starting with the first event of the range.
+    ///
+    /// A step of 0 is reported as an `FcsError::InvalidData` when the options are used.
+    pub fn step(mut self, step: usize) -> Self {
+        self.step = step;
+        self
+    }
+
+    /// Converts values as requested by `mode`. Defaults to `ValueMode::Scaled`.
+    pub fn mode(mut self, mode: ValueMode) -> Self {
+        self.mode = mode;
+        self
+    }
+}
+
+/// The amplification of a parameter, given by `$PnE` and `$PnG`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Amplification {
+    /// `$PnE/0,0/`: linear amplification with the gain from `$PnG`, which defaults to 1.
+    Linear { gain: f64 },
+    /// `$PnE/f1,f2/`: logarithmic amplification over `decades` decades, where `offset` is the
+    /// linear value of a raw value of 0.
+    Logarithmic { decades: f64, offset: f64 },
+}
+
+/// Converts the raw values of a parameter to channel values on a linear scale.
+///
+/// # Fields
+///
+/// * `range` - The range from `$PnR`. Raw integer values lie in `0..range`.
+/// * `bit_mask` - The mask that clears the unused high bits of raw integer values, or `None`
+///   for floating point parameters, which are not masked.
+/// * `amplification` - The amplification from `$PnE` and `$PnG`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scale {
+    pub range: f64,
+    pub bit_mask: Option<u64>,
+    pub amplification: Amplification,
+}
+
+impl Scale {
+    /// Reads the scale of a parameter from its `$PnR`, `$PnE` and `$PnG` keywords.
+    ///
+    /// The bit mask keeps the bits needed to store `range - 1` and only applies to integer
+    /// parameters. Logarithmic amplification only applies to integer and ASCII parameters, and an
+    /// offset of 0, which older files write for logarithmic parameters, is read as 1. Missing
+    /// keywords leave values unchanged.
+    ///
+    /// # Arguments
+    ///
+    /// * `metadata` - A reference to a HashMap containing metadata from the FCS file.
+    /// * `index` - The one-based parameter index `n`.
+    /// * `data_type` - The data type of the parameter.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the Scale or an FcsError.
+    ///
+    /// # Errors
+    ///
+    /// Returns `FcsError::InvalidKeyword` if `$PnR`, `$PnE` or `$PnG` cannot be parsed, if
+    /// `$PnG` is not positive, or if a logarithmic parameter has no positive `$PnR`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use fcs_rs::data::{DataType, Scale};
+    ///
+    /// let metadata = HashMap::from([
+    ///     ("$P1R".to_string(), "1024".to_string()),
+    ///     ("$P1E".to_string(), "4,1".to_string()),
+    /// ]);
+    /// let scale = Scale::from_metadata(&metadata, 1, DataType::Integer).unwrap();
+    /// assert_eq!(scale.apply(512.0), 100.0);
+    /// ```
+    pub fn from_metadata(
+        metadata: &HashMap<String, String>, 
+        index: usize, 
+        data_type: DataType,
+    ) -> Result<Self, FcsError> {
+        let range = match metadata.get(&format!("$P{}R", index)) {
+            Some(value) => Some(parse_number::<f64>(&format!("$P{}R", index), value)?),
+            None => None,
+        };
+
+        let bit_mask = match (data_type, range) {
+            (DataType::Integer, Some(range)) if range >= 1.0 => {
+                let bits = u64::BITS - (range.ceil() as u64 - 1).leading_zeros();
+                Some(u64::MAX.checked_shr(u64::BITS - bits).unwrap_or(0))
+            },
+            _ => None,
+        };
+
+        let (decades, offset) = match metadata.get(&format!("$P{}E", index)) {
+            Some(value) => {
+                let keyword = format!("$P{}E", index);
+                let (decades, offset) = value.split_once(',')
+                    .ok_or_else(|| FcsError::InvalidKeyword { keyword: keyword.clone(), value: value.clone() })?;
+                (parse_number::<f64>(&keyword, decades)?, parse_number::<f64>(&keyword, offset)?)
+            },
+            None => (0.0, 0.0),
+        };
+
+        let amplification = if decades > 0.0 && matches!(data_type, DataType::Integer | DataType::Ascii) {
+            if !range.is_some_and(|range| range > 0.0) {
+                let keyword = format!("$P{}R", index);
+                let value = metadata.get(&keyword).cloned().unwrap_or_default();
+                return Err(FcsError::InvalidKeyword { keyword, value });
+            }
+            Amplification::Logarithmic { decades, offset: if offset > 0.0 { offset } else { 1.0 } }
+        } else {
+            let keyword = format!("$P{}G", index);
+            let gain = match metadata.get(&keyword) {
+                Some(value) => parse_number::<f64>(&keyword, value)?,
+                None => 1.0,
+            };
+            if gain <= 0.0 {
+                return Err(FcsError::InvalidKeyword { keyword, value: gain.to_string() });
+            }
+            Amplification::Linear { gain }
+        };
+
+        Ok(Scale {
+            range: range.unwrap_or(0.0),
+            bit_mask,
+            amplification,
+        })
+    }
+
+    /// Converts a raw value to a channel value.
+    ///
+    /// The raw value is masked first. Logarithmic parameters are then converted with
+    /// `10^(decades * value / range) * offset`, and linear parameters are divided by their gain.
+    pub fn apply(&self, raw: f64) -> f64 {
+        let value = match self.bit_mask {
+            Some(mask) => ((raw as u64) & mask) as f64,
+            None => raw,
+        };
+
+        match self.amplification {
+            Amplification::Linear { gain } => value / gain,
+            Amplification::Logarithmic { decades, offset } => {
+                10f64.powf(decades * value / self.range) * offset
+            },
+        }
+    }
+}
+
+/// Parses a numeric keyword value, which may be padded with spaces.
+fn parse_number<T: std::str::FromStr>(keyword: &str, value: &str) -> Result<T, FcsError> {
+    value.trim().parse::<T>().map_err(|_| FcsError::InvalidKeyword {
+        keyword: keyword.to_string(),
+        value: value.to_string(),
+    })
+}
+
 /// A structure to represent a flow cytometry sample.
 ///
 /// # Fields
 ///
-/// * `data` - A HashMap containing the data for each channel. The key is the channel name, 
-/// and the value is a vector of measurements.
-/// * `parameters` - A HashMap containing the parameters of the flow cytometry experiment. 
-/// The key is the parameter name, and the value is the parameter value.
+/// * `data` - A DataFrame containing the data for each channel. Columns are named by `$PnN`
+///   and appear in parameter index order.
+/// * `metadata` - The keywords of the sample, with the standard keywords parsed into typed
+///   fields. `FcsFile` also fills in the FCS version, the text segment each keyword was read
+///   from and the conflicts between text segments. Every keyword is read from here, including
+///   by `parameters`, `Display` and `write_fcs`.
+/// * `channels` - The channels of the columns of `data`, in column order. They are the
+///   channels of `metadata`, or those selected by `ReadOptions` when reading.
+/// * `transforms` - The transform applied to each transformed column, keyed by column name.
+///   Transforms are recorded by `apply_transform` and removed by `invert_transform`. They are
+///   written to files by `write_fcs` and loaded again when the file is read.
+/// * `compensation` - The spillover matrix `data` was compensated with by `compensate` or
+///   `compensate_with`, or `None` if it was not compensated. `write_fcs` leaves the spillover
+///   keywords out of files of compensated samples, so they are not compensated twice.
+/// * `analysis` - The keyword/value pairs of the analysis segment, such as gate statistics.
+///   Empty if the file has no analysis segment. `write_fcs` writes them to an analysis segment.
 #[derive(Debug)]
 pub struct FlowSample {
     pub data: DataFrame,
-    pub parameters: HashMap<String, String>,
+    pub metadata: Metadata,
+    pub channels: Vec<Channel>,
+    pub transforms: HashMap<String, Transform>,
+    pub compensation: Option<SpilloverMatrix>,
+    pub analysis: HashMap<String, String>,
 }
 
 impl fmt::Display for FlowSample {
     /// Formats the `FlowSample` for display.
     ///
     /// The display includes general information about the sample such as machine type, 
-    /// run times, and volume, as well as details about the measurement axes.
+    /// run times, and volume, as well as details about the measurement axes, read from the
+    /// typed fields of `metadata`. FCS 3.2 files without `$BTIM` and `$ETIM` show
+    /// `$BEGINDATETIME` and `$ENDDATETIME` instead. The labels are those of `channels`.
     ///
     /// # Arguments
     ///
     /// * `f` - The formatter.
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn or_unknown(value: Option<impl fmt::Display>) -> String {
+            value.map_or_else(|| "Unknown".to_string(), |value| value.to_string())
+        }
+
+        let metadata = &self.metadata;
+        let begin = metadata.begin_time.map(|time| time.to_string())
+            .or_else(|| metadata.begin_datetime.map(|datetime| datetime.to_rfc3339()));
+        let end = metadata.end_time.map(|time| time.to_string())
+            .or_else(|| metadata.end_datetime.map(|datetime| datetime.to_rfc3339()));
+        let date = metadata.date.map(|date| date.format("%d-%b-%Y").to_string().to_uppercase());
+
         write!(
             f,
             "
@@ -46,22 +531,18 @@ FlowSample:
     Date: {}
     File: {}
     Volume run: {}",
-            self.parameters.get("$CYT").unwrap_or(&"Unknown".to_string()),
-            self.parameters.get("$BTIM").unwrap_or(&"Unknown".to_string()),
-            self.parameters.get("$ETIM").unwrap_or(&"Unknown".to_string()),
-            self.parameters.get("$DATE").unwrap_or(&"Unknown".to_string()),
-            self.parameters.get("$FIL").unwrap_or(&"Unknown".to_string()),
-            self.parameters.get("$VOL").unwrap_or(&"Unknown".to_string())
+            or_unknown(metadata.cytometer.as_ref()),
+            or_unknown(begin),
+            or_unknown(end),
+            or_unknown(date),
+            or_unknown(metadata.file_name.as_ref()),
+            or_unknown(metadata.volume),
         )?;
 
-        let n_params: u32 = self.parameters.get("$PAR").unwrap_or(&"0".to_string()).parse().unwrap_or(0);
         writeln!(f, "\n    Labels: ")?;
-        for i in 1..=n_params {
-            if self.parameters.contains_key(&format!("$P{}S", i)) {
-                writeln!(f, "        {} ({})", 
-                    self.parameters.get(&format!("$P{}N", i)).unwrap(), 
-                    self.parameters.get(&format!("$P{}S", i)).unwrap()
-                )?
+        for channel in &self.channels {
+            if let Some(label) = &channel.label {
+                writeln!(f, "        {} ({})", channel.name, label)?
             }
         }
         Ok(())
@@ -69,6 +550,21 @@ FlowSample:
 }
 
 impl FlowSample {
+    /// Returns every keyword/value pair of the sample, from `metadata.keywords`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fcs_rs::FcsFile;
+    /// 
+    /// let fcs_file = FcsFile::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+    /// let flow_sample = fcs_file.read().unwrap();
+    /// assert_eq!(flow_sample.parameters()["$TOT"], "8821");
+    /// ```
+    pub fn parameters(&self) -> &HashMap<String, String> {
+        &self.metadata.keywords
+    }
+
     /// Extracts and returns the column names from a FlowSample as a vector of strings.
     ///
     /// This function retrieves the column names from the `data` field of the provided
@@ -106,9 +602,88 @@ impl FlowSample {
         names.keys_vec
     }
 
+    /// Returns the channel with the given one-based parameter index.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The parameter index `n` of the channel.
+    ///
+    /// # Returns
+    ///
+    /// The matching `Channel`, or `None` if the sample has no parameter with that index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fcs_rs::FcsFile;
+    /// 
+    /// let fcs_file = FcsFile::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+    /// let flow_sample = fcs_file.read().unwrap();
+    /// let channel = flow_sample.get_channel(6).unwrap();
+    /// assert_eq!(channel.name, "RL1-A");
+    /// ```
+    pub fn get_channel(&self, index: usize) -> Option<&Channel> {
+        self.channels.iter().find(|channel| channel.index == index)
+    }
+
+    /// Returns the `$PnS` label of the channel with the given `$PnN` name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The `$PnN` short name of the channel, which is also its column name.
+    ///
+    /// # Returns
+    ///
+    /// The label, or `None` if the channel does not exist or has no label.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fcs_rs::FcsFile;
+    /// 
+    /// let fcs_file = FcsFile::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+    /// let flow_sample = fcs_file.read().unwrap();
+    /// assert_eq!(flow_sample.get_label("RL1-A"), Some("APC-A"));
+    /// ```
+    pub fn get_label(&self, name: &str) -> Option<&str> {
+        self.channels.iter()
+            .find(|channel| channel.name == name)
+            .and_then(|channel| channel.label.as_deref())
+    }
+
+    /// Returns the serial number of the cytometer from `$CYTSN`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fcs_rs::FcsFile;
+    /// 
+    /// let fcs_file = FcsFile::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+    /// let flow_sample = fcs_file.read().unwrap();
+    /// assert_eq!(flow_sample.get_cytometer_serial(), Some("1AFC202730315"));
+    /// ```
+    pub fn get_cytometer_serial(&self) -> Option<&str> {
+        self.metadata.cytometer_serial.as_deref()
+    }
+
+    /// Returns the date-time at which acquisition began, from the FCS 3.2 keyword
+    /// `$BEGINDATETIME`.
+    pub fn get_begin_datetime(&self) -> Option<DateTime<FixedOffset>> {
+        self.metadata.begin_datetime
+    }
+
+    /// Returns the date-time at which acquisition ended, from the FCS 3.2 keyword
+    /// `$ENDDATETIME`.
+    pub fn get_end_datetime(&self) -> Option<DateTime<FixedOffset>> {
+        self.metadata.end_datetime
+    }
+
     /// Applies the Arcsinh transformation to the data of specified channels.
     ///
-    /// The Arcsinh transform is a combination of logarithmic and linear scales.
+    /// The Arcsinh transform is a combination of logarithmic and linear scales: it is close to
+    /// linear around zero and close to logarithmic for large positive and negative values, so
+    /// it is defined for negative compensated values. It is applied with `Transform::Arcsinh`,
+    /// which is recorded in `transforms` for every transformed channel. Null values are kept.
     ///
     /// # Arguments
     ///
@@ -117,54 +692,211 @@ impl FlowSample {
     ///
     /// # Returns
     ///
-    /// A Result indicating success or an I/O error.
+    /// A Result indicating success or an FcsError.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an FcsError if:
+    /// - A channel does not exist (`FcsError::UnknownChannel`).
+    /// - The cofactor is not positive (`FcsError::InvalidTransform`).
+    /// - A channel is not a column of floating point values (`FcsError::InvalidData`).
+    /// - A channel already has a transform (`FcsError::InvalidTransform`).
+    ///
+    /// No channel is transformed when an error is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fcs_rs::FcsFile;
+    ///
+    /// let fcs_file = FcsFile::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+    /// let mut flow_sample = fcs_file.read().unwrap();
+    /// flow_sample.arcsinh_transform(150.0, &["BL1-A".to_string(), "RL1-A".to_string()]).unwrap();
+    /// ```
     pub fn arcsinh_transform(
         &mut self, 
         cofactor: f64, 
         channels: &[String]
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        fn arcsinh(x: f64, cofactor: f64) -> f64 {
-            (x / cofactor).ln() + ((x / cofactor).powi(2) + 1.0).sqrt().ln()
-        }
-
-        for channel in channels.iter() {
-            match self.data.column(channel) {
-                Ok(series) => {
-                    let transformed_series = series.f64()?
-                    .apply(|v| Some(arcsinh(v.expect("REASON"), cofactor)))
-                        .into_series();
-                    self.data.with_column(transformed_series)?;
-                },
-                Err(err) => {
-                    eprintln!("Error during arcsinh transformation: {}", err)
-                }
-            }
+    ) -> Result<(), FcsError> {
+        let cofactors = channels.iter()
+            .map(|channel| (channel.clone(), cofactor))
+            .collect::<HashMap<_, _>>();
+
+        self.arcsinh_transform_with(&cofactors)
+    }
+
+    /// Applies the Arcsinh transformation with a separate cofactor for every channel.
+    ///
+    /// # Arguments
+    ///
+    /// * `cofactors` - The names of the channels to transform, each mapped to its cofactor.
+    ///
+    /// # Returns
+    ///
+    /// A Result indicating success or an FcsError.
+    ///
+    /// # Errors
+    ///
+    /// This function returns the same errors as `arcsinh_transform`. All channels and
+    /// cofactors are checked, in channel name order, before any channel is transformed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use fcs_rs::FcsFile;
+    ///
+    /// let fcs_file = FcsFile::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+    /// let mut flow_sample = fcs_file.read().unwrap();
+    /// let cofactors = HashMap::from([
+    ///     ("BL1-A".to_string(), 150.0),
+    ///     ("RL1-A".to_string(), 500.0),
+    /// ]);
+    /// flow_sample.arcsinh_transform_with(&cofactors).unwrap();
+    /// ```
+    pub fn arcsinh_transform_with(&mut self, cofactors: &HashMap<String, f64>) -> Result<(), FcsError> {
+        let mut channels = cofactors.keys().collect::<Vec<_>>();
+        channels.sort();
+
+        let mut transforms = Vec::new();
+        for channel in channels {
+            self.check_transformable(channel)?;
+            transforms.push((channel, Transform::arcsinh(cofactors[channel])?));
+        }
+
+        for (channel, transform) in transforms {
+            self.apply_transform(transform, std::slice::from_ref(channel))?;
         }
 
         Ok(())
     }
-}
 
-/// Reads the data segment of the FCS file and returns a FlowSample struct.
-///
-/// # Arguments
-///
-/// * `reader` - A mutable reference to a BufReader wrapping the FCS file.
-/// * `metadata` - A reference to a HashMap containing metadata from the FCS file.
-///
-/// # Returns
-///
-/// A Result containing a FlowSample struct or an FcsError.
-///
-/// # Errors
-///
-/// This function will return an FcsError if:
-/// - The data mode is not 'L' (list mode).
-/// - Required metadata fields are missing or invalid.
-/// - The data segment cannot be read or parsed correctly.
-/// - Byte order determination fails.
-///
-/// # Examples
+    /// Applies a transform to the data of the specified channels and records it in `transforms`.
+    ///
+    /// Null values are kept as they are. A channel that already has a transform must be
+    /// inverted with `invert_transform` before another transform is applied to it. All channels
+    /// are checked before any channel is transformed.
+    ///
+    /// # Arguments
+    ///
+    /// * `transform` - The transform to apply.
+    /// * `channels` - The names of the columns to transform.
+    ///
+    /// # Returns
+    ///
+    /// A Result indicating success or an FcsError.
+    ///
+    /// # Errors
+    ///
+    /// Returns `FcsError::UnknownChannel` if a channel does not exist, `FcsError::InvalidData`
+    /// if it is not a column of floating point values, and `FcsError::InvalidTransform` if it
+    /// already has a transform or is listed more than once. No channel is transformed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fcs_rs::FcsFile;
+    /// use fcs_rs::data::Transform;
+    ///
+    /// let fcs_file = FcsFile::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+    /// let mut flow_sample = fcs_file.read().unwrap();
+    /// let logicle = Transform::logicle(262144.0, 0.5, 4.5, 0.0).unwrap();
+    /// flow_sample.apply_transform(logicle, &["BL1-A".to_string(), "RL1-A".to_string()]).unwrap();
+    /// assert_eq!(flow_sample.transforms["BL1-A"].to_string(), "logicle(T=262144, W=0.5, M=4.5, A=0)");
+    /// ```
+    pub fn apply_transform(&mut self, transform: Transform, channels: &[String]) -> Result<(), FcsError> {
+        for (i, channel) in channels.iter().enumerate() {
+            self.check_transformable(channel)?;
+            if channels[..i].contains(channel) {
+                return Err(FcsError::InvalidTransform(format!("Channel {} is listed more than once", channel)));
+            }
+        }
+
+        for channel in channels {
+            self.map_column(channel, |value| transform.forward(value))?;
+            self.transforms.insert(channel.clone(), transform);
+        }
+
+        Ok(())
+    }
+
+    /// Reverts the recorded transform of the specified channels and removes it from `transforms`.
+    ///
+    /// Channels without a recorded transform are left unchanged.
+    ///
+    /// # Arguments
+    ///
+    /// * `channels` - The names of the columns to revert.
+    ///
+    /// # Returns
+    ///
+    /// A Result indicating success or an FcsError.
+    ///
+    /// # Errors
+    ///
+    /// Returns `FcsError::InvalidData` if a transformed column is not a column of floating
+    /// point values.
+    pub fn invert_transform(&mut self, channels: &[String]) -> Result<(), FcsError> {
+        for channel in channels {
+            if let Some(transform) = self.transforms.get(channel).copied() {
+                self.map_column(channel, |value| transform.inverse(value))?;
+                self.transforms.remove(channel);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that a channel is a column of floating point values without a recorded transform.
+    fn check_transformable(&self, channel: &str) -> Result<(), FcsError> {
+        let series = self.data.column(channel)
+            .map_err(|_| FcsError::UnknownChannel(channel.to_string()))?;
+        series.f64()
+            .map_err(|err| FcsError::InvalidData(format!("Column {} is not numeric: {}", channel, err)))?;
+        if let Some(transform) = self.transforms.get(channel) {
+            return Err(FcsError::InvalidTransform(format!("Channel {} already has the transform {}", channel, transform)));
+        }
+
+        Ok(())
+    }
+
+    /// Replaces every non-null value of a column with `f(value)`.
+    fn map_column(&mut self, channel: &str, f: impl Fn(f64) -> f64) -> Result<(), FcsError> {
+        let series = self.data.column(channel)
+            .map_err(|_| FcsError::UnknownChannel(channel.to_string()))?;
+        let transformed = series.f64()
+            .map_err(|err| FcsError::InvalidData(format!("Column {} is not numeric: {}", channel, err)))?
+            .apply(|value| value.map(&f))
+            .into_series();
+        self.data.with_column(transformed)
+            .map_err(|err| FcsError::InvalidData(err.to_string()))?;
+
+        Ok(())
+    }
+}
+
+/// Reads the data segment of the FCS file and returns a FlowSample struct.
+///
+/// # Arguments
+///
+/// * `reader` - A mutable reference to a BufReader wrapping the FCS file or any other `Read + Seek` source.
+/// * `metadata` - A reference to a HashMap containing metadata from the FCS file.
+///
+/// # Returns
+///
+/// A Result containing a FlowSample struct or an FcsError.
+///
+/// # Errors
+///
+/// This function will return an FcsError if:
+/// - The data mode is not 'L' (list mode).
+/// - Required metadata fields are missing or invalid.
+/// - `$BEGINDATA` and `$ENDDATA` describe a reversed segment, or one that overlaps the header or
+///   extends past the end of the file (`FcsError::InvalidOffsets`).
+/// - The data segment cannot be read or parsed correctly.
+/// - Byte order determination fails.
+///
+/// # Examples
 ///
 /// ```
 /// use std::fs::File;
@@ -183,330 +915,1601 @@ impl FlowSample {
 /// let flow_sample = parse_data(&mut reader, &metadata).unwrap();
 /// println!("{:?}", flow_sample.data);
 /// ```
-pub fn parse_data(
-    reader: &mut BufReader<&File>, 
+pub fn parse_data<R: Read + Seek>(
+    reader: &mut BufReader<R>, 
     metadata: &HashMap<String, String>,
 ) -> Result<FlowSample, FcsError> {
-    let mode = metadata.get("$MODE")
-        .ok_or_else(|| FcsError::InvalidData("Missing $MODE in metadata".to_string()))?;
-    if mode != "L" {
-        return Err(FcsError::InvalidData("Data must be in list (L) mode".to_string()));
-    }
-
-    let data_type = metadata.get("$DATATYPE")
-        .ok_or_else(|| FcsError::InvalidData("Missing $DATATYPE in metadata".to_string()))?;
-    let n_params = metadata.get("$PAR")
-        .ok_or_else(|| FcsError::InvalidData("Missing $PAR in metadata".to_string()))?
-        .parse::<usize>()
-        .map_err(|_| FcsError::InvalidData("Invalid $PAR value".to_string()))?;
-    let n_events = metadata.get("$TOT")
-        .ok_or_else(|| FcsError::InvalidData("Missing $TOT in metadata".to_string()))?
-        .parse::<usize>()
-        .map_err(|_| FcsError::InvalidData("Invalid $TOT value".to_string()))?;
-    let data_start = metadata.get("$BEGINDATA")
-        .ok_or_else(|| FcsError::InvalidData("Missing $BEGINDATA in metadata".to_string()))?
-        .trim()
-        .parse::<u64>()
-        .map_err(|_| FcsError::InvalidData("Invalid $BEGINDATA value".to_string()))?;
-    let byte_order = metadata.get("$BYTEORD")
-        .ok_or_else(|| FcsError::InvalidData("Missing $BYTEORD in metadata".to_string()))?;
-    let capacity = n_params * n_events;
-    if capacity == 0 {
-        return Err(FcsError::InvalidData("Fcs file may be corrupted. No data found".to_string()));
-    }
-
-    reader.seek(SeekFrom::Start(data_start))?;
-    let mut parameters: HashMap<String, Vec<f64>> = HashMap::new();
-    let mut events: Vec<f64>;
-
-    for i in 1..=n_params {
-        events = if byte_order == "1,2,3,4" {
-            read_events::<LittleEndian>(reader, data_type, n_events, i, metadata)?
-        } else if byte_order == "4,3,2,1" {
-            read_events::<BigEndian>(reader, data_type, n_events, i, metadata)?
-        } else {
-            return Err(FcsError::InvalidData("Could not determine byte order.".to_string()));
-        };
-
-        let id = metadata.get(&format!("$P{}S", i))
-            .ok_or_else(|| FcsError::InvalidData(format!("Missing $P{}S in metadata", i)))?;
-        parameters.insert(id.to_owned(), events);
-    }
+    let data_start = keyword_offset(metadata, "$BEGINDATA")
+        .ok_or_else(|| FcsError::InvalidData("Missing or invalid $BEGINDATA in metadata".to_string()))?;
+    let data_end = keyword_offset(metadata, "$ENDDATA")
+        .ok_or_else(|| FcsError::InvalidData("Missing or invalid $ENDDATA in metadata".to_string()))?;
 
-    let column_titles = parameters.keys().cloned().collect::<Vec<_>>();
-    let data = parameters.values().cloned().collect::<Vec<_>>();
+    let data_offsets = data_start..=data_end;
+    let file_len = reader.seek(SeekFrom::End(0))?;
+    check_offsets(Segment::Data, &data_offsets, file_len)?;
 
-    let fcs_df = create_dataframe(&column_titles, &data)
-        .map_err(|_| FcsError::InvalidData("Failed to create DataFrame".to_string()))?;
-
-    let sample = FlowSample {
-        data: fcs_df,
-        parameters: metadata.to_owned()
-    };
-
-    Ok(sample)
+    parse_data_segment(reader, metadata, &data_offsets)
 }
 
-/// Reads the events data segment of the FCS file and returns a vector of f64 values.
+/// Reads the data segment at the given offsets and returns a FlowSample struct.
 ///
-/// This function reads the events data based on the specified data type (F, D, or I) and
-/// converts the raw data into a vector of f64 values. It handles different byte orders
-/// and bit depths as specified in the metadata.
+/// Unlike `parse_data`, the offsets do not have to come from `$BEGINDATA` and `$ENDDATA`, which
+/// FCS 2.0 files do not have. Use `data_offsets` to find them from the header and metadata.
+/// `$MODE` defaults to list mode when it is absent (it is optional in FCS 3.2), and when `$TOT`
+/// is absent (it is optional in FCS 2.0) the number of events is derived from the segment length.
+/// Values are converted to channel values with `Scale`; use `parse_data_segment_with_mode` to
+/// keep the raw values.
 ///
 /// # Arguments
 ///
-/// * `reader` - A mutable reference to a BufReader wrapping the FCS file.
-/// * `data_type` - A string slice indicating the data type ('F' for float, 'D' for double, 'I' for integer).
-/// * `n_events` - The number of events to read.
-/// * `param_idx` - The parameter index for the current data segment.
+/// * `reader` - A mutable reference to a BufReader wrapping the FCS file or any other `Read + Seek` source.
 /// * `metadata` - A reference to a HashMap containing metadata from the FCS file.
+/// * `data_offsets` - The byte offsets of the first and last byte of the data segment.
 ///
 /// # Returns
 ///
-/// A Result containing a vector of f64 values representing the events data, or an FcsError.
+/// A Result containing a FlowSample struct or an FcsError.
 ///
 /// # Errors
 ///
 /// This function will return an FcsError if:
-/// - There is an I/O error during reading.
-/// - The specified data type is not supported.
-/// - The bits per parameter for integer data types is not supported or cannot be parsed.
+/// - The data mode is not 'L' (list mode).
+/// - Required metadata fields are missing or invalid.
+/// - The data segment cannot be read or parsed correctly.
+/// - Byte order determination fails.
 ///
 /// # Examples
 ///
 /// ```
 /// use std::fs::File;
 /// use std::io::BufReader;
-/// use std::collections::HashMap;
-/// use byteorder::LittleEndian;
-/// use fcs_rs::text::read_metadata;
-/// use fcs_rs::data::read_events;
+/// use fcs_rs::data::{data_offsets, parse_data_segment};
+/// use fcs_rs::header::read_header;
+/// use fcs_rs::text::read_text;
 /// 
 /// let file = File::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
 /// let mut reader = BufReader::new(&file);
-/// let metadata: HashMap<String, String> = read_metadata(&mut reader).unwrap();
-/// let events = read_events::<LittleEndian>(&mut reader, "F", 1000, 1, &metadata).unwrap();
-/// println!("{:?}", events);
+/// let header = read_header(&mut reader).unwrap();
+/// let metadata = read_text(&mut reader, &header).unwrap();
+/// let flow_sample = parse_data_segment(&mut reader, &metadata, &data_offsets(&header, &metadata)).unwrap();
+/// println!("{:?}", flow_sample.data);
 /// ```
-pub fn read_events<B: byteorder::ByteOrder>(
-    reader: &mut BufReader<&File>, 
-    data_type: &str, 
-    n_events: usize, 
-    param_idx: usize, 
-    metadata: &HashMap<String, String>
-) -> Result<Vec<f64>, FcsError> {
-    let data = match data_type {
-        "F" => {
-            let mut float_buffer = vec![0; n_events * std::mem::size_of::<f32>()];
-            reader.read_exact(&mut float_buffer).map_err(FcsError::IoError)?;
-            let mut data = Vec::with_capacity(n_events);
-            for i in 0..n_events {
-                let float_value = B::read_f32(&float_buffer[i * 4..(i + 1) * 4]) as f64;
-                data.push(float_value);
-            }
-            data
-        },
-        "D" => {
-            let mut float_buffer = vec![0; n_events * std::mem::size_of::<f64>()];
-            reader.read_exact(&mut float_buffer).map_err(FcsError::IoError)?;
-            let mut data = Vec::with_capacity(n_events);
-            for i in 0..n_events {
-                let double_value = B::read_f64(&float_buffer[i * 8..(i + 1) * 8]);
-                data.push(double_value);
-            }
-            data
-        },
-        "I" => {
-            let bits_per_param = metadata.get(&format!("$P{}B", param_idx))
-                .ok_or_else(|| FcsError::InvalidText(format!("Missing $P{}B in metadata", param_idx)))?
-                .parse::<usize>()
-                .map_err(|_| FcsError::InvalidData(format!("Invalid bits per param value for $P{}B", param_idx)))?;
-
-            match bits_per_param / 8 {
-                2 => {
-                    let mut int_buffer = vec![0; n_events * std::mem::size_of::<u16>()];
-                    reader.read_exact(&mut int_buffer).map_err(FcsError::IoError)?;
-                    let mut data = Vec::with_capacity(n_events);
-                    for i in 0..n_events {
-                        let int_value = B::read_u16(&int_buffer[i * 2..(i + 1) * 2]) as f64;
-                        data.push(int_value);
-                    }
-                    data
-                },
-                4 => {
-                    let mut int_buffer = vec![0; n_events * std::mem::size_of::<u32>()];
-                    reader.read_exact(&mut int_buffer).map_err(FcsError::IoError)?;
-                    let mut data = Vec::with_capacity(n_events);
-                    for i in 0..n_events {
-                        let int_value = B::read_u32(&int_buffer[i * 4..(i + 1) * 4]) as f64;
-                        data.push(int_value);
-                    }
-                    data
-                },
-                8 => {
-                    let mut int_buffer = vec![0; n_events * std::mem::size_of::<u64>()];
-                    reader.read_exact(&mut int_buffer).map_err(FcsError::IoError)?;
-                    let mut data = Vec::with_capacity(n_events);
-                    for i in 0..n_events {
-                        let int_value = B::read_u64(&int_buffer[i * 8..(i + 1) * 8]) as f64;
-                        data.push(int_value);
-                    }
-                    data
-                },
-                16 => {
-                    let mut int_buffer = vec![0; n_events * std::mem::size_of::<u128>()];
-                    reader.read_exact(&mut int_buffer).map_err(FcsError::IoError)?;
-                    let mut data = Vec::with_capacity(n_events);
-                    for i in 0..n_events {
-                        let int_value = B::read_u128(&int_buffer[i * 16..(i + 1) * 16]) as f64;
-                        data.push(int_value);
-                    }
-                    data
-                },
-                _ => return Err(FcsError::InvalidData("Bits for param type not supported".to_string())),
-            }
-        },
-        _ => return Err(FcsError::InvalidData("FCS data type not supported. Must be F, D, or I".to_string())),
-    };
+pub fn parse_data_segment<R: Read + Seek>(
+    reader: &mut BufReader<R>, 
+    metadata: &HashMap<String, String>,
+    data_offsets: &RangeInclusive<usize>,
+) -> Result<FlowSample, FcsError> {
+    parse_data_segment_with_mode(reader, metadata, data_offsets, ValueMode::Scaled)
+}
 
-    Ok(data)
+/// Reads the data segment at the given offsets and returns a FlowSample struct, either with
+/// channel values or with the raw values stored in the file.
+///
+/// # Arguments
+///
+/// * `reader` - A mutable reference to a BufReader wrapping the FCS file or any other `Read + Seek` source.
+/// * `metadata` - A reference to a HashMap containing metadata from the FCS file.
+/// * `data_offsets` - The byte offsets of the first and last byte of the data segment.
+/// * `mode` - Whether values are converted to channel values or kept raw.
+///
+/// # Returns
+///
+/// A Result containing a FlowSample struct or an FcsError.
+///
+/// # Errors
+///
+/// This function returns the same errors as `parse_data_segment`, and an
+/// `FcsError::InvalidKeyword` if the scale of a parameter cannot be read in `ValueMode::Scaled`.
+pub fn parse_data_segment_with_mode<R: Read + Seek>(
+    reader: &mut BufReader<R>, 
+    metadata: &HashMap<String, String>,
+    data_offsets: &RangeInclusive<usize>,
+    mode: ValueMode,
+) -> Result<FlowSample, FcsError> {
+    parse_data_segment_with_options(reader, metadata, data_offsets, &ReadOptions::new().mode(mode))
 }
 
-/// Creates a DataFrame from column titles and corresponding data vectors.
+/// Reads the channels and events selected by `options` from the data segment at the given
+/// offsets and returns a FlowSample struct holding them.
 ///
-/// This function takes a vector of column titles and a vector of data vectors,
-/// converts each data vector to a Series with the corresponding column title,
-/// and combines them into a DataFrame.
+/// Only the bytes from the first to the last selected event are read, and values of channels
+/// that are not selected are not decoded. The `channels` of the sample are the selected
+/// channels, while `metadata` still holds every keyword of the file.
 ///
 /// # Arguments
 ///
-/// * `column_titles` - A reference to a vector of strings representing the column titles.
-/// * `data` - A reference to a vector of vectors containing the data for each column.
+/// * `reader` - A mutable reference to a BufReader wrapping the FCS file or any other `Read + Seek` source.
+/// * `metadata` - A reference to a HashMap containing metadata from the FCS file.
+/// * `data_offsets` - The byte offsets of the first and last byte of the data segment.
+/// * `options` - The channels and events to read, and how values are converted.
 ///
 /// # Returns
 ///
-/// A Result containing a DataFrame or a PolarsError if there is a mismatch in the number of columns and titles.
+/// A Result containing a FlowSample struct or an FcsError.
 ///
 /// # Errors
 ///
-/// This function will return a PolarsError if:
-/// - The number of columns does not match the number of column titles.
+/// This function returns the same errors as `parse_data_segment_with_mode`, an
+/// `FcsError::UnknownChannel` if a selected channel does not exist, and an
+/// `FcsError::InvalidData` if a channel is selected twice.
 ///
 /// # Examples
 ///
 /// ```
-/// use fcs_rs::data::create_dataframe;
-/// use polars::prelude::*;
+/// use std::fs::File;
+/// use std::io::BufReader;
+/// use fcs_rs::data::{data_offsets, parse_data_segment_with_options, ReadOptions};
+/// use fcs_rs::header::read_header;
+/// use fcs_rs::text::read_text;
 /// 
-/// let column_titles = vec!["APC-A".to_string(), "FSC-W".to_string()];
-/// let data = vec![vec![1.0, 2.0, 3.0], vec![4.0, 5.0, 6.0]];
-/// let df = create_dataframe(&column_titles, &data).unwrap();
-/// println!("{:?}", df);
+/// let file = File::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+/// let mut reader = BufReader::new(&file);
+/// let header = read_header(&mut reader).unwrap();
+/// let metadata = read_text(&mut reader, &header).unwrap();
+/// let options = ReadOptions::new().channels([1, 3]).limit(10);
+/// let flow_sample = parse_data_segment_with_options(&mut reader, &metadata, &data_offsets(&header, &metadata), &options).unwrap();
+/// assert_eq!(flow_sample.get_dataframe_columns(), vec!["Time", "SSC-A"]);
+/// assert_eq!(flow_sample.data.height(), 10);
 /// ```
-pub fn create_dataframe(column_titles: &[String], data: &[Vec<f64>]) -> Result<DataFrame, PolarsError> {
-    // Ensure the number of columns matches the number of column titles
-    if column_titles.len() != data.len() {
-        return Err(PolarsError::ShapeMismatch(
-            "Number of columns does not match number of column titles".into(),
-        ));
-    }
-
-    let mut series_vec = Vec::with_capacity(column_titles.len());
+pub fn parse_data_segment_with_options<R: Read + Seek>(
+    reader: &mut BufReader<R>, 
+    metadata: &HashMap<String, String>,
+    data_offsets: &RangeInclusive<usize>,
+    options: &ReadOptions,
+) -> Result<FlowSample, FcsError> {
+    let layout = EventLayout::from_metadata(metadata, data_offsets)?;
+    let selection = Selection::new(options, &layout, metadata)?;
 
-    for (i, column_data) in data.iter().enumerate() {
-        let series = Series::new(&column_titles[i], column_data);
-        series_vec.push(series);
-    }
+    let skipped = selection.skipped_bytes(&layout.formats) as u64;
+    reader.seek(SeekFrom::Start(*data_offsets.start() as u64 + skipped))?;
+    // Stop at the end of the segment, so delimited ASCII data does not run into the CRC
+    let segment_len = match *data_offsets.end() {
+        0 => u64::MAX,
+        end => (end.saturating_sub(*data_offsets.start()) as u64 + 1).saturating_sub(skipped),
+    };
+    let mut segment = BufReader::new(reader.take(segment_len));
+    let columns = read_selected(&mut segment, &layout.formats, &layout.byte_order, &selection)?;
 
-    let df = DataFrame::new(series_vec)?;
+    build_sample(metadata, &layout, &selection, columns, options.mode)
+}
 
-    Ok(df)
+/// Decodes a data segment held in memory and returns a FlowSample struct, either with channel
+/// values or with the raw values stored in the file.
+///
+/// This is the in-memory counterpart of `parse_data_segment_with_mode`, used by
+/// `FcsFile::open_mapped` to decode a memory-mapped file. Values are decoded straight from
+/// `bytes` into one buffer per column, which is then moved into the DataFrame without copying.
+///
+/// # Arguments
+///
+/// * `bytes` - The data segment, from its first to its last byte.
+/// * `metadata` - A reference to a HashMap containing metadata from the FCS file.
+/// * `mode` - Whether values are converted to channel values or kept raw.
+///
+/// # Returns
+///
+/// A Result containing a FlowSample struct or an FcsError.
+///
+/// # Errors
+///
+/// This function returns the same errors as `parse_data_segment_with_mode`. A segment shorter
+/// than `$TOT` events is reported as an `FcsError::IoError` of kind `UnexpectedEof`.
+///
+/// # Examples
+///
+/// ```
+/// use fcs_rs::data::{parse_data_bytes, ValueMode};
+/// use fcs_rs::text::read_metadata;
+/// use std::io::{BufReader, Cursor};
+///
+/// let bytes = std::fs::read("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+/// let metadata = read_metadata(&mut BufReader::new(Cursor::new(&bytes))).unwrap();
+/// let begin = metadata["$BEGINDATA"].trim().parse::<usize>().unwrap();
+/// let end = metadata["$ENDDATA"].trim().parse::<usize>().unwrap();
+///
+/// let flow_sample = parse_data_bytes(&bytes[begin..=end], &metadata, ValueMode::Scaled).unwrap();
+/// assert_eq!(flow_sample.data.shape(), (8821, 10));
+/// ```
+pub fn parse_data_bytes(
+    bytes: &[u8], 
+    metadata: &HashMap<String, String>,
+    mode: ValueMode,
+) -> Result<FlowSample, FcsError> {
+    parse_data_bytes_with_options(bytes, metadata, &ReadOptions::new().mode(mode))
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs::File;
-    use std::io::BufReader;
-    use crate::{read_metadata, FcsFile};
+/// Decodes the channels and events selected by `options` from a data segment held in memory,
+/// like `parse_data_segment_with_options`.
+pub(crate) fn parse_data_bytes_with_options(
+    bytes: &[u8], 
+    metadata: &HashMap<String, String>,
+    options: &ReadOptions,
+) -> Result<FlowSample, FcsError> {
+    let data_offsets = match bytes.len() {
+        0 => 0..=0,
+        len => 0..=len - 1,
+    };
+    let layout = EventLayout::from_metadata(metadata, &data_offsets)?;
+    let selection = Selection::new(options, &layout, metadata)?;
 
-    #[test]
-    fn test_flow_sample_display() {
-        let mut parameters = HashMap::new();
-        parameters.insert("$CYT".to_string(), "Test Cytometer".to_string());
-        parameters.insert("$BTIM".to_string(), "10:00".to_string());
-        parameters.insert("$ETIM".to_string(), "10:30".to_string());
-        parameters.insert("$DATE".to_string(), "2022-01-01".to_string());
-        parameters.insert("$FIL".to_string(), "test.fcs".to_string());
-        parameters.insert("$VOL".to_string(), "500".to_string());
-        parameters.insert("$PAR".to_string(), "2".to_string());
-        parameters.insert("$P1N".to_string(), "FSC".to_string());
-        parameters.insert("$P1S".to_string(), "Forward Scatter".to_string());
-        parameters.insert("$P2N".to_string(), "SSC".to_string());
-        parameters.insert("$P2S".to_string(), "Side Scatter".to_string());
+    let skipped = selection.skipped_bytes(&layout.formats).min(bytes.len());
+    let columns = decode_selected(&bytes[skipped..], &layout.formats, &layout.byte_order, &selection)?;
 
-        let data = DataFrame::new(vec![
-            Series::new("FSC", &[1.0, 2.0, 3.0]),
-            Series::new("SSC", &[4.0, 5.0, 6.0]),
-        ]).unwrap();
+    build_sample(metadata, &layout, &selection, columns, options.mode)
+}
 
-        let flow_sample = FlowSample {
-            data,
-            parameters,
+/// The layout of the events in a data segment, read from `$MODE`, `$DATATYPE`, `$BYTEORD`,
+/// `$PAR` and `$TOT`.
+#[derive(Debug)]
+struct EventLayout {
+    formats: Vec<(DataType, usize)>,
+    byte_order: ByteOrder,
+    n_params: usize,
+    n_events: usize,
+}
+
+impl EventLayout {
+    /// Reads the layout of the data segment at `data_offsets`, which is used to derive the number
+    /// of events when `$TOT` is absent.
+    fn from_metadata(
+        metadata: &HashMap<String, String>, 
+        data_offsets: &RangeInclusive<usize>,
+    ) -> Result<Self, FcsError> {
+        if let Some(mode) = metadata.get("$MODE") {
+            if mode.trim() != "L" {
+                return Err(FcsError::InvalidData("Data must be in list (L) mode".to_string()));
+            }
+        }
+
+        let data_type = metadata.get("$DATATYPE")
+            .ok_or_else(|| FcsError::InvalidData("Missing $DATATYPE in metadata".to_string()))?;
+        let n_params = metadata.get("$PAR")
+            .ok_or_else(|| FcsError::InvalidData("Missing $PAR in metadata".to_string()))?
+            .trim()
+            .parse::<usize>()
+            .map_err(|_| FcsError::InvalidData("Invalid $PAR value".to_string()))?;
+        let byte_order = metadata.get("$BYTEORD")
+            .ok_or_else(|| FcsError::InvalidData("Missing $BYTEORD in metadata".to_string()))?;
+        let formats = param_formats(data_type, n_params, metadata)?;
+        let event_bits = event_bits(&formats);
+        let n_events = match metadata.get("$TOT") {
+            Some(total) => total.trim()
+                .parse::<usize>()
+                .map_err(|_| FcsError::InvalidData("Invalid $TOT value".to_string()))?,
+            None => {
+                let segment_len = data_offsets.end().saturating_sub(*data_offsets.start()) + 1;
+                if event_bits == 0 || *data_offsets.end() == 0 { 0 } else { segment_len * 8 / event_bits }
+            },
         };
+        // Offsets within the segment are counted in bits, so they must fit as well
+        let capacity = n_params.checked_mul(n_events)
+            .filter(|_| n_events.checked_mul(event_bits).is_some())
+            .ok_or_else(|| FcsError::InvalidData(format!(
+                "{} events of {} parameters are too many to read", n_events, n_params
+            )))?;
+        if capacity == 0 {
+            return Err(FcsError::InvalidData("Fcs file may be corrupted. No data found".to_string()));
+        }
 
-        let expected_display = "
-FlowSample:
-    Machine: Test Cytometer
-    Begin Time: 10:00
-    End Time: 10:30
-    Date: 2022-01-01
-    File: test.fcs
-    Volume run: 500
-    Labels: 
-        FSC (Forward Scatter)
-        SSC (Side Scatter)
-";
+        Ok(Self {
+            formats,
+            byte_order: ByteOrder::from_keyword(byte_order)?,
+            n_params,
+            n_events,
+        })
+    }
+}
 
-        assert_eq!(format!("{}", flow_sample), expected_display);
+/// The parameters and events decoded from a data segment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Selection {
+    /// The indices of the decoded parameters, starting at 0, in column order.
+    params: Vec<usize>,
+    /// The index of the first decoded event.
+    start: usize,
+    /// The number of decoded events.
+    n_rows: usize,
+    /// The distance between decoded events.
+    step: usize,
+}
+
+impl Selection {
+    /// Selects every parameter of the first `n_events` events.
+    fn all(n_params: usize, n_events: usize) -> Self {
+        Self { params: (0..n_params).collect(), start: 0, n_rows: n_events, step: 1 }
     }
 
-    #[test]
-    fn test_get_dataframe_columns() {
-        let mut parameters = HashMap::new();
-        parameters.insert("$PAR".to_string(), "2".to_string());
-        parameters.insert("$P1N".to_string(), "FSC".to_string());
-        parameters.insert("$P2N".to_string(), "SSC".to_string());
+    /// Resolves the channels and events of `options` against the layout of a data segment.
+    fn new(options: &ReadOptions, layout: &EventLayout, metadata: &HashMap<String, String>) -> Result<Self, FcsError> {
+        if options.step == 0 {
+            return Err(FcsError::InvalidData("Event step must be non-zero".to_string()));
+        }
 
-        let data = DataFrame::new(vec![
-            Series::new("FSC", &[1.0, 2.0, 3.0]),
-            Series::new("SSC", &[4.0, 5.0, 6.0]),
-        ]).unwrap();
+        let params = match &options.channels {
+            None => (0..layout.n_params).collect(),
+            Some(selectors) => {
+                let channels = read_channels(metadata, layout.n_params)?;
+                let mut params = Vec::with_capacity(selectors.len());
+                for selector in selectors {
+                    let param = match selector {
+                        ChannelSelector::Name(name) => channels.iter().position(|channel| &channel.name == name),
+                        ChannelSelector::Index(index) => index.checked_sub(1).filter(|&param| param < layout.n_params),
+                    }.ok_or_else(|| FcsError::UnknownChannel(selector.to_string()))?;
 
-        let flow_sample = FlowSample {
-            data,
-            parameters,
+                    if params.contains(&param) {
+                        return Err(FcsError::InvalidData(format!("Channel {} is selected more than once", selector)));
+                    }
+                    params.push(param);
+                }
+                params
+            },
         };
 
-        let column_names = flow_sample.get_dataframe_columns();
-        assert_eq!(column_names, vec!["FSC", "SSC"]);
+        let end = options.end.unwrap_or(layout.n_events).min(layout.n_events);
+        let start = options.start.min(end);
+        let n_rows = (end - start).div_ceil(options.step).min(options.limit.unwrap_or(usize::MAX));
+
+        Ok(Self { params, start, n_rows, step: options.step })
     }
 
-    #[test]
-    fn test_arcsinh_transform() {
-        let fcs_file = FcsFile::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+    /// Returns the number of events from the first to the last decoded event.
+    fn span(&self) -> usize {
+        match self.n_rows {
+            0 => 0,
+            n_rows => (n_rows - 1) * self.step + 1,
+        }
+    }
 
-        let mut flow_sample = fcs_file.read().unwrap();
+    /// Returns the number of bytes before the byte holding the first decoded event. Delimited
+    /// ASCII events are read from the start of the segment.
+    fn skipped_bytes(&self, formats: &[(DataType, usize)]) -> usize {
+        self.start * event_bits(formats) / 8
+    }
+
+    /// Returns the number of bytes from the byte holding the first decoded event to the byte
+    /// holding the last one.
+    fn span_bytes(&self, formats: &[(DataType, usize)]) -> usize {
+        let event_bits = event_bits(formats);
+        (self.start * event_bits % 8 + self.span() * event_bits).div_ceil(8)
+    }
+}
+
+/// Returns the number of bits of an event.
+fn event_bits(formats: &[(DataType, usize)]) -> usize {
+    formats.iter().map(|(_, bits)| bits).sum()
+}
+
+/// Decodes the events of a data segment a chunk at a time, for `FcsFile::chunks_with`.
+///
+/// Only one chunk of events is held in memory at a time. The decoder remembers where the next
+/// chunk starts, so the reader may be used for other reads between chunks.
+#[derive(Debug)]
+pub(crate) struct ChunkDecoder {
+    metadata: HashMap<String, String>,
+    layout: EventLayout,
+    selection: Selection,
+    column_titles: Vec<String>,
+    mode: ValueMode,
+    chunk_size: usize,
+    /// The number of events decoded so far.
+    rows_done: usize,
+    /// The number of events left to decode, which drops to 0 after an error.
+    rows_left: usize,
+    /// The length of the segment, or `u64::MAX` if its end is unknown.
+    segment_len: u64,
+    /// For delimited ASCII data, the offset from the start of the segment of the first byte
+    /// that has not been read yet.
+    offset: u64,
+}
+
+impl ChunkDecoder {
+    /// Creates a decoder of the channels and events of `options` in the data segment at
+    /// `data_offsets`, which yields `chunk_size` events at a time.
+    pub(crate) fn new(
+        metadata: &HashMap<String, String>,
+        data_offsets: &RangeInclusive<usize>,
+        options: &ReadOptions,
+        chunk_size: usize,
+    ) -> Result<Self, FcsError> {
+        if chunk_size == 0 {
+            return Err(FcsError::InvalidData("Chunk size must be non-zero".to_string()));
+        }
+
+        let layout = EventLayout::from_metadata(metadata, data_offsets)?;
+        let selection = Selection::new(options, &layout, metadata)?;
+        let channels = read_channels(metadata, layout.n_params)?;
+        let column_titles = selection.params.iter().map(|&param| channels[param].name.clone()).collect();
+        let segment_len = match *data_offsets.end() {
+            0 => u64::MAX,
+            end => end.saturating_sub(*data_offsets.start()) as u64 + 1,
+        };
+
+        Ok(Self {
+            metadata: metadata.clone(),
+            layout,
+            rows_left: selection.n_rows,
+            selection,
+            column_titles,
+            mode: options.mode,
+            chunk_size,
+            rows_done: 0,
+            segment_len,
+            offset: 0,
+        })
+    }
+
+    /// Returns the number of events that have not been decoded yet.
+    pub(crate) fn events_left(&self) -> usize {
+        self.rows_left
+    }
+
+    /// Decodes the next chunk of events from `reader`, in which the data segment starts at
+    /// byte `segment_start`, into a DataFrame.
+    ///
+    /// Returns `Ok(None)` once every event has been decoded. After an error no further chunks
+    /// are decoded.
+    pub(crate) fn decode_next<R: Read + Seek>(&mut self, reader: &mut R, segment_start: u64) -> Result<Option<DataFrame>, FcsError> {
+        if self.rows_left == 0 {
+            return Ok(None);
+        }
+
+        let n_rows = self.chunk_size.min(self.rows_left);
+        let columns = self.read_chunk(reader, segment_start, n_rows);
+        self.rows_left = match columns {
+            Ok(_) => self.rows_left - n_rows,
+            Err(_) => 0,
+        };
+        self.rows_done += n_rows;
+        let mut columns = columns?;
+
+        if self.mode == ValueMode::Scaled {
+            scale_selected(&mut columns, &self.layout.formats, &self.selection.params, &self.metadata)?;
+        }
+        columns_into_dataframe(&self.column_titles, columns)
+            .map(Some)
+            .map_err(|_| FcsError::InvalidData("Failed to create DataFrame".to_string()))
+    }
+
+    /// Reads the `n_rows` events that follow the events decoded so far.
+    fn read_chunk<R: Read + Seek>(&mut self, reader: &mut R, segment_start: u64, n_rows: usize) -> Result<Vec<Vec<f64>>, FcsError> {
+        let formats = &self.layout.formats;
+        let step = self.selection.step;
+
+        if !is_delimited(formats)? {
+            // Fixed width events are found from their index, so chunks of packed values may
+            // start inside a byte
+            let chunk = Selection {
+                params: self.selection.params.clone(),
+                start: self.selection.start + self.rows_done * step,
+                n_rows,
+                step,
+            };
+            let skipped = chunk.skipped_bytes(formats) as u64;
+            reader.seek(SeekFrom::Start(segment_start + skipped))?;
+            let mut segment = BufReader::new(reader.take(self.segment_len.saturating_sub(skipped)));
+            return read_fixed_width(&mut segment, formats, &self.layout.byte_order, &chunk, READ_BLOCK_BYTES);
+        }
+
+        // Delimited ASCII events can only be found by reading on from the previous chunk,
+        // skipping the events between the last decoded event and the next one
+        let chunk = Selection {
+            params: self.selection.params.clone(),
+            start: if self.rows_done == 0 { self.selection.start } else { step - 1 },
+            n_rows,
+            step,
+        };
+        let limit = self.segment_len.saturating_sub(self.offset);
+        reader.seek(SeekFrom::Start(segment_start + self.offset))?;
+        let mut segment = BufReader::new(reader.take(limit));
+        let columns = read_delimited_ascii(&mut segment, self.layout.n_params, &chunk)?;
+
+        // Values are read through the buffer, so count what is left unread
+        let unread = segment.get_ref().limit() + segment.buffer().len() as u64;
+        self.offset += limit - unread;
+        Ok(columns)
+    }
+}
+
+/// Scales the decoded columns as requested by `mode` and builds the FlowSample holding them.
+/// `columns` holds the parameters of `selection`.
+fn build_sample(
+    metadata: &HashMap<String, String>,
+    layout: &EventLayout,
+    selection: &Selection,
+    mut columns: Vec<Vec<f64>>,
+    mode: ValueMode,
+) -> Result<FlowSample, FcsError> {
+    if mode == ValueMode::Scaled {
+        scale_selected(&mut columns, &layout.formats, &selection.params, metadata)?;
+    }
+
+    let channels = read_channels(metadata, layout.n_params)?;
+    let channels = selection.params.iter().map(|&param| channels[param].clone()).collect::<Vec<_>>();
+    let column_titles = channels.iter().map(|channel| channel.name.clone()).collect::<Vec<_>>();
+
+    let fcs_df = columns_into_dataframe(&column_titles, columns)
+        .map_err(|_| FcsError::InvalidData("Failed to create DataFrame".to_string()))?;
+
+    let transforms = read_transforms(metadata, &channels)?;
+    let sample = FlowSample {
+        data: fcs_df,
+        metadata: Metadata::from_keywords(metadata.to_owned()),
+        channels,
+        transforms,
+        compensation: None,
+        analysis: HashMap::new(),
+    };
+
+    Ok(sample)
+}
+
+/// Reads the transforms that `write_fcs` recorded for the channels, keyed by channel name.
+///
+/// # Errors
+///
+/// Returns `FcsError::InvalidKeyword` if a recorded transform cannot be parsed.
+fn read_transforms(metadata: &HashMap<String, String>, channels: &[Channel]) -> Result<HashMap<String, Transform>, FcsError> {
+    let mut transforms = HashMap::new();
+    for channel in channels {
+        let keyword = transform::transform_keyword(channel.index);
+        if let Some(value) = metadata.get(&keyword) {
+            let transform = value.parse::<Transform>()
+                .map_err(|_| FcsError::InvalidKeyword { keyword, value: value.clone() })?;
+            transforms.insert(channel.name.clone(), transform);
+        }
+    }
+
+    Ok(transforms)
+}
+
+/// Returns the byte offsets of the data segment.
+///
+/// The `$BEGINDATA` and `$ENDDATA` keywords are used when they are present and non-zero.
+/// Otherwise the offsets come from the header, which is the only place FCS 2.0 files store them.
+///
+/// # Arguments
+///
+/// * `header` - The header of the FCS file.
+/// * `metadata` - A reference to a HashMap containing metadata from the FCS file.
+///
+/// # Returns
+///
+/// The offsets of the first and last byte of the data segment.
+pub fn data_offsets(header: &Header, metadata: &HashMap<String, String>) -> RangeInclusive<usize> {
+    match (keyword_offset(metadata, "$BEGINDATA"), keyword_offset(metadata, "$ENDDATA")) {
+        (Some(start), Some(end)) if end > 0 => start..=end,
+        _ => header.data_offsets.clone(),
+    }
+}
+
+/// Parses a byte offset keyword such as `$BEGINDATA`, which may be padded with spaces.
+pub(crate) fn keyword_offset(metadata: &HashMap<String, String>, keyword: &str) -> Option<usize> {
+    metadata.get(keyword)?.trim().parse::<usize>().ok()
+}
+
+/// Builds the channel list from the `$PnN` and `$PnS` keywords in parameter index order.
+///
+/// Returns an FcsError if a `$PnN` keyword is missing or two parameters share a name, since
+/// names are used as DataFrame column names.
+fn read_channels(metadata: &HashMap<String, String>, n_params: usize) -> Result<Vec<Channel>, FcsError> {
+    let mut channels: Vec<Channel> = Vec::with_capacity(n_params);
+
+    for index in 1..=n_params {
+        let name = metadata.get(&format!("$P{}N", index))
+            .ok_or_else(|| FcsError::InvalidData(format!("Missing $P{}N in metadata", index)))?;
+        if channels.iter().any(|channel| &channel.name == name) {
+            return Err(FcsError::InvalidData(format!("Duplicate parameter name {} in $P{}N", name, index)));
+        }
+
+        let label = metadata.get(&format!("$P{}S", index))
+            .filter(|label| !label.trim().is_empty())
+            .cloned();
+
+        channels.push(Channel {
+            index,
+            name: name.to_owned(),
+            label,
+        });
+    }
+
+    Ok(channels)
+}
+
+/// Reads list-mode events from the data segment and returns one column of f64 values per parameter.
+///
+/// List mode stores events row by row: the values of parameters 1 to n for the first event,
+/// followed by the values of parameters 1 to n for the second event, and so on. This function
+/// walks the data segment event by event and scatters each value into the column of its
+/// parameter. It handles different byte orders and the bit depths specified in the metadata.
+/// Parameters may have different widths. When every `$PnB` is a multiple of 8 values are
+/// byte-aligned; otherwise the integer values are unpacked from a continuous bit stream, as in
+/// older files using widths such as 10 or 12 bits.
+///
+/// # Arguments
+///
+/// * `reader` - A mutable reference to a BufReader positioned at the first event to read.
+/// * `data_type` - A string slice indicating the data type ('F' for float, 'D' for double, 'I' for integer,
+///   'A' for ASCII).
+/// * `byte_order` - The byte order of the stored values, from `$BYTEORD`.
+/// * `n_events` - The number of events to read.
+/// * `n_params` - The number of parameters stored in each event.
+/// * `metadata` - A reference to a HashMap containing metadata from the FCS file.
+///
+/// # Returns
+///
+/// A Result containing one vector of f64 values per parameter, in parameter order, or an FcsError.
+///
+/// # Errors
+///
+/// This function will return an FcsError if:
+/// - There is an I/O error during reading.
+/// - The specified data type is not supported.
+/// - The bits per parameter for integer data types is not between 1 and 128 or cannot be parsed.
+/// - Packed data with a `$PnB` that is not a multiple of 8 holds non-integer parameters, or
+///   uses a byte order other than little or big endian.
+/// - A value is wider or narrower than a `ByteOrder::Permutation`.
+/// - An ASCII value is not a number, or only some parameters of delimited ASCII data have
+///   `$PnB` set to `*`.
+///
+/// # Examples
+///
+/// ```
+/// use std::fs::File;
+/// use std::io::{BufReader, Seek, SeekFrom};
+/// use std::collections::HashMap;
+/// use fcs_rs::text::read_metadata;
+/// use fcs_rs::data::{read_events, ByteOrder};
+/// 
+/// let file = File::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+/// let mut reader = BufReader::new(&file);
+/// let metadata: HashMap<String, String> = read_metadata(&mut reader).unwrap();
+/// let data_start = metadata["$BEGINDATA"].trim().parse::<u64>().unwrap();
+/// reader.seek(SeekFrom::Start(data_start)).unwrap();
+/// let byte_order = ByteOrder::from_keyword(&metadata["$BYTEORD"]).unwrap();
+/// let columns = read_events(&mut reader, "F", &byte_order, 1000, 10, &metadata).unwrap();
+/// println!("{:?}", columns);
+/// ```
+pub fn read_events(
+    reader: &mut BufReader<impl Read>, 
+    data_type: &str, 
+    byte_order: &ByteOrder, 
+    n_events: usize, 
+    n_params: usize, 
+    metadata: &HashMap<String, String>
+) -> Result<Vec<Vec<f64>>, FcsError> {
+    let formats = param_formats(data_type, n_params, metadata)?;
+    read_selected(reader, &formats, byte_order, &Selection::all(n_params, n_events))
+}
+
+/// Decodes `n_events` events held in memory into one column of values per parameter.
+///
+/// This decodes the same layouts as `read_events`, without copying `bytes` first, and
+/// allocates a single buffer per column. `bytes` may extend past the last event.
+///
+/// # Arguments
+///
+/// * `bytes` - The events, starting with the first event to decode.
+/// * `data_type` - A string slice indicating the data type ('F' for float, 'D' for double, 'I' for integer,
+///   'A' for ASCII).
+/// * `byte_order` - The byte order of the stored values, from `$BYTEORD`.
+/// * `n_events` - The number of events to decode.
+/// * `n_params` - The number of parameters stored in each event.
+/// * `metadata` - A reference to a HashMap containing metadata from the FCS file.
+///
+/// # Returns
+///
+/// A Result containing one vector of f64 values per parameter, in parameter order, or an FcsError.
+///
+/// # Errors
+///
+/// This function returns the same errors as `read_events`, and an `FcsError::IoError` of kind
+/// `UnexpectedEof` if `bytes` holds fewer than `n_events` events.
+///
+/// # Examples
+///
+/// ```
+/// use std::collections::HashMap;
+/// use fcs_rs::data::{decode_events, ByteOrder};
+///
+/// let metadata = HashMap::from([
+///     ("$P1B".to_string(), "16".to_string()),
+///     ("$P2B".to_string(), "16".to_string()),
+/// ]);
+/// let bytes = [1, 0, 2, 0, 3, 0, 4, 0];
+/// let columns = decode_events(&bytes, "I", &ByteOrder::LittleEndian, 2, 2, &metadata).unwrap();
+/// assert_eq!(columns, vec![vec![1.0, 3.0], vec![2.0, 4.0]]);
+/// ```
+pub fn decode_events(
+    bytes: &[u8], 
+    data_type: &str, 
+    byte_order: &ByteOrder, 
+    n_events: usize, 
+    n_params: usize, 
+    metadata: &HashMap<String, String>
+) -> Result<Vec<Vec<f64>>, FcsError> {
+    let formats = param_formats(data_type, n_params, metadata)?;
+    decode_selected(bytes, &formats, byte_order, &Selection::all(n_params, n_events))
+}
+
+/// Decodes byte aligned events like `decode_events`, but on the calling thread even when the
+/// `parallel` feature is enabled.
+///
+/// It is only public so that `benches/decode.rs` can measure the parallel decoder against the
+/// serial one, and is not part of the API.
+#[doc(hidden)]
+pub fn decode_events_serial(
+    bytes: &[u8], 
+    data_type: &str, 
+    byte_order: &ByteOrder, 
+    n_events: usize, 
+    n_params: usize, 
+    metadata: &HashMap<String, String>
+) -> Result<Vec<Vec<f64>>, FcsError> {
+    let formats = param_formats(data_type, n_params, metadata)?;
+    if formats.iter().any(|(_, bits)| bits % 8 != 0) {
+        return Err(FcsError::InvalidData("Only byte aligned events are decoded serially".to_string()));
+    }
+
+    let selection = Selection::all(n_params, n_events);
+    let len = selection.span_bytes(&formats);
+    if bytes.len() < len {
+        return Err(FcsError::IoError(std::io::ErrorKind::UnexpectedEof.into()));
+    }
+    let mut columns = vec![vec![0.0; n_events]; n_params];
+    decode_aligned_serial(&bytes[..len], &formats, byte_order, &selection, &mut columns)?;
+    Ok(columns)
+}
+
+/// Reads the events of `selection` from `reader`, which starts at the byte holding the first
+/// selected event, or at the start of the segment for delimited ASCII data.
+fn read_selected(
+    reader: &mut BufReader<impl Read>, 
+    formats: &[(DataType, usize)], 
+    byte_order: &ByteOrder, 
+    selection: &Selection,
+) -> Result<Vec<Vec<f64>>, FcsError> {
+    if is_delimited(formats)? {
+        return read_delimited_ascii(reader, formats.len(), selection);
+    }
+
+    read_fixed_width(reader, formats, byte_order, selection, READ_BLOCK_BYTES)
+}
+
+/// Reads the events of `selection` from `reader` like `read_selected`, for values with the fixed
+/// widths in `formats`, decoding up to `block_bytes` bytes of events at a time.
+///
+/// Memory is only taken for the bytes actually read, not for the span that `$TOT` claims.
+fn read_fixed_width(
+    reader: &mut BufReader<impl Read>, 
+    formats: &[(DataType, usize)], 
+    byte_order: &ByteOrder, 
+    selection: &Selection,
+    block_bytes: usize,
+) -> Result<Vec<Vec<f64>>, FcsError> {
+    let event_bits = event_bits(formats);
+    if event_bits == 0 {
+        return Ok(vec![Vec::new(); selection.params.len()]);
+    }
+
+    let block_rows = (block_bytes * 8 / (event_bits * selection.step)).max(1);
+    let first_byte = selection.skipped_bytes(formats);
+    let mut columns = vec![Vec::new(); selection.params.len()];
+    // The bytes read but not yet decoded, and the offset of the first one from `first_byte`
+    let mut buffer = Vec::new();
+    let mut buffer_start = 0;
+    let mut row = 0;
+    while row < selection.n_rows {
+        let block = Selection {
+            params: selection.params.clone(),
+            start: selection.start + row * selection.step,
+            n_rows: block_rows.min(selection.n_rows - row),
+            step: selection.step,
+        };
+        let block_start = block.skipped_bytes(formats) - first_byte;
+        let block_end = block_start + block.span_bytes(formats);
+
+        // Packed events may share their first byte with the last event of the previous block
+        let buffer_end = buffer_start + buffer.len();
+        if block_start < buffer_end {
+            buffer.drain(..block_start - buffer_start);
+        } else {
+            buffer.clear();
+            let gap = (block_start - buffer_end) as u64;
+            if std::io::copy(&mut reader.by_ref().take(gap), &mut std::io::sink())? < gap {
+                return Err(FcsError::IoError(std::io::ErrorKind::UnexpectedEof.into()));
+            }
+        }
+        buffer_start = block_start;
+
+        let filled = buffer.len();
+        buffer.resize(block_end - block_start, 0);
+        reader.read_exact(&mut buffer[filled..]).map_err(FcsError::IoError)?;
+
+        let decoded = decode_fixed_width(&buffer, formats, byte_order, &block)?;
+        for (column, values) in columns.iter_mut().zip(decoded) {
+            column.extend(values);
+        }
+        row += block.n_rows;
+    }
+
+    Ok(columns)
+}
+
+/// Decodes the events of `selection` from `bytes`, which start like the reader of
+/// `read_selected`.
+fn decode_selected(
+    bytes: &[u8], 
+    formats: &[(DataType, usize)], 
+    byte_order: &ByteOrder, 
+    selection: &Selection,
+) -> Result<Vec<Vec<f64>>, FcsError> {
+    if is_delimited(formats)? {
+        return read_delimited_ascii(&mut BufReader::new(bytes), formats.len(), selection);
+    }
+
+    let len = selection.span_bytes(formats);
+    if bytes.len() < len {
+        return Err(FcsError::IoError(std::io::ErrorKind::UnexpectedEof.into()));
+    }
+    decode_fixed_width(&bytes[..len], formats, byte_order, selection)
+}
+
+/// Returns whether the parameters hold delimited ASCII values, which must then be the case for
+/// every parameter.
+fn is_delimited(formats: &[(DataType, usize)]) -> Result<bool, FcsError> {
+    if !formats.iter().any(|&(param_type, bits)| param_type == DataType::Ascii && bits == 0) {
+        return Ok(false);
+    }
+    if formats.iter().any(|&(param_type, bits)| param_type != DataType::Ascii || bits != 0) {
+        return Err(FcsError::InvalidData(
+            "Delimited ASCII data requires $PnB to be * for every parameter".to_string()
+        ));
+    }
+    Ok(true)
+}
+
+/// Decodes the events of `selection` from `buffer`, whose values have the fixed widths in
+/// `formats`, into one column per selected parameter.
+fn decode_fixed_width(
+    buffer: &[u8], 
+    formats: &[(DataType, usize)], 
+    byte_order: &ByteOrder, 
+    selection: &Selection,
+) -> Result<Vec<Vec<f64>>, FcsError> {
+    let event_bits = event_bits(formats);
+    if event_bits == 0 {
+        return Ok(vec![Vec::new(); selection.params.len()]);
+    }
+
+    let mut columns;
+    if formats.iter().all(|(_, bits)| bits % 8 == 0) {
+        columns = vec![vec![0.0; selection.n_rows]; selection.params.len()];
+        decode_aligned(buffer, formats, byte_order, selection, &mut columns)?;
+    } else {
+        columns = vec![Vec::with_capacity(selection.n_rows); selection.params.len()];
+        if formats.iter().any(|&(param_type, _)| param_type != DataType::Integer) {
+            return Err(FcsError::InvalidData(
+                "Packed data with $PnB not a multiple of 8 must only hold integer parameters".to_string()
+            ));
+        }
+
+        let little_endian = match byte_order {
+            ByteOrder::LittleEndian => true,
+            ByteOrder::BigEndian => false,
+            ByteOrder::Permutation(_) => return Err(FcsError::InvalidData(format!(
+                "Packed data with $PnB not a multiple of 8 cannot use $BYTEORD {}", byte_order.keyword()
+            ))),
+        };
+
+        // The column of each parameter, if it is selected
+        let mut targets = vec![None; formats.len()];
+        for (column, &param) in selection.params.iter().enumerate() {
+            targets[param] = Some(column);
+        }
+
+        let mut unpacker = BitUnpacker::new(buffer, little_endian);
+        unpacker.skip(selection.start * event_bits % 8);
+        for event in 0..selection.span() {
+            if !event.is_multiple_of(selection.step) {
+                unpacker.skip(event_bits);
+                continue;
+            }
+            for (&target, &(_, bits)) in targets.iter().zip(formats) {
+                match target {
+                    Some(column) => columns[column].push(unpacker.next_value(bits) as f64),
+                    None => unpacker.skip(bits),
+                }
+            }
+        }
+    }
+
+    Ok(columns)
+}
+
+/// Number of bytes of events that `read_selected` reads and decodes at a time.
+const READ_BLOCK_BYTES: usize = 8 * 1024 * 1024;
+
+/// Number of decoded events in each task when byte aligned events are decoded in parallel.
+#[cfg(feature = "parallel")]
+const PARALLEL_CHUNK_EVENTS: usize = 16 * 1024;
+
+/// Decodes the byte aligned events of `selection` from `buffer` into `columns`, which hold one
+/// value per decoded event, on the calling thread.
+fn decode_aligned_serial(
+    buffer: &[u8], 
+    formats: &[(DataType, usize)], 
+    byte_order: &ByteOrder, 
+    selection: &Selection,
+    columns: &mut [Vec<f64>],
+) -> Result<(), FcsError> {
+    let mut parts = columns.iter_mut().map(|column| column.as_mut_slice()).collect::<Vec<_>>();
+    decode_aligned_into(buffer, formats, byte_order, selection, &mut parts)
+}
+
+#[cfg(not(feature = "parallel"))]
+use decode_aligned_serial as decode_aligned;
+
+/// Decodes the byte aligned events of `selection` from `buffer` into `columns`, which hold one
+/// value per decoded event.
+///
+/// The decoded events are split into ranges of `PARALLEL_CHUNK_EVENTS` events that are decoded
+/// concurrently, each into its own part of the columns. Errors are reported for the first
+/// failing range, so the result is the same as decoding serially.
+#[cfg(feature = "parallel")]
+fn decode_aligned(
+    buffer: &[u8], 
+    formats: &[(DataType, usize)], 
+    byte_order: &ByteOrder, 
+    selection: &Selection,
+    columns: &mut [Vec<f64>],
+) -> Result<(), FcsError> {
+    use rayon::prelude::*;
+
+    let mut ranges = (0..selection.n_rows.div_ceil(PARALLEL_CHUNK_EVENTS))
+        .map(|_| Vec::with_capacity(columns.len()))
+        .collect::<Vec<_>>();
+    for column in columns.iter_mut() {
+        for (range, part) in ranges.iter_mut().zip(column.chunks_mut(PARALLEL_CHUNK_EVENTS)) {
+            range.push(part);
+        }
+    }
+
+    let range_size = event_bits(formats) / 8 * selection.step * PARALLEL_CHUNK_EVENTS;
+    buffer.par_chunks(range_size)
+        .zip(ranges)
+        .map(|(events, mut parts)| decode_aligned_into(events, formats, byte_order, selection, &mut parts))
+        .collect::<Vec<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// Decodes every `selection.step`th byte aligned event of `buffer`, starting with the first,
+/// into `parts`, one slice per selected parameter holding one value per decoded event.
+fn decode_aligned_into(
+    buffer: &[u8], 
+    formats: &[(DataType, usize)], 
+    byte_order: &ByteOrder, 
+    selection: &Selection,
+    parts: &mut [&mut [f64]],
+) -> Result<(), FcsError> {
+    let mut offsets = Vec::with_capacity(formats.len());
+    let mut offset = 0;
+    for &(_, bits) in formats {
+        offsets.push(offset);
+        offset += bits / 8;
+    }
+    let fields = selection.params.iter()
+        .map(|&param| (offsets[param], formats[param].0, formats[param].1 / 8))
+        .collect::<Vec<_>>();
+
+    for (row, event) in buffer.chunks_exact(offset).step_by(selection.step).enumerate() {
+        for (part, &(offset, param_type, size)) in parts.iter_mut().zip(&fields) {
+            part[row] = decode_value(param_type, byte_order, &event[offset..offset + size])?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads unsigned integers of any width from 1 to 128 bits out of a packed bit stream.
+///
+/// Values follow each other without padding, also across events. With a little endian
+/// `$BYTEORD` the stream starts at the least significant bit of the first byte and the first
+/// bit read is the least significant bit of the value. With a big endian `$BYTEORD` the stream
+/// starts at the most significant bit of the first byte and the first bit read is the most
+/// significant bit of the value.
+struct BitUnpacker<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    little_endian: bool,
+}
+
+impl<'a> BitUnpacker<'a> {
+    fn new(bytes: &'a [u8], little_endian: bool) -> Self {
+        Self { bytes, position: 0, little_endian }
+    }
+
+    /// Skips the next `bits` bits.
+    fn skip(&mut self, bits: usize) {
+        self.position += bits;
+    }
+
+    /// Reads the next `bits` bits as an unsigned integer. The stream must hold enough bits.
+    fn next_value(&mut self, bits: usize) -> u128 {
+        let mut value = 0u128;
+        for i in 0..bits {
+            let byte = self.bytes[self.position / 8];
+            let shift = self.position % 8;
+            if self.little_endian {
+                value |= (((byte >> shift) & 1) as u128) << i;
+            } else {
+                value = (value << 1) | ((byte >> (7 - shift)) & 1) as u128;
+            }
+            self.position += 1;
+        }
+        value
+    }
+}
+
+/// Reads the events of `selection` from delimited ASCII data with `n_params` values per event
+/// into one column per selected parameter.
+///
+/// Values are separated by spaces, tabs, commas, carriage returns or line feeds, and consecutive
+/// delimiters count as one. Values that are not selected are skipped without being parsed.
+fn read_delimited_ascii(
+    reader: &mut BufReader<impl Read>, 
+    n_params: usize, 
+    selection: &Selection,
+) -> Result<Vec<Vec<f64>>, FcsError> {
+    // `$TOT` is not checked against the length of the segment, so reserve no more than a block
+    // of values at a time, each taking at least a digit and a delimiter
+    let capacity = selection.n_rows.min(READ_BLOCK_BYTES / 2);
+    let mut columns = vec![Vec::with_capacity(capacity); selection.params.len()];
+    let mut targets = vec![None; n_params];
+    for (column, &param) in selection.params.iter().enumerate() {
+        targets[param] = Some(column);
+    }
+
+    let too_many = || FcsError::InvalidData(format!(
+        "Too many delimited ASCII values to read for {} parameters", n_params
+    ));
+    let first = selection.start.checked_mul(n_params).ok_or_else(too_many)?;
+    let n_values = selection.start.checked_add(selection.span())
+        .and_then(|n_events| n_events.checked_mul(n_params))
+        .ok_or_else(too_many)?;
+    let mut token = Vec::new();
+    let mut n_read = 0;
+    let mut bytes = reader.bytes();
+
+    while n_read < n_values {
+        match bytes.next().transpose()? {
+            Some(b' ' | b'\t' | b',' | b'\r' | b'\n') | None if !token.is_empty() => {
+                let event = n_read.saturating_sub(first) / n_params;
+                if n_read >= first && event.is_multiple_of(selection.step) {
+                    if let Some(column) = targets[n_read % n_params] {
+                        columns[column].push(parse_ascii(&token)?);
+                    }
+                }
+                token.clear();
+                n_read += 1;
+            },
+            Some(b' ' | b'\t' | b',' | b'\r' | b'\n') => {},
+            Some(byte) => token.push(byte),
+            None => return Err(FcsError::InvalidData(format!(
+                "Delimited ASCII data ended after {} of {} values", n_read, n_values
+            ))),
+        }
+    }
+
+    Ok(columns)
+}
+
+/// Parses an ASCII encoded value, which may be padded with spaces.
+fn parse_ascii(bytes: &[u8]) -> Result<f64, FcsError> {
+    std::str::from_utf8(bytes).ok()
+        .and_then(|value| value.trim().parse::<f64>().ok())
+        .ok_or_else(|| FcsError::InvalidData(format!("Invalid ASCII value {:?}", String::from_utf8_lossy(bytes))))
+}
+
+/// Converts the raw values of every column to channel values in place.
+///
+/// Each column is converted with the `Scale` of its parameter.
+///
+/// # Arguments
+///
+/// * `columns` - One column of raw values per parameter, in parameter order, as returned by `read_events`.
+/// * `data_type` - A string slice indicating the default data type from `$DATATYPE`.
+/// * `metadata` - A reference to a HashMap containing metadata from the FCS file.
+///
+/// # Returns
+///
+/// A Result indicating success or an FcsError.
+///
+/// # Errors
+///
+/// Returns an FcsError if the data type or the scale of a parameter cannot be read.
+pub fn scale_columns(
+    columns: &mut [Vec<f64>], 
+    data_type: &str, 
+    metadata: &HashMap<String, String>
+) -> Result<(), FcsError> {
+    let formats = param_formats(data_type, columns.len(), metadata)?;
+    let params = (0..columns.len()).collect::<Vec<_>>();
+    scale_selected(columns, &formats, &params, metadata)
+}
+
+/// Converts the raw values of the columns of the parameters `params`, counted from 0, to
+/// channel values in place.
+fn scale_selected(
+    columns: &mut [Vec<f64>], 
+    formats: &[(DataType, usize)], 
+    params: &[usize], 
+    metadata: &HashMap<String, String>
+) -> Result<(), FcsError> {
+    for (column, &param) in columns.iter_mut().zip(params) {
+        let scale = Scale::from_metadata(metadata, param + 1, formats[param].0)?;
+        for value in column.iter_mut() {
+            *value = scale.apply(*value);
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the data type and the number of bits of a single value of every parameter.
+///
+/// `$PnDATATYPE` (FCS 3.2) overrides `data_type` for a single parameter. Floats and doubles
+/// always use 32 and 64 bits. Integers use the width given by `$PnB`, which may be any number of
+/// bits from 1 to 128, and ASCII values use 8 bits per character of `$PnB`. Delimited ASCII
+/// values (`$PnB` is `*`) have a width of 0.
+fn param_formats(
+    data_type: &str, 
+    n_params: usize, 
+    metadata: &HashMap<String, String>
+) -> Result<Vec<(DataType, usize)>, FcsError> {
+    let default_type = DataType::from_keyword(data_type)?;
+    let mut formats = Vec::with_capacity(n_params);
+
+    for param_idx in 1..=n_params {
+        let param_type = match metadata.get(&format!("$P{}DATATYPE", param_idx)) {
+            Some(value) => DataType::from_keyword(value)?,
+            None => default_type,
+        };
+
+        let width = match param_type {
+            DataType::Float => 32,
+            DataType::Double => 64,
+            DataType::Integer => {
+                let bits_per_param = metadata.get(&format!("$P{}B", param_idx))
+                    .ok_or_else(|| FcsError::InvalidText(format!("Missing $P{}B in metadata", param_idx)))?
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|_| FcsError::InvalidData(format!("Invalid bits per param value for $P{}B", param_idx)))?;
+
+                match bits_per_param {
+                    1..=128 => bits_per_param,
+                    _ => return Err(FcsError::InvalidData(format!(
+                        "Bits for param type not supported: $P{}B is {}", param_idx, bits_per_param
+                    ))),
+                }
+            },
+            DataType::Ascii => {
+                let keyword = format!("$P{}B", param_idx);
+                let value = metadata.get(&keyword)
+                    .ok_or_else(|| FcsError::InvalidText(format!("Missing {} in metadata", keyword)))?;
+                match value.trim() {
+                    "*" => 0,
+                    chars => match chars.parse::<usize>() {
+                        Ok(width) if width > 0 => width * 8,
+                        _ => return Err(FcsError::InvalidKeyword { keyword, value: value.clone() }),
+                    },
+                }
+            },
+        };
+
+        formats.push((param_type, width));
+    }
+
+    Ok(formats)
+}
+
+/// Decodes a single byte-aligned value from its raw bytes. The width must come from
+/// `param_formats`.
+fn decode_value(data_type: DataType, byte_order: &ByteOrder, bytes: &[u8]) -> Result<f64, FcsError> {
+    if data_type == DataType::Ascii {
+        return parse_ascii(bytes);
+    }
+
+    let mut value = [0; 16];
+    let value = &mut value[..bytes.len()];
+    value.copy_from_slice(bytes);
+    byte_order.to_little_endian(value)?;
+
+    Ok(match (data_type, value.len()) {
+        (DataType::Float, _) => LittleEndian::read_f32(value) as f64,
+        (DataType::Double, _) => LittleEndian::read_f64(value),
+        (_, width) if width <= 8 => LittleEndian::read_uint(value, width) as f64,
+        (_, width) => LittleEndian::read_uint128(value, width) as f64,
+    })
+}
+
+/// Creates a DataFrame from column titles and corresponding data vectors.
+///
+/// This function takes a vector of column titles and a vector of data vectors,
+/// converts each data vector to a Series with the corresponding column title,
+/// and combines them into a DataFrame.
+///
+/// # Arguments
+///
+/// * `column_titles` - A reference to a vector of strings representing the column titles.
+/// * `data` - A reference to a vector of vectors containing the data for each column.
+///
+/// # Returns
+///
+/// A Result containing a DataFrame or a PolarsError if there is a mismatch in the number of columns and titles.
+///
+/// # Errors
+///
+/// This function will return a PolarsError if:
+/// - The number of columns does not match the number of column titles.
+///
+/// # Examples
+///
+/// ```
+/// use fcs_rs::data::create_dataframe;
+/// use polars::prelude::*;
+/// 
+/// let column_titles = vec!["APC-A".to_string(), "FSC-W".to_string()];
+/// let data = vec![vec![1.0, 2.0, 3.0], vec![4.0, 5.0, 6.0]];
+/// let df = create_dataframe(&column_titles, &data).unwrap();
+/// println!("{:?}", df);
+/// ```
+pub fn create_dataframe(column_titles: &[String], data: &[Vec<f64>]) -> Result<DataFrame, PolarsError> {
+    // Ensure the number of columns matches the number of column titles
+    if column_titles.len() != data.len() {
+        return Err(PolarsError::ShapeMismatch(
+            "Number of columns does not match number of column titles".into(),
+        ));
+    }
+
+    let mut series_vec = Vec::with_capacity(column_titles.len());
+
+    for (i, column_data) in data.iter().enumerate() {
+        let series = Series::new(&column_titles[i], column_data);
+        series_vec.push(series);
+    }
+
+    let df = DataFrame::new(series_vec)?;
+
+    Ok(df)
+}
+
+/// Creates a DataFrame from column titles and columns, moving each column into its Series
+/// without copying it.
+fn columns_into_dataframe(column_titles: &[String], columns: Vec<Vec<f64>>) -> Result<DataFrame, PolarsError> {
+    if column_titles.len() != columns.len() {
+        return Err(PolarsError::ShapeMismatch(
+            "Number of columns does not match number of column titles".into(),
+        ));
+    }
+
+    let series_vec = column_titles.iter()
+        .zip(columns)
+        .map(|(title, column)| Float64Chunked::from_vec(title, column).into_series())
+        .collect::<Vec<_>>();
+
+    DataFrame::new(series_vec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::BufReader;
+    use crate::{read_metadata, FcsFile};
+    use crate::test_utils::build_fcs;
+
+    /// Builds a minimal FCS 3.1 file in memory holding `events`, one inner vector per event.
+    fn synthetic_fcs(
+        data_type: &str,
+        byte_order: &str,
+        bits: &[usize],
+        events: &[Vec<f64>],
+    ) -> Vec<u8> {
+        let little_endian = byte_order == "1,2,3,4";
+        let mut data = Vec::new();
+        for event in events {
+            for (&value, &bits) in event.iter().zip(bits) {
+                let bytes = match (data_type, bits) {
+                    ("F", _) if little_endian => (value as f32).to_le_bytes().to_vec(),
+                    ("F", _) => (value as f32).to_be_bytes().to_vec(),
+                    ("D", _) if little_endian => value.to_le_bytes().to_vec(),
+                    ("D", _) => value.to_be_bytes().to_vec(),
+                    (_, 16) if little_endian => (value as u16).to_le_bytes().to_vec(),
+                    (_, 16) => (value as u16).to_be_bytes().to_vec(),
+                    (_, _) if little_endian => (value as u32).to_le_bytes().to_vec(),
+                    (_, _) => (value as u32).to_be_bytes().to_vec(),
+                };
+                data.extend(bytes);
+            }
+        }
+
+        let (n_params, n_events) = (bits.len().to_string(), events.len().to_string());
+        let mut keywords = vec![
+            ("$BEGINANALYSIS".to_string(), "0".to_string()),
+            ("$ENDANALYSIS".to_string(), "0".to_string()),
+            ("$BEGINSTEXT".to_string(), "0".to_string()),
+            ("$ENDSTEXT".to_string(), "0".to_string()),
+            ("$NEXTDATA".to_string(), "0".to_string()),
+            ("$MODE".to_string(), "L".to_string()),
+            ("$DATATYPE".to_string(), data_type.to_string()),
+            ("$BYTEORD".to_string(), byte_order.to_string()),
+            ("$PAR".to_string(), n_params),
+            ("$TOT".to_string(), n_events),
+        ];
+        for (i, b) in bits.iter().enumerate() {
+            let n = i + 1;
+            keywords.push((format!("$P{n}N"), format!("P{n}")));
+            keywords.push((format!("$P{n}S"), format!("Label{n}")));
+            keywords.push((format!("$P{n}B"), b.to_string()));
+            keywords.push((format!("$P{n}E"), "0,0".to_string()));
+            let range = if data_type == "I" { (1u64 << b).to_string() } else { "1024".to_string() };
+            keywords.push((format!("$P{n}R"), range));
+        }
+
+        let keywords = keywords.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect::<Vec<_>>();
+        build_fcs("FCS3.1", &keywords, &data, true)
+    }
+
+    fn column(sample: &FlowSample, name: &str) -> Vec<f64> {
+        sample.data.column(name).unwrap().f64().unwrap().into_no_null_iter().collect()
+    }
+
+    #[test]
+    fn test_flow_sample_display() {
+        let mut parameters = HashMap::new();
+        parameters.insert("$CYT".to_string(), "Test Cytometer".to_string());
+        parameters.insert("$BTIM".to_string(), "10:00:00".to_string());
+        parameters.insert("$ETIM".to_string(), "10:30:15.50".to_string());
+        parameters.insert("$DATE".to_string(), "01-Jan-2022".to_string());
+        parameters.insert("$FIL".to_string(), "test.fcs".to_string());
+        parameters.insert("$VOL".to_string(), "500".to_string());
+        parameters.insert("$PAR".to_string(), "2".to_string());
+        parameters.insert("$P1N".to_string(), "FSC".to_string());
+        parameters.insert("$P1S".to_string(), "Forward Scatter".to_string());
+        parameters.insert("$P2N".to_string(), "SSC".to_string());
+        parameters.insert("$P2S".to_string(), "Side Scatter".to_string());
+
+        let data = DataFrame::new(vec![
+            Series::new("FSC", &[1.0, 2.0, 3.0]),
+            Series::new("SSC", &[4.0, 5.0, 6.0]),
+        ]).unwrap();
+
+        let flow_sample = FlowSample {
+            data,
+            metadata: Metadata::from_keywords(parameters),
+            channels: vec![
+                Channel { index: 1, name: "FSC".to_string(), label: Some("Forward Scatter".to_string()) },
+                Channel { index: 2, name: "SSC".to_string(), label: Some("Side Scatter".to_string()) },
+            ],
+            transforms: HashMap::new(),
+            compensation: None,
+            analysis: HashMap::new(),
+        };
+
+        let expected_display = "
+FlowSample:
+    Machine: Test Cytometer
+    Begin Time: 10:00:00
+    End Time: 10:30:15.500
+    Date: 01-JAN-2022
+    File: test.fcs
+    Volume run: 500
+    Labels: 
+        FSC (Forward Scatter)
+        SSC (Side Scatter)
+";
+
+        assert_eq!(format!("{}", flow_sample), expected_display);
+    }
+
+    #[test]
+    fn test_get_dataframe_columns() {
+        let mut parameters = HashMap::new();
+        parameters.insert("$PAR".to_string(), "2".to_string());
+        parameters.insert("$P1N".to_string(), "FSC".to_string());
+        parameters.insert("$P2N".to_string(), "SSC".to_string());
+
+        let data = DataFrame::new(vec![
+            Series::new("FSC", &[1.0, 2.0, 3.0]),
+            Series::new("SSC", &[4.0, 5.0, 6.0]),
+        ]).unwrap();
+
+        let flow_sample = FlowSample {
+            data,
+            metadata: Metadata::from_keywords(parameters),
+            channels: vec![
+                Channel { index: 1, name: "FSC".to_string(), label: None },
+                Channel { index: 2, name: "SSC".to_string(), label: None },
+            ],
+            transforms: HashMap::new(),
+            compensation: None,
+            analysis: HashMap::new(),
+        };
+
+        let column_names = flow_sample.get_dataframe_columns();
+        assert_eq!(column_names, vec!["FSC", "SSC"]);
+    }
+
+    #[test]
+    fn test_arcsinh_transform() {
+        let fcs_file = FcsFile::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+
+        let mut flow_sample = fcs_file.read().unwrap();
+
+        let column_names = flow_sample.get_dataframe_columns();
+        
+        assert!(flow_sample.arcsinh_transform(5.0, &column_names).is_ok(), "{}", false);   
+
+        let fsc: Vec<f64> = flow_sample.data.column("FSC-H").unwrap().f64().unwrap().into_no_null_iter().collect();
+        let ssc: Vec<f64> = flow_sample.data.column("RL1-A").unwrap().f64().unwrap().into_no_null_iter().collect();
+
+        assert_eq!(fsc[..=2], vec![12.007167945372798, 12.031588297464872, 11.780650718297412]);
+        assert_eq!(ssc[..=2], vec![7.89759396426525, 6.953876126480831, 6.562446088644499]);
+    }
+
+    /// Builds a sample holding the given columns, which may contain nulls.
+    fn sample_with_columns(columns: &[(&str, Vec<Option<f64>>)]) -> FlowSample {
+        FlowSample {
+            data: DataFrame::new(columns.iter().map(|(name, values)| Series::new(name, values)).collect()).unwrap(),
+            metadata: Metadata::from_keywords(HashMap::new()),
+            channels: columns.iter().enumerate()
+                .map(|(i, (name, _))| Channel { index: i + 1, name: name.to_string(), label: None })
+                .collect(),
+            transforms: HashMap::new(),
+            compensation: None,
+            analysis: HashMap::new(),
+        }
+    }
+
+    fn nullable_column(sample: &FlowSample, name: &str) -> Vec<Option<f64>> {
+        sample.data.column(name).unwrap().f64().unwrap().into_iter().collect()
+    }
+
+    #[test]
+    fn test_arcsinh_transform_real_line() {
+        let values = vec![-1e300, -1e6, -5.0, -1e-300, 0.0, 1e-300, 5.0, 1e6, 1e300];
+        let mut sample = sample_with_columns(&[("FL1", values.iter().copied().map(Some).collect())]);
+
+        sample.arcsinh_transform(5.0, &["FL1".to_string()]).unwrap();
+        let transformed = column(&sample, "FL1");
+
+        assert!(transformed.iter().all(|value| value.is_finite()), "{:?}", transformed);
+        assert!(transformed.windows(2).all(|pair| pair[0] <= pair[1]), "{:?}", transformed);
+        for (value, x) in transformed.iter().zip(&values) {
+            // Odd symmetry around zero
+            assert_eq!(*value, -Transform::arcsinh(5.0).unwrap().forward(-x));
+        }
+        assert_eq!(transformed[4], 0.0);
+        assert!((transformed[6] - 1f64.asinh()).abs() < 1e-15);
+        assert!((transformed[2] + 1f64.asinh()).abs() < 1e-15);
+        // Large values are on a log scale: asinh(x) = ln(2x) + O(1 / x^2)
+        assert!((transformed[8] - (2e300f64 / 5.0).ln()).abs() < 1e-12);
+        assert!((transformed[7] - (2e6f64 / 5.0).ln()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_arcsinh_transform_nulls() {
+        let mut sample = sample_with_columns(&[("FL1", vec![Some(-5.0), None, Some(5.0)])]);
+
+        sample.arcsinh_transform(5.0, &["FL1".to_string()]).unwrap();
+        assert_eq!(nullable_column(&sample, "FL1"), vec![Some(-(1f64.asinh())), None, Some(1f64.asinh())]);
+    }
+
+    #[test]
+    fn test_arcsinh_transform_with_cofactors() {
+        let mut sample = sample_with_columns(&[
+            ("FL1", vec![Some(150.0), Some(-300.0)]),
+            ("FL2", vec![Some(150.0), Some(-300.0)]),
+            ("FL3", vec![Some(150.0), Some(-300.0)]),
+        ]);
+        let cofactors = HashMap::from([("FL1".to_string(), 150.0), ("FL2".to_string(), 5.0)]);
+
+        sample.arcsinh_transform_with(&cofactors).unwrap();
+        assert_eq!(column(&sample, "FL1"), vec![1f64.asinh(), (-2f64).asinh()]);
+        assert_eq!(column(&sample, "FL2"), vec![30f64.asinh(), (-60f64).asinh()]);
+        assert_eq!(column(&sample, "FL3"), vec![150.0, -300.0]);
+        assert_eq!(sample.transforms["FL1"], Transform::Arcsinh { cofactor: 150.0 });
+        assert_eq!(sample.transforms["FL2"], Transform::Arcsinh { cofactor: 5.0 });
+        assert!(!sample.transforms.contains_key("FL3"));
+    }
+
+    #[test]
+    fn test_arcsinh_transform_errors() {
+        let mut sample = sample_with_columns(&[("FL1", vec![Some(1.0)]), ("FL2", vec![Some(2.0)])]);
 
-        let column_names = flow_sample.get_dataframe_columns();
-        
-        assert!(flow_sample.arcsinh_transform(5.0, &column_names).is_ok(), "{}", false);   
+        let result = sample.arcsinh_transform(5.0, &["FL1".to_string(), "CD4".to_string()]);
+        assert!(matches!(result, Err(FcsError::UnknownChannel(ref name)) if name == "CD4"));
+        assert_eq!(column(&sample, "FL1"), vec![1.0]);
+        assert!(sample.transforms.is_empty());
 
-        let fsc: Vec<f64> = flow_sample.data.column("FSC-H").unwrap().f64().unwrap().into_no_null_iter().collect();
-        let ssc: Vec<f64> = flow_sample.data.column("APC-A").unwrap().f64().unwrap().into_no_null_iter().collect();
+        let result = sample.arcsinh_transform(0.0, &["FL1".to_string()]);
+        assert!(matches!(result, Err(FcsError::InvalidTransform(_))));
+
+        let cofactors = HashMap::from([("FL1".to_string(), 5.0), ("FL2".to_string(), -1.0)]);
+        assert!(matches!(sample.arcsinh_transform_with(&cofactors), Err(FcsError::InvalidTransform(_))));
+        assert_eq!(column(&sample, "FL1"), vec![1.0]);
+
+        // The first invalid channel in name order is reported
+        for _ in 0..8 {
+            let cofactors = HashMap::from([("ZZ".to_string(), 5.0), ("AA".to_string(), 5.0), ("FL1".to_string(), 5.0)]);
+            let result = sample.arcsinh_transform_with(&cofactors);
+            assert!(matches!(result, Err(FcsError::UnknownChannel(ref name)) if name == "AA"), "{:?}", result);
+        }
+
+        // A column that is not f64 fails before any channel is transformed
+        sample.data.with_column(Series::new("FL0", &[7i32])).unwrap();
+        let result = sample.arcsinh_transform(5.0, &["FL1".to_string(), "FL0".to_string()]);
+        assert!(matches!(result, Err(FcsError::InvalidData(_))), "{:?}", result);
+        assert_eq!(column(&sample, "FL1"), vec![1.0]);
+        assert!(sample.transforms.is_empty());
+    }
+
+    #[test]
+    fn test_apply_and_invert_transform() {
+        let fcs_file = FcsFile::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+        let original = fcs_file.read().unwrap();
+        let mut flow_sample = fcs_file.read().unwrap();
+        let channels = vec!["BL1-A".to_string(), "RL1-A".to_string()];
+        let logicle = Transform::logicle(262144.0, 0.5, 4.5, 0.0).unwrap();
+
+        flow_sample.apply_transform(logicle, &channels).unwrap();
+        assert_eq!(flow_sample.transforms.len(), 2);
+        assert_eq!(flow_sample.transforms["RL1-A"], logicle);
+        let expected: Vec<f64> = column(&original, "BL1-A").iter().map(|&x| logicle.forward(x)).collect();
+        assert_eq!(column(&flow_sample, "BL1-A"), expected);
+        assert_eq!(column(&flow_sample, "FSC-A"), column(&original, "FSC-A"));
+
+        flow_sample.invert_transform(&channels).unwrap();
+        assert!(flow_sample.transforms.is_empty());
+        for (inverted, raw) in column(&flow_sample, "RL1-A").iter().zip(column(&original, "RL1-A")) {
+            assert!((inverted - raw).abs() <= 1e-9 * raw.abs().max(1.0));
+        }
+    }
+
+    #[test]
+    fn test_apply_transform_unknown_channel() {
+        let fcs_file = FcsFile::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+        let mut flow_sample = fcs_file.read().unwrap();
+        let result = flow_sample.apply_transform(Transform::arcsinh(150.0).unwrap(), &["CD4".to_string()]);
+
+        assert!(matches!(result, Err(FcsError::UnknownChannel(ref name)) if name == "CD4"));
+        assert!(flow_sample.transforms.is_empty());
+    }
 
-        assert_eq!(fsc[..=2], vec![22.692050373389968, 21.3191345559628, 5.869126409425455]);
-        assert_eq!(ssc[..=2], vec![7.3780713106122935, 23.476587479533844, 23.01206351580942]);
+    #[test]
+    fn test_apply_transform_twice() {
+        let fcs_file = FcsFile::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+        let original = fcs_file.read().unwrap();
+        let mut flow_sample = fcs_file.read().unwrap();
+        let arcsinh = Transform::arcsinh(150.0).unwrap();
+        let log = Transform::log(262144.0, 4.5).unwrap();
+
+        flow_sample.apply_transform(arcsinh, &["BL1-A".to_string()]).unwrap();
+        let transformed = column(&flow_sample, "BL1-A");
+
+        // Neither a transformed channel nor a channel listed twice is transformed again
+        let result = flow_sample.apply_transform(log, &["RL1-A".to_string(), "BL1-A".to_string()]);
+        assert!(matches!(result, Err(FcsError::InvalidTransform(ref reason)) if reason.contains("BL1-A")), "{:?}", result);
+        let result = flow_sample.apply_transform(log, &["RL1-A".to_string(), "RL1-A".to_string()]);
+        assert!(matches!(result, Err(FcsError::InvalidTransform(ref reason)) if reason.contains("RL1-A")), "{:?}", result);
+        assert_eq!(column(&flow_sample, "BL1-A"), transformed);
+        assert_eq!(column(&flow_sample, "RL1-A"), column(&original, "RL1-A"));
+        assert_eq!(flow_sample.transforms.len(), 1);
+
+        flow_sample.invert_transform(&["BL1-A".to_string()]).unwrap();
+        flow_sample.apply_transform(log, &["BL1-A".to_string()]).unwrap();
+        assert_eq!(flow_sample.transforms["BL1-A"], log);
     }
 
     #[test]
@@ -518,10 +2521,310 @@ FlowSample:
         let flow_sample = parse_data(&mut reader, &metadata).expect("Failed to parse data");
 
         let fsc: Vec<f64> = flow_sample.data.column("FSC-H").unwrap().f64().unwrap().into_no_null_iter().collect();
-        let ssc: Vec<f64> = flow_sample.data.column("APC-A").unwrap().f64().unwrap().into_no_null_iter().collect();
+        let ssc: Vec<f64> = flow_sample.data.column("RL1-A").unwrap().f64().unwrap().into_no_null_iter().collect();
+
+        assert_eq!(fsc[..=2], vec![409814.0, 419945.0, 326747.0]);
+        assert_eq!(ssc[..=2], vec![6727.0, 2618.0, 1770.0]);
+    }
+
+    #[test]
+    fn test_parse_data_column_order() {
+        let fcs_file = FcsFile::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+        let flow_sample = fcs_file.read().unwrap();
+
+        assert_eq!(
+            flow_sample.get_dataframe_columns(),
+            vec!["Time", "FSC-A", "SSC-A", "BL1-A", "YL1-A", "RL1-A", "FSC-H", "SSC-H", "FSC-W", "SSC-W"]
+        );
+        for (position, channel) in flow_sample.channels.iter().enumerate() {
+            assert_eq!(channel.index, position + 1);
+            assert_eq!(flow_sample.get_dataframe_columns()[position], channel.name);
+        }
+        assert_eq!(flow_sample.get_label("SSC-A"), Some("SSC-SSC-A"));
+        assert_eq!(flow_sample.get_channel(4).unwrap().label.as_deref(), Some("FITC-A"));
+    }
+
+    #[test]
+    fn test_parse_data_without_labels() {
+        let events = vec![vec![1.0, 2.0], vec![3.0, 4.0]];
+        let mut bytes = synthetic_fcs("F", "1,2,3,4", &[32, 32], &events);
+        // Rename the $PnS keywords so the file no longer carries any labels
+        for i in 0..bytes.len() - 3 {
+            if &bytes[i..i + 3] == b"S/L" {
+                bytes[i] = b'X';
+            }
+        }
+
+        let sample = FcsFile::from_bytes(&bytes).read().unwrap();
+        assert_eq!(sample.get_dataframe_columns(), vec!["P1", "P2"]);
+        assert_eq!(sample.get_label("P1"), None);
+        assert_eq!(column(&sample, "P2"), vec![2.0, 4.0]);
+    }
+
+    #[test]
+    fn test_parse_fcs2_0() {
+        let keywords = [
+            ("$BYTEORD", "1,2"),
+            ("$DATATYPE", "I"),
+            ("$MODE", "L"),
+            ("$NEXTDATA", "0"),
+            ("$PAR", "2"),
+            ("$P1N", "FSC"),
+            ("$P1B", "16"),
+            ("$P1R", "65536"),
+            ("$P2N", "SSC"),
+            ("$P2B", "16"),
+            ("$P2R", "65536"),
+        ];
+        // No $TOT and no $BEGINDATA/$ENDDATA: the header locates three 4 byte events
+        let data = [1u8, 0, 2, 0, 3, 0, 4, 0, 5, 0, 6, 0];
+        let bytes = build_fcs("FCS2.0", &keywords, &data, false);
+        let sample = FcsFile::from_bytes(&bytes).read().unwrap();
+
+        assert_eq!(column(&sample, "FSC"), vec![1.0, 3.0, 5.0]);
+        assert_eq!(column(&sample, "SSC"), vec![2.0, 4.0, 6.0]);
+
+        let mut keywords = keywords.to_vec();
+        keywords[0] = ("$BYTEORD", "2,1");
+        let bytes = build_fcs("FCS2.0", &keywords, &data, false);
+        let sample = FcsFile::from_bytes(&bytes).read().unwrap();
+
+        assert_eq!(column(&sample, "FSC"), vec![256.0, 768.0, 1280.0]);
+    }
+
+    #[test]
+    fn test_parse_fcs3_2_parameter_data_types() {
+        let keywords = [
+            ("$BYTEORD", "1,2,3,4"),
+            ("$CYT", "Test Cytometer"),
+            ("$CYTSN", "SN-42"),
+            ("$BEGINDATETIME", "2023-04-01T09:30:00.5+02:00"),
+            ("$ENDDATETIME", "2023-04-01T09:45:00Z"),
+            ("$DATATYPE", "F"),
+            ("$NEXTDATA", "0"),
+            ("$PAR", "3"),
+            ("$TOT", "2"),
+            ("$P1N", "Time"),
+            ("$P1B", "16"),
+            ("$P1E", "0,0"),
+            ("$P1R", "65536"),
+            ("$P1DATATYPE", "I"),
+            ("$P2N", "FSC-A"),
+            ("$P2B", "32"),
+            ("$P2E", "0,0"),
+            ("$P2R", "1024"),
+            ("$P3N", "SSC-A"),
+            ("$P3B", "64"),
+            ("$P3E", "0,0"),
+            ("$P3R", "1024"),
+            ("$P3DATATYPE", "D"),
+        ];
+        let mut data = Vec::new();
+        for (time, fsc, ssc) in [(7u16, 1.5f32, -2.25f64), (8, 2.5, 3.75)] {
+            data.extend(time.to_le_bytes());
+            data.extend(fsc.to_le_bytes());
+            data.extend(ssc.to_le_bytes());
+        }
+        let bytes = build_fcs("FCS3.2", &keywords, &data, true);
+        let sample = FcsFile::from_bytes(&bytes).read().unwrap();
+
+        assert_eq!(column(&sample, "Time"), vec![7.0, 8.0]);
+        assert_eq!(column(&sample, "FSC-A"), vec![1.5, 2.5]);
+        assert_eq!(column(&sample, "SSC-A"), vec![-2.25, 3.75]);
+        assert_eq!(sample.get_cytometer_serial(), Some("SN-42"));
+        assert_eq!(sample.get_begin_datetime(), DateTime::parse_from_rfc3339("2023-04-01T09:30:00.5+02:00").ok());
+        assert_eq!(sample.get_end_datetime(), DateTime::parse_from_rfc3339("2023-04-01T09:45:00Z").ok());
+        assert!(format!("{}", sample).contains("Begin Time: 2023-04-01T09:30:00.500+02:00"));
+    }
+
+    #[test]
+    fn test_byte_order_from_keyword() {
+        assert_eq!(ByteOrder::from_keyword("1,2,3,4").unwrap(), ByteOrder::LittleEndian);
+        assert_eq!(ByteOrder::from_keyword("1,2").unwrap(), ByteOrder::LittleEndian);
+        assert_eq!(ByteOrder::from_keyword("4,3,2,1").unwrap(), ByteOrder::BigEndian);
+        assert_eq!(ByteOrder::from_keyword("2,1").unwrap(), ByteOrder::BigEndian);
+        assert_eq!(ByteOrder::from_keyword("1,2,3,4,5,6,7,8").unwrap(), ByteOrder::LittleEndian);
+        assert_eq!(ByteOrder::from_keyword(" 8,7,6,5,4,3,2,1 ").unwrap(), ByteOrder::BigEndian);
+        assert_eq!(ByteOrder::from_keyword("3,4,1,2").unwrap(), ByteOrder::Permutation(vec![3, 4, 1, 2]));
+        assert_eq!(ByteOrder::from_keyword("3,4,1,2").unwrap().keyword(), "3,4,1,2");
+
+        for value in ["", "1,2,2,4", "0,1", "1,2,4", "1;2;3;4", "LE"] {
+            let err = ByteOrder::from_keyword(value).unwrap_err();
+            assert!(
+                matches!(err, FcsError::InvalidKeyword { ref keyword, value: ref v } if keyword == "$BYTEORD" && v == value),
+                "Unexpected error {:?}", err
+            );
+            assert!(err.to_string().contains(&format!("`{}`", value)), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_byte_order_permutation() {
+        let byte_order = ByteOrder::from_keyword("3,4,1,2").unwrap();
+        let mut bytes = 0x12345678u32.to_le_bytes();
+        byte_order.from_little_endian(&mut bytes).unwrap();
+        assert_eq!(bytes, [0x34, 0x12, 0x78, 0x56]);
+        byte_order.to_little_endian(&mut bytes).unwrap();
+        assert_eq!(u32::from_le_bytes(bytes), 0x12345678);
+
+        let mut bytes = [1, 2];
+        let err = byte_order.to_little_endian(&mut bytes).unwrap_err();
+        assert_eq!(err.to_string(), "Invalid FCS Data: $BYTEORD 3,4,1,2 cannot be applied to 2 byte values");
+
+        let mut bytes = [7];
+        byte_order.to_little_endian(&mut bytes).unwrap();
+        assert_eq!(bytes, [7]);
+    }
+
+    #[test]
+    fn test_read_events_byte_order_permutations() {
+        // A float and a 32 bit integer parameter with the 16 bit words swapped
+        let mut data = Vec::new();
+        for (float, integer) in [(1.5f32, 70000u32), (-2.25, 3)] {
+            for little_endian in [float.to_le_bytes(), integer.to_le_bytes()] {
+                data.extend([2, 3, 0, 1].map(|significance| little_endian[significance]));
+            }
+        }
+        let metadata = HashMap::from([
+            ("$P2B".to_string(), "32".to_string()),
+            ("$P2DATATYPE".to_string(), "I".to_string()),
+        ]);
+        let byte_order = ByteOrder::from_keyword("3,4,1,2").unwrap();
+        let mut reader = BufReader::new(std::io::Cursor::new(data));
+        let columns = read_events(&mut reader, "F", &byte_order, 2, 2, &metadata).unwrap();
+        assert_eq!(columns, vec![vec![1.5, -2.25], vec![70000.0, 3.0]]);
+
+        // An 8 byte ordering for doubles
+        let mut data = Vec::new();
+        for value in [1e9f64, -0.5] {
+            let little_endian = value.to_le_bytes();
+            data.extend([4, 5, 6, 7, 0, 1, 2, 3].map(|significance| little_endian[significance]));
+        }
+        let byte_order = ByteOrder::from_keyword("5,6,7,8,1,2,3,4").unwrap();
+        let mut reader = BufReader::new(std::io::Cursor::new(data));
+        let columns = read_events(&mut reader, "D", &byte_order, 2, 1, &HashMap::new()).unwrap();
+        assert_eq!(columns, vec![vec![1e9, -0.5]]);
+
+        // A 4 byte permutation cannot be applied to 8 byte doubles
+        let mut reader = BufReader::new(std::io::Cursor::new(vec![0u8; 8]));
+        let byte_order = ByteOrder::from_keyword("3,4,1,2").unwrap();
+        let err = read_events(&mut reader, "D", &byte_order, 1, 1, &HashMap::new()).unwrap_err();
+        assert!(err.to_string().contains("$BYTEORD 3,4,1,2"), "{}", err);
+    }
+
+    fn scale_from(keywords: &[(&str, &str)], data_type: DataType) -> Result<Scale, FcsError> {
+        let metadata = keywords.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        Scale::from_metadata(&metadata, 1, data_type)
+    }
+
+    #[test]
+    fn test_scale_bit_mask() {
+        let scale = scale_from(&[("$P1R", "1024"), ("$P1E", "0,0")], DataType::Integer).unwrap();
+        assert_eq!(scale.bit_mask, Some(1023));
+        assert_eq!(scale.apply(1023.0), 1023.0);
+        assert_eq!(scale.apply((0xFC00 | 5) as f64), 5.0);
+
+        // A range that is not a power of two keeps the bits needed for range - 1
+        let scale = scale_from(&[("$P1R", "1000")], DataType::Integer).unwrap();
+        assert_eq!(scale.bit_mask, Some(1023));
+
+        let scale = scale_from(&[("$P1R", "4294967296")], DataType::Integer).unwrap();
+        assert_eq!(scale.bit_mask, Some(u32::MAX as u64));
+
+        // Floating point values are never masked
+        let scale = scale_from(&[("$P1R", "1024")], DataType::Float).unwrap();
+        assert_eq!(scale.bit_mask, None);
+        assert_eq!(scale.apply(4096.5), 4096.5);
+    }
+
+    #[test]
+    fn test_scale_log_amplification() {
+        let scale = scale_from(&[("$P1R", "1024"), ("$P1E", "4,1")], DataType::Integer).unwrap();
+        assert_eq!(scale.amplification, Amplification::Logarithmic { decades: 4.0, offset: 1.0 });
+        assert_eq!(scale.apply(0.0), 1.0);
+        assert_eq!(scale.apply(256.0), 10.0);
+        assert_eq!(scale.apply(768.0), 1000.0);
+
+        let scale = scale_from(&[("$P1R", "256"), ("$P1E", "2,0.5")], DataType::Integer).unwrap();
+        assert_eq!(scale.apply(128.0), 5.0);
+
+        // An offset of 0 is read as 1
+        let scale = scale_from(&[("$P1R", "1024"), ("$P1E", "4,0")], DataType::Integer).unwrap();
+        assert_eq!(scale.apply(512.0), 100.0);
+
+        // Log amplification does not apply to floating point values
+        let scale = scale_from(&[("$P1R", "1024"), ("$P1E", "4,1")], DataType::Float).unwrap();
+        assert_eq!(scale.apply(512.0), 512.0);
+    }
+
+    #[test]
+    fn test_scale_gain() {
+        let scale = scale_from(&[("$P1R", "1024"), ("$P1E", "0,0"), ("$P1G", "4")], DataType::Integer).unwrap();
+        assert_eq!(scale.amplification, Amplification::Linear { gain: 4.0 });
+        assert_eq!(scale.apply(100.0), 25.0);
+
+        let scale = scale_from(&[("$P1E", "0,0"), ("$P1G", "0.5")], DataType::Float).unwrap();
+        assert_eq!(scale.apply(100.0), 200.0);
+    }
+
+    #[test]
+    fn test_scale_invalid_keywords() {
+        for (keywords, expected) in [
+            (vec![("$P1R", "abc")], "$P1R"),
+            (vec![("$P1R", "1024"), ("$P1E", "4")], "$P1E"),
+            (vec![("$P1R", "1024"), ("$P1E", "x,1")], "$P1E"),
+            (vec![("$P1R", "1024"), ("$P1G", "0")], "$P1G"),
+            (vec![("$P1E", "4,1")], "$P1R"),
+        ] {
+            let result = scale_from(&keywords, DataType::Integer);
+            assert!(
+                matches!(result, Err(FcsError::InvalidKeyword { ref keyword, .. }) if keyword == expected),
+                "Unexpected result {:?} for {:?}", result, keywords
+            );
+        }
+    }
+
+    #[test]
+    fn test_read_scaled_and_raw_values() {
+        let keywords = [
+            ("$BYTEORD", "1,2,3,4"),
+            ("$CYT", "Test Cytometer"),
+            ("$DATATYPE", "I"),
+            ("$MODE", "L"),
+            ("$NEXTDATA", "0"),
+            ("$PAR", "3"),
+            ("$TOT", "2"),
+            ("$P1N", "Masked"),
+            ("$P1B", "16"),
+            ("$P1E", "0,0"),
+            ("$P1R", "1024"),
+            ("$P2N", "Log"),
+            ("$P2B", "16"),
+            ("$P2E", "3,1"),
+            ("$P2R", "1024"),
+            ("$P3N", "Gain"),
+            ("$P3B", "16"),
+            ("$P3E", "0,0"),
+            ("$P3R", "65536"),
+            ("$P3G", "2"),
+        ];
+        let mut data = Vec::new();
+        for value in [0xF000u16 | 7, 0, 10, 0x0400 | 9, 1024 * 2 / 3, 11] {
+            data.extend(value.to_le_bytes());
+        }
+        let bytes = build_fcs("FCS3.2", &keywords, &data, true);
+        let fcs_file = FcsFile::from_bytes(&bytes);
 
-        assert_eq!(fsc[..=2], vec![423142.0, 212991.0, 94.0]);
-        assert_eq!(ssc[..=2], vec![200.0, 626392.0, 496565.0]);
+        let sample = fcs_file.read().unwrap();
+        assert_eq!(column(&sample, "Masked"), vec![7.0, 9.0]);
+        assert_eq!(column(&sample, "Log")[0], 1.0);
+        assert!((column(&sample, "Log")[1] - 10f64.powf(3.0 * 682.0 / 1024.0)).abs() < 1e-9);
+        assert_eq!(column(&sample, "Gain"), vec![5.0, 5.5]);
+
+        let raw = fcs_file.read_raw().unwrap();
+        assert_eq!(column(&raw, "Masked"), vec![(0xF000 | 7) as f64, (0x0400 | 9) as f64]);
+        assert_eq!(column(&raw, "Log"), vec![0.0, 682.0]);
+        assert_eq!(column(&raw, "Gain"), vec![10.0, 11.0]);
     }
 
     #[test]
@@ -532,8 +2835,579 @@ FlowSample:
         let metadata = read_metadata(&mut reader).expect("Failed to read metadata");
         reader.seek(SeekFrom::Start(150)).expect("Failed to seek to data start");
 
-        let events = read_events::<LittleEndian>(&mut reader, "F", 3, 1, &metadata).expect("Failed to read events");
-        assert_eq!(events, vec![2.745084202615544e-6, 11018227712.0, 6.37629560262809e-10]);
+        let events = read_events(&mut reader, "F", &ByteOrder::LittleEndian, 3, 1, &metadata).expect("Failed to read events");
+        assert_eq!(events, vec![vec![2.745084202615544e-6, 11018227712.0, 6.37629560262809e-10]]);
+    }
+
+    #[test]
+    fn test_read_events_interleaved_float() {
+        let events = vec![
+            vec![1.0, 10.0, 100.0],
+            vec![2.0, 20.0, 200.0],
+            vec![3.0, 30.0, 300.0],
+            vec![4.0, 40.0, 400.0],
+        ];
+        let bytes = synthetic_fcs("F", "1,2,3,4", &[32, 32, 32], &events);
+        let sample = FcsFile::from_bytes(&bytes).read().unwrap();
+
+        assert_eq!(column(&sample, "P1"), vec![1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(column(&sample, "P2"), vec![10.0, 20.0, 30.0, 40.0]);
+        assert_eq!(column(&sample, "P3"), vec![100.0, 200.0, 300.0, 400.0]);
+    }
+
+    #[test]
+    fn test_read_events_interleaved_double_big_endian() {
+        let events = vec![
+            vec![-1.5, 0.25],
+            vec![2.5, 1e9],
+        ];
+        let bytes = synthetic_fcs("D", "4,3,2,1", &[64, 64], &events);
+        let sample = FcsFile::from_bytes(&bytes).read().unwrap();
+
+        assert_eq!(column(&sample, "P1"), vec![-1.5, 2.5]);
+        assert_eq!(column(&sample, "P2"), vec![0.25, 1e9]);
+    }
+
+    #[test]
+    fn test_read_events_interleaved_mixed_integer_widths() {
+        let events = vec![
+            vec![1.0, 70000.0, 3.0],
+            vec![4.0, 80000.0, 6.0],
+            vec![7.0, 90000.0, 9.0],
+        ];
+        let bytes = synthetic_fcs("I", "4,3,2,1", &[16, 32, 16], &events);
+        let sample = FcsFile::from_bytes(&bytes).read().unwrap();
+
+        assert_eq!(column(&sample, "P1"), vec![1.0, 4.0, 7.0]);
+        assert_eq!(column(&sample, "P2"), vec![70000.0, 80000.0, 90000.0]);
+        assert_eq!(column(&sample, "P3"), vec![3.0, 6.0, 9.0]);
+    }
+
+    /// Builds an FCS 3.1 file with integer parameters P1, P2, ... of the given `$PnB` widths
+    /// from a hand-packed DATA segment.
+    fn packed_fcs(byte_order: &str, bits: &[usize], n_events: usize, data: &[u8]) -> Vec<u8> {
+        let (n_params, n_events) = (bits.len().to_string(), n_events.to_string());
+        let mut keywords = vec![
+            ("$BEGINANALYSIS".to_string(), "0".to_string()),
+            ("$ENDANALYSIS".to_string(), "0".to_string()),
+            ("$BEGINSTEXT".to_string(), "0".to_string()),
+            ("$ENDSTEXT".to_string(), "0".to_string()),
+            ("$NEXTDATA".to_string(), "0".to_string()),
+            ("$MODE".to_string(), "L".to_string()),
+            ("$DATATYPE".to_string(), "I".to_string()),
+            ("$BYTEORD".to_string(), byte_order.to_string()),
+            ("$PAR".to_string(), n_params),
+            ("$TOT".to_string(), n_events),
+        ];
+        for (i, b) in bits.iter().enumerate() {
+            let n = i + 1;
+            keywords.push((format!("$P{n}N"), format!("P{n}")));
+            keywords.push((format!("$P{n}B"), b.to_string()));
+            keywords.push((format!("$P{n}E"), "0,0".to_string()));
+            keywords.push((format!("$P{n}R"), (1u128 << (*b).min(64)).to_string()));
+        }
+
+        let keywords = keywords.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect::<Vec<_>>();
+        build_fcs("FCS3.1", &keywords, data, true)
+    }
+
+    #[test]
+    fn test_read_events_byte_aligned_widths() {
+        // 8, 24 and 16 bit values: (7, 0x123456, 0xBEEF) and (255, 1, 2)
+        let big_endian = [0x07, 0x12, 0x34, 0x56, 0xBE, 0xEF, 0xFF, 0x00, 0x00, 0x01, 0x00, 0x02];
+        let little_endian = [0x07, 0x56, 0x34, 0x12, 0xEF, 0xBE, 0xFF, 0x01, 0x00, 0x00, 0x02, 0x00];
+
+        for (byte_order, data) in [("4,3,2,1", big_endian), ("1,2,3,4", little_endian)] {
+            let bytes = packed_fcs(byte_order, &[8, 24, 16], 2, &data);
+            let sample = FcsFile::from_bytes(&bytes).read().unwrap();
+
+            assert_eq!(column(&sample, "P1"), vec![7.0, 255.0], "{}", byte_order);
+            assert_eq!(column(&sample, "P2"), vec![0x123456 as f64, 1.0], "{}", byte_order);
+            assert_eq!(column(&sample, "P3"), vec![0xBEEF as f64, 2.0], "{}", byte_order);
+        }
+    }
+
+    #[test]
+    fn test_read_events_packed_widths() {
+        // 10 and 12 bit values (0x2AB, 0xC3D) and (0x155, 0x00F) in a 44 bit stream padded to
+        // 6 bytes. Big endian: 1010101011 110000111101 0101010101 000000001111 0000
+        let big_endian = [0xAA, 0xF0, 0xF5, 0x55, 0x00, 0xF0];
+        let little_endian = [0xAB, 0xF6, 0x70, 0x55, 0x0F, 0x00];
+
+        for (byte_order, data) in [("4,3,2,1", big_endian), ("1,2,3,4", little_endian)] {
+            let bytes = packed_fcs(byte_order, &[10, 12], 2, &data);
+            let sample = FcsFile::from_bytes(&bytes).read().unwrap();
+
+            assert_eq!(column(&sample, "P1"), vec![683.0, 341.0], "{}", byte_order);
+            assert_eq!(column(&sample, "P2"), vec![3133.0, 15.0], "{}", byte_order);
+        }
+    }
+
+    #[test]
+    fn test_read_events_packed_mixed_widths() {
+        // 10, 20 and 2 bit values, 32 bits per event
+        let big_endian = [0xFF, 0xFF, 0xFF, 0xFE, 0x00, 0x2A, 0xAA, 0xA9, 0x01, 0x40, 0x00, 0x07];
+        let little_endian = [0xFF, 0xFF, 0xFF, 0xBF, 0x00, 0xA8, 0xAA, 0x6A, 0x05, 0x04, 0x00, 0xC0];
+
+        for (byte_order, data) in [("4,3,2,1", big_endian), ("1,2,3,4", little_endian)] {
+            let bytes = packed_fcs(byte_order, &[10, 20, 2], 3, &data);
+            let sample = FcsFile::from_bytes(&bytes).read().unwrap();
+
+            assert_eq!(column(&sample, "P1"), vec![1023.0, 0.0, 5.0], "{}", byte_order);
+            assert_eq!(column(&sample, "P2"), vec![1048575.0, 699050.0, 1.0], "{}", byte_order);
+            assert_eq!(column(&sample, "P3"), vec![2.0, 1.0, 3.0], "{}", byte_order);
+        }
+    }
+
+    #[test]
+    fn test_read_events_invalid_widths() {
+        for bits in [0, 129] {
+            let bytes = packed_fcs("1,2,3,4", &[bits], 1, &[0; 17]);
+            let err = FcsFile::from_bytes(&bytes).read().unwrap_err();
+            assert!(err.to_string().contains(&format!("$P1B is {}", bits)), "{}", err);
+        }
+
+        // Packed values cannot be mixed with floating point parameters
+        let mut metadata = HashMap::from([
+            ("$P1B".to_string(), "12".to_string()),
+            ("$P2B".to_string(), "32".to_string()),
+            ("$P2DATATYPE".to_string(), "F".to_string()),
+        ]);
+        let mut reader = BufReader::new(std::io::Cursor::new(vec![0u8; 16]));
+        let err = read_events(&mut reader, "I", &ByteOrder::LittleEndian, 2, 2, &metadata).unwrap_err();
+        assert!(matches!(err, FcsError::InvalidData(ref message) if message.starts_with("Packed data")), "{:?}", err);
+
+        metadata.insert("$P1B".to_string(), "8".to_string());
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        assert!(read_events(&mut reader, "I", &ByteOrder::LittleEndian, 2, 2, &metadata).is_ok());
+    }
+
+    /// Decodes the DATA segment of `bytes` both from a reader and from memory, checks that the
+    /// samples match and returns the one decoded from memory.
+    fn parse_in_memory(bytes: &[u8], mode: ValueMode) -> FlowSample {
+        let mut reader = BufReader::new(std::io::Cursor::new(bytes));
+        let metadata = read_metadata(&mut reader).unwrap();
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        let header = crate::header::read_header(&mut reader).unwrap();
+        let offsets = data_offsets(&header, &metadata);
+
+        let expected = parse_data_segment_with_mode(&mut reader, &metadata, &offsets, mode).unwrap();
+        let sample = parse_data_bytes(&bytes[offsets.clone()], &metadata, mode).unwrap();
+        assert!(sample.data.equals(&expected.data));
+        sample
+    }
+
+    #[test]
+    fn test_parse_data_bytes() {
+        let bytes = std::fs::read("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+        for mode in [ValueMode::Scaled, ValueMode::Raw] {
+            let sample = parse_in_memory(&bytes, mode);
+            assert_eq!(sample.data.shape(), (8821, 10));
+        }
+
+        let little_endian = [0xFF, 0xFF, 0xFF, 0xBF, 0x00, 0xA8, 0xAA, 0x6A, 0x05, 0x04, 0x00, 0xC0];
+        let sample = parse_in_memory(&packed_fcs("1,2,3,4", &[10, 20, 2], 3, &little_endian), ValueMode::Scaled);
+        assert_eq!(column(&sample, "P2"), vec![1048575.0, 699050.0, 1.0]);
+
+        let little_endian = [0x07, 0x56, 0x34, 0x12, 0xEF, 0xBE, 0xFF, 0x01, 0x00, 0x00, 0x02, 0x00];
+        let sample = parse_in_memory(&packed_fcs("1,2,3,4", &[8, 24, 16], 2, &little_endian), ValueMode::Scaled);
+        assert_eq!(column(&sample, "P2"), vec![0x123456 as f64, 1.0]);
+
+        for (bits, data) in [(["4", "3"], &b"0012 45 100  71023  9"[..]), (["*", "*"], &b"12 45\r\n100,,7\t1023  9\n"[..])] {
+            let sample = parse_in_memory(&ascii_fcs(bits, data), ValueMode::Scaled);
+            assert_eq!(column(&sample, "FSC"), vec![12.0, 100.0, 1023.0]);
+        }
+    }
+
+    #[test]
+    fn test_parse_data_bytes_truncated() {
+        let bytes = packed_fcs("1,2,3,4", &[16, 16], 3, &[1, 0, 2, 0, 3, 0, 4, 0, 5, 0, 6, 0]);
+        let mut reader = BufReader::new(std::io::Cursor::new(&bytes));
+        let metadata = read_metadata(&mut reader).unwrap();
+        let data = &bytes[bytes.len() - 12..];
+
+        let err = parse_data_bytes(&data[..10], &metadata, ValueMode::Raw).unwrap_err();
+        assert!(matches!(err, FcsError::IoError(ref err) if err.kind() == std::io::ErrorKind::UnexpectedEof), "{:?}", err);
+        let err = parse_data_bytes(&[], &metadata, ValueMode::Raw).unwrap_err();
+        assert!(matches!(err, FcsError::IoError(_)), "{:?}", err);
+    }
+
+    #[test]
+    fn test_decode_events() {
+        let metadata = HashMap::from([
+            ("$P1B".to_string(), "16".to_string()),
+            ("$P2B".to_string(), "32".to_string()),
+            ("$P2DATATYPE".to_string(), "F".to_string()),
+        ]);
+        let mut bytes = Vec::new();
+        for (p1, p2) in [(1u16, 1.5f32), (2, -2.5)] {
+            bytes.extend(p1.to_be_bytes());
+            bytes.extend(p2.to_be_bytes());
+        }
+
+        let columns = decode_events(&bytes, "I", &ByteOrder::BigEndian, 2, 2, &metadata).unwrap();
+        assert_eq!(columns, vec![vec![1.0, 2.0], vec![1.5, -2.5]]);
+        // Bytes after the last event are ignored
+        bytes.extend([0xFF; 3]);
+        let columns = decode_events(&bytes, "I", &ByteOrder::BigEndian, 1, 2, &metadata).unwrap();
+        assert_eq!(columns, vec![vec![1.0], vec![1.5]]);
+
+        let err = decode_events(&bytes[..5], "I", &ByteOrder::BigEndian, 1, 2, &metadata).unwrap_err();
+        assert!(matches!(err, FcsError::IoError(_)), "{:?}", err);
+    }
+
+    /// Reads the DATA segment of `bytes` with `options` both from a reader and from memory,
+    /// checks that the samples match and returns the one read from a reader.
+    fn read_selected_both(bytes: &[u8], options: &ReadOptions) -> Result<FlowSample, FcsError> {
+        let mut reader = BufReader::new(std::io::Cursor::new(bytes));
+        let metadata = read_metadata(&mut reader).unwrap();
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        let header = crate::header::read_header(&mut reader).unwrap();
+        let offsets = data_offsets(&header, &metadata);
+
+        let sample = parse_data_segment_with_options(&mut reader, &metadata, &offsets, options);
+        let in_memory = parse_data_bytes_with_options(&bytes[offsets], &metadata, options);
+        match (&sample, &in_memory) {
+            (Ok(sample), Ok(in_memory)) => assert!(sample.data.equals(&in_memory.data)),
+            (Err(err), Err(in_memory)) => assert_eq!(err.to_string(), in_memory.to_string()),
+            _ => panic!("Results differ: {:?} and {:?}", sample, in_memory),
+        }
+        sample
+    }
+
+    #[test]
+    fn test_read_options_channels_and_events() {
+        // Events (i, 1000 + i, 2000 + i) for i in 0..20
+        let mut data = Vec::new();
+        for i in 0..20u16 {
+            for value in [i, 1000 + i, 2000 + i] {
+                data.extend(value.to_le_bytes());
+            }
+        }
+        let bytes = packed_fcs("1,2", &[16, 16, 16], 20, &data);
+
+        let sample = read_selected_both(&bytes, &ReadOptions::new().channels(["P3", "P1"])).unwrap();
+        assert_eq!(sample.get_dataframe_columns(), vec!["P3", "P1"]);
+        assert_eq!(sample.channels.iter().map(|channel| channel.index).collect::<Vec<_>>(), vec![3, 1]);
+        assert_eq!(column(&sample, "P1"), (0..20).map(f64::from).collect::<Vec<_>>());
+
+        let options = ReadOptions::new().channels([2]).events(5..15).step(4);
+        let sample = read_selected_both(&bytes, &options).unwrap();
+        assert_eq!(column(&sample, "P2"), vec![1005.0, 1009.0, 1013.0]);
+
+        let options = ReadOptions::new().events(3..100).step(2).limit(4);
+        let sample = read_selected_both(&bytes, &options).unwrap();
+        assert_eq!(sample.data.shape(), (4, 3));
+        assert_eq!(column(&sample, "P3"), vec![2003.0, 2005.0, 2007.0, 2009.0]);
+
+        let sample = read_selected_both(&bytes, &ReadOptions::new().events(18..100)).unwrap();
+        assert_eq!(column(&sample, "P1"), vec![18.0, 19.0]);
+        for options in [ReadOptions::new().events(25..30), ReadOptions::new().limit(0)] {
+            let sample = read_selected_both(&bytes, &options).unwrap();
+            assert_eq!(sample.data.shape(), (0, 3));
+        }
+    }
+
+    #[test]
+    fn test_read_options_packed_and_ascii() {
+        // 12 bit values 0x001, 0x002, ... so events after the first do not start on a byte
+        let mut data = Vec::new();
+        for i in 0..5u32 {
+            let (a, b) = (4 * i + 1, 4 * i + 3);
+            data.extend([(a >> 4) as u8, ((a & 0xF) << 4 | (a + 1) >> 8) as u8, ((a + 1) & 0xFF) as u8]);
+            data.extend([(b >> 4) as u8, ((b & 0xF) << 4 | (b + 1) >> 8) as u8, ((b + 1) & 0xFF) as u8]);
+        }
+        let bytes = packed_fcs("4,3,2,1", &[12, 12], 10, &data);
+        let options = ReadOptions::new().channels(["P2"]).events(1..10).step(3);
+        let sample = read_selected_both(&bytes, &options).unwrap();
+        assert_eq!(column(&sample, "P2"), vec![4.0, 10.0, 16.0]);
+
+        for bits in [["4", "3"], ["*", "*"]] {
+            let data: &[u8] = if bits[0] == "*" { b"12 45\r\n100,,7\t1023  9\n" } else { b"0012 45 100  71023  9" };
+            let options = ReadOptions::new().channels(["SSC"]).events(1..3);
+            let sample = read_selected_both(&ascii_fcs(bits, data), &options).unwrap();
+            assert_eq!(column(&sample, "SSC"), vec![7.0, 9.0], "{:?}", bits);
+        }
+
+        // Values that are not selected are not parsed
+        let options = ReadOptions::new().channels(["SSC"]);
+        let sample = read_selected_both(&ascii_fcs(["*", "*"], b"x 45 y 7 z 9"), &options).unwrap();
+        assert_eq!(column(&sample, "SSC"), vec![45.0, 7.0, 9.0]);
+    }
+
+    #[test]
+    fn test_read_fixed_width_blocks() {
+        let data = (0..200u8).map(|i| i.wrapping_mul(37)).collect::<Vec<_>>();
+        let selections = [(0, 40, 1), (1, 13, 3), (3, 5, 7), (2, 0, 1)];
+        for (bits, byte_order) in [(12, ByteOrder::BigEndian), (16, ByteOrder::LittleEndian), (3, ByteOrder::LittleEndian)] {
+            let formats = [(DataType::Integer, bits), (DataType::Integer, bits)];
+            for &(start, n_rows, step) in &selections {
+                let selection = Selection { params: vec![1, 0], start, n_rows, step };
+                let skipped = selection.skipped_bytes(&formats);
+                let expected = decode_selected(&data[skipped..], &formats, &byte_order, &selection).unwrap();
+                // Blocks of one event upwards, including blocks that end inside a byte
+                for block_bytes in 1..8 {
+                    let mut reader = BufReader::new(&data[skipped..]);
+                    let columns = read_fixed_width(&mut reader, &formats, &byte_order, &selection, block_bytes).unwrap();
+                    assert_eq!(columns, expected, "{} bits, {:?}, {} byte blocks", bits, (start, n_rows, step), block_bytes);
+                }
+            }
+        }
+
+        let formats = [(DataType::Integer, 16)];
+        let selection = Selection { params: vec![0], start: 0, n_rows: 40, step: 2 };
+        let err = read_fixed_width(&mut BufReader::new(&data[..70]), &formats, &ByteOrder::LittleEndian, &selection, 4).unwrap_err();
+        assert!(matches!(err, FcsError::IoError(ref err) if err.kind() == std::io::ErrorKind::UnexpectedEof), "{:?}", err);
+    }
+
+    #[test]
+    fn test_read_options_invalid_channels() {
+        let bytes = packed_fcs("1,2", &[16, 16], 1, &[1, 0, 2, 0]);
+        for (selector, name) in [(ChannelSelector::from("P9"), "P9"), (0.into(), "0"), (3.into(), "3")] {
+            let err = read_selected_both(&bytes, &ReadOptions::new().channels([selector])).unwrap_err();
+            assert!(matches!(err, FcsError::UnknownChannel(ref channel) if channel == name), "{:?}", err);
+        }
+
+        let options = ReadOptions::new().channels([ChannelSelector::from("P1"), 1.into()]);
+        let err = read_selected_both(&bytes, &options).unwrap_err();
+        assert!(matches!(err, FcsError::InvalidData(ref message) if message == "Channel 1 is selected more than once"), "{:?}", err);
+    }
+
+    #[test]
+    fn test_event_layout_too_many_events() {
+        let layout = |n_params: &str, n_events: &str| {
+            let mut metadata = HashMap::from([
+                ("$DATATYPE".to_string(), "I".to_string()),
+                ("$BYTEORD".to_string(), "1,2".to_string()),
+                ("$PAR".to_string(), n_params.to_string()),
+                ("$TOT".to_string(), n_events.to_string()),
+            ]);
+            for n in 1..=n_params.parse::<usize>().unwrap() {
+                metadata.insert(format!("$P{}B", n), "16".to_string());
+                metadata.insert(format!("$P{}R", n), "65536".to_string());
+            }
+            EventLayout::from_metadata(&metadata, &(0..=0))
+        };
+
+        assert_eq!(layout("2", "3").unwrap().n_events, 3);
+        for (n_params, n_events) in [("2", "9223372036854775808"), ("1", "2305843009213693952")] {
+            let err = layout(n_params, n_events).unwrap_err();
+            assert!(matches!(err, FcsError::InvalidData(ref message) if message.contains("too many")), "{:?}", err);
+        }
+    }
+
+    #[test]
+    fn test_read_options_zero_step() {
+        let bytes = packed_fcs("1,2", &[16, 16], 1, &[1, 0, 2, 0]);
+        let err = read_selected_both(&bytes, &ReadOptions::new().step(0)).unwrap_err();
+        assert!(matches!(err, FcsError::InvalidData(ref message) if message == "Event step must be non-zero"), "{:?}", err);
+    }
+
+    #[test]
+    fn test_decode_aligned_matches_serial() {
+        // 8, 16, 32 and 64 bit integers, a float and a double, over several parallel ranges
+        let formats = [
+            (DataType::Integer, 8),
+            (DataType::Integer, 16),
+            (DataType::Integer, 32),
+            (DataType::Integer, 64),
+            (DataType::Float, 32),
+            (DataType::Double, 64),
+        ];
+        let event_size = 1 + 2 + 4 + 8 + 4 + 8;
+        let n_events = 40_000 + 7;
+        let buffer = (0..n_events * event_size).map(|i| (i * 7 + i / 13) as u8).collect::<Vec<_>>();
+
+        let subset = Selection { params: vec![5, 0, 3], start: 0, n_rows: (n_events - 1) / 3 + 1, step: 3 };
+        for selection in [Selection::all(formats.len(), n_events), subset] {
+            let buffer = &buffer[..selection.span() * event_size];
+            for byte_order in [ByteOrder::LittleEndian, ByteOrder::BigEndian] {
+                let mut columns = vec![vec![0.0; selection.n_rows]; selection.params.len()];
+                decode_aligned(buffer, &formats, &byte_order, &selection, &mut columns).unwrap();
+
+                let mut expected = vec![vec![0.0; selection.n_rows]; selection.params.len()];
+                let mut parts = expected.iter_mut().map(|column| column.as_mut_slice()).collect::<Vec<_>>();
+                decode_aligned_into(buffer, &formats, &byte_order, &selection, &mut parts).unwrap();
+
+                for (column, expected) in columns.iter().zip(&expected) {
+                    let bits = column.iter().map(|value: &f64| value.to_bits()).collect::<Vec<_>>();
+                    assert_eq!(bits, expected.iter().map(|value: &f64| value.to_bits()).collect::<Vec<_>>());
+                }
+            }
+        }
+
+        // The error of the first invalid event is reported
+        let formats = [(DataType::Ascii, 16)];
+        let mut buffer = b"12".repeat(n_events);
+        buffer[2 * 30_000..2 * 30_000 + 2].copy_from_slice(b"x3");
+        buffer[2 * 20_000..2 * 20_000 + 2].copy_from_slice(b"x2");
+        let mut columns = vec![vec![0.0; n_events]];
+        let selection = Selection::all(1, n_events);
+        let err = decode_aligned(&buffer, &formats, &ByteOrder::LittleEndian, &selection, &mut columns).unwrap_err();
+        assert!(err.to_string().contains("x2"), "{}", err);
+    }
+
+    #[test]
+    fn test_decode_events_serial() {
+        let metadata = HashMap::from([
+            ("$P1B".to_string(), "16".to_string()),
+            ("$P2B".to_string(), "32".to_string()),
+        ]);
+        let bytes = (0..6 * 40_000).map(|i| (i * 7) as u8).collect::<Vec<_>>();
+        let serial = decode_events_serial(&bytes, "I", &ByteOrder::BigEndian, 40_000, 2, &metadata).unwrap();
+        assert_eq!(serial, decode_events(&bytes, "I", &ByteOrder::BigEndian, 40_000, 2, &metadata).unwrap());
+
+        let err = decode_events_serial(&bytes[1..], "I", &ByteOrder::BigEndian, 40_000, 2, &metadata).unwrap_err();
+        assert!(matches!(err, FcsError::IoError(ref err) if err.kind() == std::io::ErrorKind::UnexpectedEof), "{:?}", err);
+    }
+
+    /// Decodes the DATA segment of `bytes` in chunks of `chunk_size` events with `options`,
+    /// after cutting `cut` bytes off the end of the file.
+    fn decode_chunks(bytes: &[u8], options: &ReadOptions, chunk_size: usize, cut: usize) -> Vec<Result<DataFrame, FcsError>> {
+        let mut reader = BufReader::new(std::io::Cursor::new(bytes));
+        let metadata = read_metadata(&mut reader).unwrap();
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        let header = crate::header::read_header(&mut reader).unwrap();
+        let offsets = data_offsets(&header, &metadata);
+
+        let mut decoder = ChunkDecoder::new(&metadata, &offsets, options, chunk_size).unwrap();
+        let mut reader = std::io::Cursor::new(&bytes[..bytes.len() - cut]);
+        std::iter::from_fn(|| decoder.decode_next(&mut reader, *offsets.start() as u64).transpose()).collect()
+    }
+
+    /// Returns the heights of `chunks` and the values of `name` across them.
+    fn chunk_column(chunks: &[Result<DataFrame, FcsError>], name: &str) -> (Vec<usize>, Vec<f64>) {
+        let chunks = chunks.iter().map(|chunk| chunk.as_ref().unwrap()).collect::<Vec<_>>();
+        let heights = chunks.iter().map(|chunk| chunk.height()).collect();
+        let values = chunks.iter()
+            .flat_map(|chunk| chunk.column(name).unwrap().f64().unwrap().into_no_null_iter().collect::<Vec<_>>())
+            .collect();
+        (heights, values)
+    }
+
+    #[test]
+    fn test_chunk_decoder() {
+        let data = [1, 0, 2, 0, 3, 0, 4, 0, 5, 0, 6, 0];
+        let chunks = decode_chunks(&packed_fcs("1,2,3,4", &[16, 16], 3, &data), &ReadOptions::new(), 2, 0);
+        let heights = chunks.iter().map(|chunk| chunk.as_ref().unwrap().height()).collect::<Vec<_>>();
+        assert_eq!(heights, vec![2, 1]);
+        assert_eq!(chunks[1].as_ref().unwrap().column("P2").unwrap().f64().unwrap().get(0), Some(6.0));
+
+        // Chunks of 12 bit values start inside a byte when needed to hold exactly 3 events
+        let mut data = Vec::new();
+        for i in 0..5u8 {
+            data.extend([0x00, 0x10 | i, 0x02 * i]);
+        }
+        let bytes = packed_fcs("4,3,2,1", &[12], 10, &data);
+        let (heights, values) = chunk_column(&decode_chunks(&bytes, &ReadOptions::new(), 3, 0), "P1");
+        assert_eq!(heights, vec![3, 3, 3, 1]);
+        assert_eq!(values, vec![1.0, 0.0, 1.0, 258.0, 1.0, 516.0, 1.0, 774.0, 1.0, 1032.0]);
+
+        let options = ReadOptions::new().events(1..10).step(3);
+        let (heights, values) = chunk_column(&decode_chunks(&bytes, &options, 2, 0), "P1");
+        assert_eq!(heights, vec![2, 1]);
+        assert_eq!(values, vec![0.0, 1.0, 774.0]);
+    }
+
+    #[test]
+    fn test_chunk_decoder_delimited_ascii() {
+        let bytes = ascii_fcs(["*", "*"], b"12 45\r\n100,,7\t1023  9\n");
+        let (heights, values) = chunk_column(&decode_chunks(&bytes, &ReadOptions::new(), 2, 0), "FSC");
+        assert_eq!(heights, vec![2, 1]);
+        assert_eq!(values, vec![12.0, 100.0, 1023.0]);
+
+        // Events between chunks are skipped, and unselected channels are not decoded
+        let bytes = ascii_fcs(["*", "*"], b"1 x 2 y 3 z");
+        let options = ReadOptions::new().channels(["FSC"]).step(2);
+        let chunks = decode_chunks(&bytes, &options, 1, 0);
+        assert_eq!(chunks[0].as_ref().unwrap().get_column_names(), vec!["FSC"]);
+        let (heights, values) = chunk_column(&chunks, "FSC");
+        assert_eq!(heights, vec![1, 1]);
+        assert_eq!(values, vec![1.0, 3.0]);
+    }
+
+    #[test]
+    fn test_chunk_decoder_truncated() {
+        let bytes = packed_fcs("1,2,3,4", &[16, 16], 3, &[1, 0, 2, 0, 3, 0, 4, 0, 5, 0, 6, 0]);
+        let chunks = decode_chunks(&bytes, &ReadOptions::new(), 2, 2);
+
+        // The first chunk is decoded, and decoding stops after the error
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].as_ref().unwrap().height(), 2);
+        assert!(matches!(chunks[1], Err(FcsError::IoError(_))), "{:?}", chunks[1]);
+    }
+
+    /// Builds an FCS 3.1 file with two ASCII parameters, FSC and SSC, with the given `$PnB`.
+    fn ascii_fcs(bits: [&str; 2], data: &[u8]) -> Vec<u8> {
+        let keywords = [
+            ("$BEGINANALYSIS", "0"),
+            ("$ENDANALYSIS", "0"),
+            ("$BEGINSTEXT", "0"),
+            ("$ENDSTEXT", "0"),
+            ("$BYTEORD", "1,2,3,4"),
+            ("$DATATYPE", "A"),
+            ("$MODE", "L"),
+            ("$NEXTDATA", "0"),
+            ("$PAR", "2"),
+            ("$TOT", "3"),
+            ("$P1N", "FSC"),
+            ("$P1B", bits[0]),
+            ("$P1E", "0,0"),
+            ("$P1R", "1024"),
+            ("$P2N", "SSC"),
+            ("$P2B", bits[1]),
+            ("$P2E", "0,0"),
+            ("$P2R", "1024"),
+        ];
+        build_fcs("FCS3.1", &keywords, data, true)
+    }
+
+    #[test]
+    fn test_read_events_fixed_width_ascii() {
+        let bytes = ascii_fcs(["4", "3"], b"0012 45 100  71023  9");
+        let sample = FcsFile::from_bytes(&bytes).read().unwrap();
+
+        assert_eq!(column(&sample, "FSC"), vec![12.0, 100.0, 1023.0]);
+        assert_eq!(column(&sample, "SSC"), vec![45.0, 7.0, 9.0]);
+    }
+
+    #[test]
+    fn test_read_events_delimited_ascii() {
+        let bytes = ascii_fcs(["*", "*"], b"12 45\r\n100,,7\t1023  9\n");
+        let sample = FcsFile::from_bytes(&bytes).read().unwrap();
+
+        assert_eq!(column(&sample, "FSC"), vec![12.0, 100.0, 1023.0]);
+        assert_eq!(column(&sample, "SSC"), vec![45.0, 7.0, 9.0]);
+    }
+
+    #[test]
+    fn test_read_events_invalid_ascii() {
+        let read = |bits, data: &[u8]| match FcsFile::from_bytes(&ascii_fcs(bits, data)).read() {
+            Err(FcsError::SegmentError { source, .. }) => *source,
+            other => panic!("Unexpected result {:?}", other),
+        };
+
+        let err = read(["*", "3"], b"12 45 100 7 1023 9");
+        assert!(matches!(err, FcsError::InvalidData(ref message) if message.contains("$PnB to be *")), "{:?}", err);
+        let err = read(["4", "3"], b"0012 45 1x0  71023  9");
+        assert!(matches!(err, FcsError::InvalidData(ref message) if message.contains("\" 1x0\"")), "{:?}", err);
+        let err = read(["*", "*"], b"12 45 100 7 1023");
+        assert!(matches!(err, FcsError::InvalidData(ref message) if message.contains("5 of 6")), "{:?}", err);
+        let err = read(["0", "3"], b"0012 45 100  71023  9");
+        assert!(matches!(err, FcsError::InvalidKeyword { ref keyword, .. } if keyword == "$P1B"), "{:?}", err);
+    }
+
+    #[test]
+    fn test_read_delimited_ascii_large_event_count() {
+        let read = |n_params, n_rows| {
+            let selection = Selection { params: (0..n_params).collect(), start: 0, n_rows, step: 1 };
+            read_delimited_ascii(&mut BufReader::new(&b"12 45 100"[..]), n_params, &selection).unwrap_err()
+        };
+
+        let err = read(1, 9_000_000_000_000_000_000);
+        assert!(matches!(err, FcsError::InvalidData(ref message) if message.contains("3 of 9000000000000000000")), "{:?}", err);
+        let err = read(2, usize::MAX / 2 + 1);
+        assert!(matches!(err, FcsError::InvalidData(ref message) if message.contains("Too many")), "{:?}", err);
     }
 
     #[test]
@@ -0,0 +1,579 @@
+//! Scale transforms for displaying and gating flow cytometry data.
+//!
+//! Every transform maps channel values to a display scale with `forward` and back with
+//! `inverse`. The log, Logicle, hyperlog and biex transforms map their top of scale to 1.
+
+use std::fmt;
+use std::str::FromStr;
+use crate::FcsError;
+
+/// A scale transform of channel values.
+///
+/// Use the constructors such as `Transform::logicle` to create a transform, which validate the
+/// parameters. The `Display` and `FromStr` implementations convert a transform to and from a
+/// text form such as `logicle(T=262144, W=0.5, M=4.5, A=0)`, so transform settings can be
+/// exported and loaded again.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transform {
+    /// `asinh(x / cofactor)`.
+    Arcsinh { cofactor: f64 },
+    /// `log10(x / top) / decades + 1`, which maps `top / 10^decades..=top` to `0..=1`.
+    /// Values that are not positive map to NaN.
+    Log { top: f64, decades: f64 },
+    /// The Logicle transform of Parks, Roederer and Moore (2006).
+    Logicle(Logicle),
+    /// The hyperlog transform of Bagwell (2005), in the parameterization of Moore and Parks (2012).
+    Hyperlog(Hyperlog),
+    /// The biexponential transform of FlowJo.
+    Biex(Biex),
+}
+
+impl Transform {
+    /// Creates an arcsinh transform.
+    ///
+    /// # Errors
+    ///
+    /// Returns `FcsError::InvalidTransform` if `cofactor` is not positive and finite.
+    pub fn arcsinh(cofactor: f64) -> Result<Self, FcsError> {
+        check(cofactor.is_finite() && cofactor > 0.0, "arcsinh cofactor must be positive")?;
+        Ok(Transform::Arcsinh { cofactor })
+    }
+
+    /// Creates a log transform over `decades` decades below `top`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `FcsError::InvalidTransform` if `top` or `decades` is not positive and finite.
+    pub fn log(top: f64, decades: f64) -> Result<Self, FcsError> {
+        check(top.is_finite() && top > 0.0, "log top of scale must be positive")?;
+        check(decades.is_finite() && decades > 0.0, "log decades must be positive")?;
+        Ok(Transform::Log { top, decades })
+    }
+
+    /// Creates a Logicle transform.
+    ///
+    /// # Arguments
+    ///
+    /// * `t` - The top of scale, which maps to 1.
+    /// * `w` - The width of the linearization region in decades.
+    /// * `m` - The number of decades of the full log scale.
+    /// * `a` - The number of additional negative decades.
+    ///
+    /// # Errors
+    ///
+    /// Returns `FcsError::InvalidTransform` unless `t > 0`, `m > 0`, `0 <= w <= m / 2` and
+    /// `-w <= a <= m - 2w`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fcs_rs::data::Transform;
+    ///
+    /// let logicle = Transform::logicle(262144.0, 0.5, 4.5, 0.0).unwrap();
+    /// assert!((logicle.forward(262144.0) - 1.0).abs() < 1e-12);
+    /// assert!((logicle.inverse(logicle.forward(-100.0)) + 100.0).abs() < 1e-6);
+    /// ```
+    pub fn logicle(t: f64, w: f64, m: f64, a: f64) -> Result<Self, FcsError> {
+        Ok(Transform::Logicle(Logicle::new(t, w, m, a)?))
+    }
+
+    /// Creates a hyperlog transform with the same parameters as `Transform::logicle`, except
+    /// that `w` must be positive.
+    ///
+    /// # Errors
+    ///
+    /// Returns `FcsError::InvalidTransform` unless `t > 0`, `m > 0`, `0 < w <= m / 2` and
+    /// `-w <= a <= m - 2w`.
+    pub fn hyperlog(t: f64, w: f64, m: f64, a: f64) -> Result<Self, FcsError> {
+        Ok(Transform::Hyperlog(Hyperlog::new(t, w, m, a)?))
+    }
+
+    /// Creates a biex transform from the settings used by FlowJo.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_value` - The top of scale, which maps to 1.
+    /// * `positive_decades` - The number of positive decades.
+    /// * `negative_decades` - The number of additional negative decades.
+    /// * `width_basis` - The width basis, usually negative, e.g. `-10`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `FcsError::InvalidTransform` if the settings do not describe a valid Logicle
+    /// transform. See `Biex`.
+    pub fn biex(
+        max_value: f64,
+        positive_decades: f64,
+        negative_decades: f64,
+        width_basis: f64,
+    ) -> Result<Self, FcsError> {
+        Ok(Transform::Biex(Biex::new(max_value, positive_decades, negative_decades, width_basis)?))
+    }
+
+    /// Maps a channel value to the display scale.
+    pub fn forward(&self, x: f64) -> f64 {
+        match self {
+            Transform::Arcsinh { cofactor } => (x / cofactor).asinh(),
+            Transform::Log { top, decades } => {
+                if x > 0.0 { (x / top).log10() / decades + 1.0 } else { f64::NAN }
+            },
+            Transform::Logicle(logicle) => logicle.forward(x),
+            Transform::Hyperlog(hyperlog) => hyperlog.forward(x),
+            Transform::Biex(biex) => biex.logicle.forward(x),
+        }
+    }
+
+    /// Maps a display scale value back to a channel value.
+    pub fn inverse(&self, y: f64) -> f64 {
+        match self {
+            Transform::Arcsinh { cofactor } => y.sinh() * cofactor,
+            Transform::Log { top, decades } => top * 10f64.powf((y - 1.0) * decades),
+            Transform::Logicle(logicle) => logicle.inverse(y),
+            Transform::Hyperlog(hyperlog) => hyperlog.inverse(y),
+            Transform::Biex(biex) => biex.logicle.inverse(y),
+        }
+    }
+}
+
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transform::Arcsinh { cofactor } => write!(f, "arcsinh(cofactor={})", cofactor),
+            Transform::Log { top, decades } => write!(f, "log(top={}, decades={})", top, decades),
+            Transform::Logicle(l) => write!(f, "logicle(T={}, W={}, M={}, A={})", l.t, l.w, l.m, l.a),
+            Transform::Hyperlog(h) => write!(f, "hyperlog(T={}, W={}, M={}, A={})", h.t, h.w, h.m, h.a),
+            Transform::Biex(b) => write!(
+                f,
+                "biex(max_value={}, positive_decades={}, negative_decades={}, width_basis={})",
+                b.max_value, b.positive_decades, b.negative_decades, b.width_basis
+            ),
+        }
+    }
+}
+
+impl FromStr for Transform {
+    type Err = FcsError;
+
+    /// Parses the text form written by `Display`. Names are case insensitive and the
+    /// parameters must all be given, in any order.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || FcsError::InvalidTransform(format!("Cannot parse transform `{}`", s));
+
+        let (name, rest) = s.trim().split_once('(').ok_or_else(invalid)?;
+        let arguments = rest.strip_suffix(')').ok_or_else(invalid)?;
+        let mut values: Vec<(String, f64)> = Vec::new();
+        for argument in arguments.split(',').filter(|argument| !argument.trim().is_empty()) {
+            let (key, value) = argument.split_once('=').ok_or_else(invalid)?;
+            let value = value.trim().parse::<f64>().map_err(|_| invalid())?;
+            values.push((key.trim().to_ascii_lowercase(), value));
+        }
+
+        let name = name.trim().to_ascii_lowercase();
+        let expected: &[&str] = match name.as_str() {
+            "arcsinh" => &["cofactor"],
+            "log" => &["top", "decades"],
+            "logicle" | "hyperlog" => &["t", "w", "m", "a"],
+            "biex" => &["max_value", "positive_decades", "negative_decades", "width_basis"],
+            _ => return Err(invalid()),
+        };
+        if values.len() != expected.len() {
+            return Err(invalid());
+        }
+        let p = expected.iter()
+            .map(|key| values.iter().find(|(k, _)| k == key).map(|(_, value)| *value).ok_or_else(invalid))
+            .collect::<Result<Vec<f64>, FcsError>>()?;
+
+        match name.as_str() {
+            "arcsinh" => Transform::arcsinh(p[0]),
+            "log" => Transform::log(p[0], p[1]),
+            "logicle" => Transform::logicle(p[0], p[1], p[2], p[3]),
+            "hyperlog" => Transform::hyperlog(p[0], p[1], p[2], p[3]),
+            _ => Transform::biex(p[0], p[1], p[2], p[3]),
+        }
+    }
+}
+
+/// Returns the keyword that records the transform of parameter `index` in written files,
+/// e.g. `P3TRANSFORM`. FCS does not define such a keyword, so it does not start with `$`.
+pub(crate) fn transform_keyword(index: usize) -> String {
+    format!("P{}TRANSFORM", index)
+}
+
+/// Returns the parameter index of a keyword returned by `transform_keyword`.
+pub(crate) fn transform_keyword_index(keyword: &str) -> Option<usize> {
+    let index = keyword.strip_prefix('P')?.strip_suffix("TRANSFORM")?;
+    if index.is_empty() || !index.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    index.parse().ok()
+}
+
+/// The Logicle transform, which is linear around zero and logarithmic for large values.
+///
+/// The inverse is the biexponential `a * e^(b y) - c * e^(-d y) + f` for `y >= x1`, extended to
+/// `y < x1` by point symmetry around `(x1, 0)`. The forward transform solves it numerically.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Logicle {
+    pub t: f64,
+    pub w: f64,
+    pub m: f64,
+    pub a: f64,
+    coefficients: [f64; 5],
+    x1: f64,
+}
+
+impl Logicle {
+    fn new(t: f64, w: f64, m: f64, a: f64) -> Result<Self, FcsError> {
+        check_logicle_parameters(t, w, m, a)?;
+
+        let width = w / (m + a);
+        let x2 = a / (m + a);
+        let x1 = x2 + width;
+        let x0 = x2 + 2.0 * width;
+        let b = (m + a) * std::f64::consts::LN_10;
+        let d = solve_logicle_d(b, width);
+
+        let c_a = (x0 * (b + d)).exp();
+        let mf_a = (b * x1).exp() - c_a / (d * x1).exp();
+        let scale = t / (b.exp() - mf_a - c_a / d.exp());
+
+        Ok(Logicle {
+            t, w, m, a,
+            coefficients: [scale, b, c_a * scale, d, -mf_a * scale],
+            x1,
+        })
+    }
+
+    /// Returns the biexponential and its derivative at `y`.
+    fn biexponential(&self, y: f64) -> (f64, f64) {
+        let [a, b, c, d, f] = self.coefficients;
+        let (positive, negative) = (a * (b * y).exp(), c * (-d * y).exp());
+        (positive - negative + f, b * positive + d * negative)
+    }
+
+    fn forward(&self, x: f64) -> f64 {
+        symmetric_forward(x, self.x1, |y| self.biexponential(y))
+    }
+
+    fn inverse(&self, y: f64) -> f64 {
+        symmetric_inverse(y, self.x1, |y| self.biexponential(y).0)
+    }
+}
+
+/// The hyperlog transform, which is linear around zero and logarithmic for large values.
+///
+/// The inverse is `a * e^(b y) + c * y - f` for `y >= x1`, extended to `y < x1` by point
+/// symmetry around `(x1, 0)`. The forward transform solves it numerically.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hyperlog {
+    pub t: f64,
+    pub w: f64,
+    pub m: f64,
+    pub a: f64,
+    coefficients: [f64; 4],
+    x1: f64,
+}
+
+impl Hyperlog {
+    fn new(t: f64, w: f64, m: f64, a: f64) -> Result<Self, FcsError> {
+        check(w > 0.0, "hyperlog W must be positive")?;
+        check_logicle_parameters(t, w, m, a)?;
+
+        let width = w / (m + a);
+        let x2 = a / (m + a);
+        let x1 = x2 + width;
+        let x0 = x2 + 2.0 * width;
+        let b = (m + a) * std::f64::consts::LN_10;
+
+        let c_a = (b * x0).exp() / width;
+        let f_a = (b * x1).exp() + c_a * x1;
+        let scale = t / (b.exp() + c_a - f_a);
+
+        Ok(Hyperlog {
+            t, w, m, a,
+            coefficients: [scale, b, c_a * scale, f_a * scale],
+            x1,
+        })
+    }
+
+    /// Returns the inverse function and its derivative at `y`.
+    fn exponential_linear(&self, y: f64) -> (f64, f64) {
+        let [a, b, c, f] = self.coefficients;
+        let exponential = a * (b * y).exp();
+        (exponential + c * y - f, b * exponential + c)
+    }
+
+    fn forward(&self, x: f64) -> f64 {
+        symmetric_forward(x, self.x1, |y| self.exponential_linear(y))
+    }
+
+    fn inverse(&self, y: f64) -> f64 {
+        symmetric_inverse(y, self.x1, |y| self.exponential_linear(y).0)
+    }
+}
+
+/// The biexponential transform with the settings used by FlowJo.
+///
+/// The settings are converted to a Logicle transform with `T = max_value`,
+/// `M = positive_decades`, `A = negative_decades` and `W = log10(|width_basis|) / 2`, so the
+/// default width basis of `-10` gives a linearization width of half a decade.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Biex {
+    pub max_value: f64,
+    pub positive_decades: f64,
+    pub negative_decades: f64,
+    pub width_basis: f64,
+    logicle: Logicle,
+}
+
+impl Biex {
+    fn new(max_value: f64, positive_decades: f64, negative_decades: f64, width_basis: f64) -> Result<Self, FcsError> {
+        check(width_basis.abs() >= 1.0, "biex width basis must be at least 1 in magnitude")?;
+        let w = width_basis.abs().log10() / 2.0;
+
+        Ok(Biex {
+            max_value,
+            positive_decades,
+            negative_decades,
+            width_basis,
+            logicle: Logicle::new(max_value, w, positive_decades, negative_decades)?,
+        })
+    }
+}
+
+/// Returns an `FcsError::InvalidTransform` with `message` unless `condition` holds.
+fn check(condition: bool, message: &str) -> Result<(), FcsError> {
+    if condition {
+        Ok(())
+    } else {
+        Err(FcsError::InvalidTransform(message.to_string()))
+    }
+}
+
+fn check_logicle_parameters(t: f64, w: f64, m: f64, a: f64) -> Result<(), FcsError> {
+    check([t, w, m, a].iter().all(|p| p.is_finite()), "T, W, M and A must be finite")?;
+    check(t > 0.0, "T must be positive")?;
+    check(m > 0.0, "M must be positive")?;
+    check(w >= 0.0 && 2.0 * w <= m, "W must lie between 0 and M / 2")?;
+    check(-w <= a && a <= m - 2.0 * w, "A must lie between -W and M - 2W")
+}
+
+/// Solves `2 (ln d - ln b) + w (b + d) = 0` for `d` in `(0, b]` by bisection.
+fn solve_logicle_d(b: f64, w: f64) -> f64 {
+    if w == 0.0 {
+        return b;
+    }
+
+    let (mut lo, mut hi) = (0.0, b);
+    for _ in 0..200 {
+        let d = (lo + hi) / 2.0;
+        if 2.0 * (d.ln() - b.ln()) + w * (b + d) < 0.0 {
+            lo = d;
+        } else {
+            hi = d;
+        }
+        if hi - lo <= f64::EPSILON * b {
+            break;
+        }
+    }
+    (lo + hi) / 2.0
+}
+
+/// Inverts an increasing function `g` with `g(x1) = 0` that is point symmetric around `(x1, 0)`.
+///
+/// `g` returns its value and derivative. For `x >= 0` the solution lies at or above `x1` and
+/// is found by Newton's method, safeguarded by bisection.
+fn symmetric_forward(x: f64, x1: f64, g: impl Fn(f64) -> (f64, f64)) -> f64 {
+    if x.is_nan() || x.is_infinite() {
+        return x;
+    }
+    if x < 0.0 {
+        return 2.0 * x1 - symmetric_forward(-x, x1, g);
+    }
+
+    let (mut lo, mut hi) = (x1, x1 + 1.0);
+    while g(hi).0 < x {
+        lo = hi;
+        hi = x1 + 2.0 * (hi - x1);
+    }
+
+    let mut y = (lo + hi) / 2.0;
+    for _ in 0..100 {
+        let (value, slope) = g(y);
+        if value == x {
+            break;
+        }
+        if value < x {
+            lo = y;
+        } else {
+            hi = y;
+        }
+
+        let next = y - (value - x) / slope;
+        let next = if next > lo && next < hi { next } else { (lo + hi) / 2.0 };
+        let converged = (next - y).abs() <= 4.0 * f64::EPSILON * y.abs().max(1.0);
+        y = next;
+        if converged {
+            break;
+        }
+    }
+    y
+}
+
+/// Evaluates a function `g` that is point symmetric around `(x1, 0)` from its values at or
+/// above `x1`.
+fn symmetric_inverse(y: f64, x1: f64, g: impl Fn(f64) -> f64) -> f64 {
+    if y < x1 {
+        -g(2.0 * x1 - y)
+    } else {
+        g(y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_round_trip(transform: &Transform, values: &[f64]) {
+        for &x in values {
+            let y = transform.forward(x);
+            let back = transform.inverse(y);
+            assert!(
+                (back - x).abs() <= 1e-9 * x.abs().max(1.0),
+                "{} maps {} to {} and back to {}", transform, x, y, back
+            );
+        }
+    }
+
+    fn assert_increasing(transform: &Transform, values: &[f64]) {
+        for pair in values.windows(2) {
+            assert!(
+                transform.forward(pair[0]) < transform.forward(pair[1]),
+                "{} is not increasing between {} and {}", transform, pair[0], pair[1]
+            );
+        }
+    }
+
+    const VALUES: [f64; 13] = [
+        -10000.0, -1000.0, -100.0, -10.0, -1.0, 0.0, 0.5, 1.0, 10.0, 100.0, 1000.0, 10000.0, 262144.0,
+    ];
+
+    #[test]
+    fn test_arcsinh() {
+        let arcsinh = Transform::arcsinh(5.0).unwrap();
+        assert_eq!(arcsinh.forward(0.0), 0.0);
+        assert_eq!(arcsinh.forward(5.0), 1f64.asinh());
+        assert_eq!(arcsinh.forward(-5.0), -arcsinh.forward(5.0));
+        assert_round_trip(&arcsinh, &VALUES);
+        assert_increasing(&arcsinh, &VALUES);
+    }
+
+    #[test]
+    fn test_log() {
+        let log = Transform::log(10000.0, 4.0).unwrap();
+        assert_eq!(log.forward(10000.0), 1.0);
+        assert_eq!(log.forward(1.0), 0.0);
+        assert_eq!(log.forward(100.0), 0.5);
+        assert!(log.forward(0.0).is_nan());
+        assert!(log.forward(-1.0).is_nan());
+        assert_round_trip(&log, &[0.01, 1.0, 100.0, 262144.0]);
+    }
+
+    #[test]
+    fn test_logicle() {
+        let logicle = Transform::logicle(262144.0, 0.5, 4.5, 0.0).unwrap();
+        assert!((logicle.forward(262144.0) - 1.0).abs() < 1e-12);
+        assert!(logicle.forward(0.0) > 0.0);
+        assert_round_trip(&logicle, &VALUES);
+        assert_increasing(&logicle, &VALUES);
+
+        // Large values are on a log scale: one decade spans 1 / (M + A) of the display
+        let decade = logicle.forward(100000.0) - logicle.forward(10000.0);
+        assert!((decade - 1.0 / 4.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_logicle_zero_and_symmetry() {
+        // Zero maps to W / (M + A) and negative values mirror positive values around it
+        let logicle = Transform::logicle(1000.0, 1.0, 4.0, 0.0).unwrap();
+        let zero = logicle.forward(0.0);
+        assert!((zero - 0.25).abs() < 1e-12);
+        for x in [0.3, 1.0, 10.0, 100.0, 1000.0] {
+            assert!((logicle.forward(-x) - (2.0 * zero - logicle.forward(x))).abs() < 1e-12);
+        }
+
+        let logicle = Transform::logicle(262144.0, 0.5, 4.5, 1.0).unwrap();
+        assert!((logicle.forward(0.0) - 1.5 / 5.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_logicle_without_linearization() {
+        let logicle = Transform::logicle(10000.0, 0.0, 4.0, 0.0).unwrap();
+        assert!((logicle.forward(100.0) - 0.5).abs() < 1e-4);
+        assert_round_trip(&logicle, &[1.0, 100.0, 10000.0]);
+    }
+
+    #[test]
+    fn test_hyperlog() {
+        let hyperlog = Transform::hyperlog(262144.0, 0.5, 4.5, 0.0).unwrap();
+        assert!((hyperlog.forward(262144.0) - 1.0).abs() < 1e-12);
+        assert_round_trip(&hyperlog, &VALUES);
+        assert_increasing(&hyperlog, &VALUES);
+    }
+
+    #[test]
+    fn test_biex() {
+        let biex = Transform::biex(262144.0, 4.5, 0.0, -10.0).unwrap();
+        let logicle = Transform::logicle(262144.0, 0.5, 4.5, 0.0).unwrap();
+        for x in VALUES {
+            assert_eq!(biex.forward(x), logicle.forward(x));
+        }
+        assert_round_trip(&biex, &VALUES);
+    }
+
+    #[test]
+    fn test_invalid_parameters() {
+        for result in [
+            Transform::arcsinh(0.0),
+            Transform::arcsinh(f64::NAN),
+            Transform::log(100.0, -1.0),
+            Transform::logicle(0.0, 0.5, 4.5, 0.0),
+            Transform::logicle(262144.0, 3.0, 4.5, 0.0),
+            Transform::logicle(262144.0, 0.5, 4.5, -1.0),
+            Transform::logicle(262144.0, 0.5, 4.5, 4.0),
+            Transform::hyperlog(262144.0, 0.0, 4.5, 0.0),
+            Transform::biex(262144.0, 4.5, 0.0, 0.5),
+        ] {
+            assert!(matches!(result, Err(FcsError::InvalidTransform(_))), "Unexpected result {:?}", result);
+        }
+    }
+
+    #[test]
+    fn test_text_form() {
+        for transform in [
+            Transform::arcsinh(150.0).unwrap(),
+            Transform::log(262144.0, 4.5).unwrap(),
+            Transform::logicle(262144.0, 0.5, 4.5, 0.25).unwrap(),
+            Transform::hyperlog(10000.0, 1.0, 4.0, 0.0).unwrap(),
+            Transform::biex(262144.0, 4.42, 0.0, -100.0).unwrap(),
+        ] {
+            assert_eq!(transform.to_string().parse::<Transform>().unwrap(), transform);
+        }
+
+        assert_eq!(
+            Transform::logicle(262144.0, 0.5, 4.5, 0.0).unwrap().to_string(),
+            "logicle(T=262144, W=0.5, M=4.5, A=0)"
+        );
+        assert_eq!(
+            " Logicle(a=0, m=4.5, w=0.5, t=262144) ".parse::<Transform>().unwrap(),
+            Transform::logicle(262144.0, 0.5, 4.5, 0.0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_text_form_invalid() {
+        for text in ["", "logicle", "logicle(T=1, W=0, M=4)", "logicle(T=1, W=0, M=4, A=0, X=1)", "unknown(x=1)", "log(top=a, decades=1)", "arcsinh(cofactor=-1)"] {
+            assert!(matches!(text.parse::<Transform>(), Err(FcsError::InvalidTransform(_))), "Parsed {:?}", text);
+        }
+    }
+}
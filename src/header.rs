@@ -3,7 +3,7 @@ use std::{
     num::ParseIntError,
     ops::RangeInclusive,
 };
-use crate::{FcsError, VALID_FCS_VERSIONS, Seek};
+use crate::{FcsError, HashMap, Segment, VALID_FCS_VERSIONS, Seek};
 use std::io::BufReader;
 use std::str;
 use std::io::Read;
@@ -39,7 +39,8 @@ pub struct Header {
 /// # Errors
 ///
 /// This function will return a `ParseIntError` if any of the required numeric fields in the header
-/// string cannot be parsed.
+/// string cannot be parsed, or if the string is too short or a field splits a multi-byte
+/// character.
 ///
 /// # Examples
 ///
@@ -66,16 +67,16 @@ impl TryFrom<&str> for Header {
     type Error = ParseIntError;
 
     fn try_from(header: &str) -> Result<Self, Self::Error> {
-        let version = header[0..=5].to_string();
-        let text_start = header[10..=17].trim_start().parse::<usize>()?;
-        let text_end = header[18..=25].trim_start().parse::<usize>()?;
-        let data_start = header[26..=33].trim_start().parse::<usize>()?;
-        let data_end = header[34..=41].trim_start().parse::<usize>()?;
-        let analysis_start = header[42..=49]
+        let version = field(header, 0..=5)?.to_string();
+        let text_start = field(header, 10..=17)?.trim_start().parse::<usize>()?;
+        let text_end = field(header, 18..=25)?.trim_start().parse::<usize>()?;
+        let data_start = field(header, 26..=33)?.trim_start().parse::<usize>()?;
+        let data_end = field(header, 34..=41)?.trim_start().parse::<usize>()?;
+        let analysis_start = field(header, 42..=49)?
             .trim_start()
             .parse::<usize>()
             .or_else(zero_when_empty)?;
-        let analysis_end = header[50..=57]
+        let analysis_end = field(header, 50..=57)?
             .trim_start()
             .parse::<usize>()
             .or_else(zero_when_empty)?;
@@ -105,6 +106,17 @@ impl Display for Header {
     }
 }
 
+/// Returns the bytes `range` of the header string.
+///
+/// Fails like an unparsable number if the string is too short or the range does not lie on
+/// character boundaries, so that garbage input is an error rather than a panic.
+fn field(header: &str, range: RangeInclusive<usize>) -> Result<&str, ParseIntError> {
+    match header.get(range) {
+        Some(field) => Ok(field),
+        None => Err("-".parse::<usize>().unwrap_err()),
+    }
+}
+
 /// A helper function to return zero when the parsing of an empty string fails.
 ///
 /// This function is used to handle optional analysis segment offsets in the FCS header.
@@ -142,7 +154,7 @@ fn zero_when_empty(err: ParseIntError) -> Result<usize, ParseIntError> {
 ///
 /// This function will return an FcsError if:
 /// - There is an I/O error during reading.
-/// - The header cannot be parsed as a UTF-8 string.
+/// - The header is not ASCII text.
 /// - The header cannot be converted to a Header struct.
 /// - The FCS version in the header is not supported.
 ///
@@ -163,6 +175,9 @@ fn zero_when_empty(err: ParseIntError) -> Result<usize, ParseIntError> {
 pub fn read_header<R: Read + Seek>(reader: &mut BufReader<R>) -> Result<Header, FcsError> {
     let mut buffer = [0u8; 58];
     reader.read_exact(&mut buffer).map_err(FcsError::IoError)?;
+    if !buffer.is_ascii() {
+        return Err(FcsError::InvalidHeader);
+    }
     let header_line = str::from_utf8(&buffer).map_err(|_| FcsError::InvalidHeader)?;
 
     let header = Header::try_from(header_line).map_err(|_| FcsError::InvalidHeader)?;
@@ -175,6 +190,143 @@ pub fn read_header<R: Read + Seek>(reader: &mut BufReader<R>) -> Result<Header,
     Ok(header)
 }
 
+/// Reconciles the segment offsets of the header with those in TEXT and checks them against the
+/// length of the file.
+///
+/// The header has 8 byte offset fields, so files larger than 99,999,999 bytes write zeros there
+/// and store the real DATA and ANALYSIS offsets in `$BEGINDATA`/`$ENDDATA` and
+/// `$BEGINANALYSIS`/`$ENDANALYSIS`. For each of these segments:
+/// - Non-zero header offsets and non-zero TEXT offsets must agree.
+/// - If only one of them is non-zero, it is used. FCS 2.0 files only have the header offsets.
+/// - If both are zero the segment is absent and its offsets are `0..=0`.
+///
+/// Every segment that is present must end after it starts, start after the header and end
+/// before the end of the file.
+///
+/// # Arguments
+///
+/// * `header` - The header of the FCS file.
+/// * `metadata` - A reference to a HashMap containing metadata from the FCS file.
+/// * `file_len` - The length of the file in bytes, counted from the start of the header.
+///
+/// # Returns
+///
+/// A Result containing a `Header` with the resolved offsets, or an FcsError.
+///
+/// # Errors
+///
+/// This function will return an FcsError if:
+/// - An offset keyword is not a number (`FcsError::InvalidKeyword`).
+/// - The header and TEXT give a segment different offsets (`FcsError::OffsetMismatch`).
+/// - A segment is reversed, overlaps the header or extends past the end of the file
+///   (`FcsError::InvalidOffsets`).
+///
+/// # Examples
+///
+/// ```
+/// use std::collections::HashMap;
+/// use fcs_rs::header::{resolve_offsets, Header};
+///
+/// // The DATA segment of a large file is only described by TEXT
+/// let header = Header::try_from("FCS3.1          58    1000       0       0       0       0").unwrap();
+/// let metadata = HashMap::from([
+///     ("$BEGINDATA".to_string(), "150000000".to_string()),
+///     ("$ENDDATA".to_string(), "150000799".to_string()),
+/// ]);
+///
+/// let resolved = resolve_offsets(&header, &metadata, 150_000_808).unwrap();
+/// assert_eq!(resolved.data_offsets, 150_000_000..=150_000_799);
+/// assert!(resolve_offsets(&header, &metadata, 150_000_000).is_err());
+/// ```
+pub fn resolve_offsets(header: &Header, metadata: &HashMap<String, String>, file_len: u64) -> Result<Header, FcsError> {
+    check_offsets(Segment::Text, &header.text_offsets, file_len)?;
+
+    let data_offsets = reconcile_offsets(
+        Segment::Data,
+        &header.data_offsets,
+        offset_keywords(metadata, "$BEGINDATA", "$ENDDATA")?,
+    )?;
+    let analysis_offsets = reconcile_offsets(
+        Segment::Analysis,
+        &header.analysis_offsets,
+        offset_keywords(metadata, "$BEGINANALYSIS", "$ENDANALYSIS")?,
+    )?;
+
+    for (segment, offsets) in [(Segment::Data, &data_offsets), (Segment::Analysis, &analysis_offsets)] {
+        if *offsets.end() != 0 {
+            check_offsets(segment, offsets, file_len)?;
+        }
+    }
+
+    Ok(Header {
+        version: header.version.clone(),
+        text_offsets: header.text_offsets.clone(),
+        data_offsets,
+        analysis_offsets,
+    })
+}
+
+/// Checks that a segment ends after it starts, starts after the header and ends before the
+/// end of the file.
+pub(crate) fn check_offsets(segment: Segment, offsets: &RangeInclusive<usize>, file_len: u64) -> Result<(), FcsError> {
+    let reason = if offsets.end() < offsets.start() {
+        Some("the segment ends before it starts".to_string())
+    } else if *offsets.start() < 58 {
+        Some("the segment overlaps the HEADER".to_string())
+    } else if *offsets.end() as u64 >= file_len {
+        Some(format!("the segment ends past the end of the file at byte {}", file_len))
+    } else {
+        None
+    };
+
+    match reason {
+        Some(reason) => Err(FcsError::InvalidOffsets { segment, offsets: offsets.clone(), reason }),
+        None => Ok(()),
+    }
+}
+
+/// Returns the segment offsets to use given the header offsets and the TEXT offsets, if any.
+fn reconcile_offsets(
+    segment: Segment,
+    header: &RangeInclusive<usize>,
+    text: Option<RangeInclusive<usize>>,
+) -> Result<RangeInclusive<usize>, FcsError> {
+    let header_set = *header.start() != 0 || *header.end() != 0;
+
+    match text {
+        Some(text) if *text.end() != 0 => {
+            if header_set && text != *header {
+                return Err(FcsError::OffsetMismatch { segment, header: header.clone(), text });
+            }
+            Ok(text)
+        },
+        _ if header_set => Ok(header.clone()),
+        _ => Ok(0..=0),
+    }
+}
+
+/// Parses a pair of offset keywords such as `$BEGINDATA` and `$ENDDATA`, which may be padded
+/// with spaces. Returns `None` if either is absent.
+fn offset_keywords(
+    metadata: &HashMap<String, String>,
+    begin: &str,
+    end: &str,
+) -> Result<Option<RangeInclusive<usize>>, FcsError> {
+    let parse = |keyword: &str| -> Result<Option<usize>, FcsError> {
+        metadata.get(keyword)
+            .map(|value| value.trim().parse::<usize>().map_err(|_| FcsError::InvalidKeyword {
+                keyword: keyword.to_string(),
+                value: value.clone(),
+            }))
+            .transpose()
+    };
+
+    Ok(match (parse(begin)?, parse(end)?) {
+        (Some(begin), Some(end)) => Some(begin..=end),
+        _ => None,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -235,6 +387,17 @@ mod tests {
         assert_eq!(parsed_header, Header::try_from(header).unwrap());
     }
 
+    #[test]
+    fn header_garbage() {
+        // A multi-byte character across the end of the version field
+        let header = "FCS3.\u{e9}        256    1545    1792  202456       0       0";
+        assert!(Header::try_from(header).is_err());
+        assert!(Header::try_from("FCS3.0         256").is_err());
+
+        let mut reader = BufReader::new(std::io::Cursor::new(header.as_bytes()));
+        assert!(matches!(read_header(&mut reader), Err(FcsError::InvalidHeader)));
+    }
+
     #[test]
     fn write_header1() {
         let header = Header {
@@ -282,4 +445,82 @@ mod tests {
             formatted
         );
     }
+
+    fn offset_keywords(keywords: &[(&str, &str)]) -> HashMap<String, String> {
+        keywords.iter().map(|(keyword, value)| (keyword.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn resolve_large_offsets_from_text() {
+        let header = Header::try_from("FCS3.1          58    2000       0       0       0       0").unwrap();
+        let metadata = offset_keywords(&[
+            ("$BEGINDATA", "150000000"),
+            ("$ENDDATA", "  250000799"),
+            ("$BEGINANALYSIS", "250000800"),
+            ("$ENDANALYSIS", "250001023"),
+        ]);
+
+        let resolved = resolve_offsets(&header, &metadata, 250_001_024).unwrap();
+        assert_eq!(resolved.text_offsets, 58..=2000);
+        assert_eq!(resolved.data_offsets, 150_000_000..=250_000_799);
+        assert_eq!(resolved.analysis_offsets, 250_000_800..=250_001_023);
+    }
+
+    #[test]
+    fn resolve_offsets_from_header() {
+        // FCS 2.0 files have no offset keywords and zero TEXT offsets mean "see the header"
+        let header = Header::try_from("FCS2.0          58    2000    2001    2400       0       0").unwrap();
+        let resolved = resolve_offsets(&header, &HashMap::new(), 2401).unwrap();
+        assert_eq!(resolved.data_offsets, 2001..=2400);
+        assert_eq!(resolved.analysis_offsets, 0..=0);
+
+        let metadata = offset_keywords(&[("$BEGINDATA", "0"), ("$ENDDATA", "0")]);
+        let resolved = resolve_offsets(&header, &metadata, 2401).unwrap();
+        assert_eq!(resolved.data_offsets, 2001..=2400);
+    }
+
+    #[test]
+    fn resolve_offsets_mismatch() {
+        let header = Header::try_from("FCS3.1          58    2000    2001    2400       0       0").unwrap();
+        let metadata = offset_keywords(&[("$BEGINDATA", "150002001"), ("$ENDDATA", "150002400")]);
+
+        let err = resolve_offsets(&header, &metadata, 150_002_401).unwrap_err();
+        assert!(matches!(
+            err,
+            FcsError::OffsetMismatch { segment: Segment::Data, ref header, ref text }
+                if *header == (2001..=2400) && *text == (150_002_001..=150_002_400)
+        ), "Unexpected error {:?}", err);
+    }
+
+    #[test]
+    fn resolve_offsets_out_of_bounds() {
+        let header = Header::try_from("FCS3.1          58    2000       0       0       0       0").unwrap();
+        let invalid = |keywords: &[(&str, &str)], file_len: u64| {
+            match resolve_offsets(&header, &offset_keywords(keywords), file_len) {
+                Err(FcsError::InvalidOffsets { segment, .. }) => segment,
+                other => panic!("Unexpected result {:?}", other),
+            }
+        };
+
+        // Past the end of the file
+        let data = [("$BEGINDATA", "150000000"), ("$ENDDATA", "150000799")];
+        assert_eq!(invalid(&data, 150_000_799), Segment::Data);
+        // Reversed
+        let data = [("$BEGINDATA", "150000799"), ("$ENDDATA", "150000000")];
+        assert_eq!(invalid(&data, 200_000_000), Segment::Data);
+        // Overlapping the header
+        let analysis = [("$BEGINANALYSIS", "10"), ("$ENDANALYSIS", "150000000")];
+        assert_eq!(invalid(&analysis, 200_000_000), Segment::Analysis);
+        // The TEXT segment itself
+        assert_eq!(invalid(&[], 1000), Segment::Text);
+    }
+
+    #[test]
+    fn resolve_offsets_invalid_keyword() {
+        let header = Header::try_from("FCS3.1          58    2000       0       0       0       0").unwrap();
+        let metadata = offset_keywords(&[("$BEGINDATA", "1.5e8"), ("$ENDDATA", "150000799")]);
+
+        let err = resolve_offsets(&header, &metadata, 200_000_000).unwrap_err();
+        assert!(matches!(err, FcsError::InvalidKeyword { ref keyword, .. } if keyword == "$BEGINDATA"));
+    }
 }
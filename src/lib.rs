@@ -8,10 +8,12 @@
 //!
 //! The Flow Cytometry Standard (FCS) is a file format used to store data generated by flow cytometry experiments. This library allows users to easily read and manipulate FCS files in Rust. Key features include:
 //!
-//! - **File Handling**: Open and read FCS files in a structured and efficient manner.
-//! - **Metadata Extraction**: Extract and validate metadata from FCS files, ensuring all required information is available.
+//! - **File Handling**: Open and read FCS files in a structured and efficient manner, memory-mapping large files.
+//! - **Metadata Extraction**: Extract and validate metadata from the TEXT, supplemental TEXT and ANALYSIS segments, ensuring all required information is available.
 //! - **Data Processing**: Parse data segments from FCS files and convert them into usable formats such as dataframes.
-//! - **Data Transformation**: Can transform raw data using arcsinh.
+//! - **Data Transformation**: Can transform data using arcsinh, log, Logicle, hyperlog and biex transforms.
+//! - **Compensation**: Compensate spillover using the matrix stored in the file or a custom one.
+//! - **File Writing**: Write samples back to disk as FCS 3.1 files.
 //! - **Error Handling**: Comprehensive error handling to deal with various issues that may arise during file operations.
 //!
 //! # Getting Started
@@ -23,6 +25,13 @@
 //! fcs_rs = "0.1.0"
 //! ```
 //!
+//! Enable the `parallel` feature to decode the DATA segment on multiple threads with rayon:
+//!
+//! ```toml
+//! [dependencies]
+//! fcs_rs = { version = "0.1.0", features = ["parallel"] }
+//! ```
+//!
 //! Then, include the library in your project:
 //!
 //! ```rust
@@ -37,37 +46,101 @@
 //!
 //! ## Opening an FCS File
 //!
-//! ```rust
+//! ```rust,no_run
 //! use fcs_rs::FcsFile;
 //!
 //! let fcs_file = FcsFile::open("path/to/file.fcs")?;
+//! # Ok::<(), fcs_rs::FcsError>(())
 //! ```
 //!
 //! ## Reading an FCS File
 //!
-//! ```rust
+//! ```rust,no_run
 //! use fcs_rs::{FcsFile, FcsError};
 //!
 //! let fcs_file = FcsFile::open("path/to/file.fcs")?;
 //! let flow_sample = fcs_file.read()?;
 //! println!("{:?}", flow_sample.data);
-//! println!("{:?}", flow_sample.parameters);
+//! println!("{:?}", flow_sample.parameters());
+//! # Ok::<(), FcsError>(())
+//! ```
+//!
+//! ## Reading an FCS File From Memory
+//!
+//! ```rust,no_run
+//! use fcs_rs::FcsFile;
+//!
+//! let bytes: Vec<u8> = std::fs::read("path/to/file.fcs")?;
+//! let flow_sample = FcsFile::from_bytes(&bytes).read()?;
+//! println!("{:?}", flow_sample.data);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! ## Reading a Large FCS File
+//!
+//! ```rust,no_run
+//! use fcs_rs::FcsFile;
+//!
+//! // The DATA segment is decoded straight from the memory-mapped file
+//! let fcs_file = FcsFile::open_mapped("path/to/large_file.fcs")?;
+//! let flow_sample = fcs_file.read()?;
+//! println!("{:?}", flow_sample.data);
+//! # Ok::<(), fcs_rs::FcsError>(())
+//! ```
+//!
+//! ## Reading Selected Channels and Events
+//!
+//! ```rust,no_run
+//! use fcs_rs::{FcsFile, ReadOptions};
+//!
+//! let fcs_file = FcsFile::open("path/to/file.fcs")?;
+//! let options = ReadOptions::new().channels(["FSC-A", "SSC-A", "FL1-A"]).limit(10_000);
+//! let preview = fcs_file.read_with(&options)?;
+//! println!("{:?}", preview.data);
+//! # Ok::<(), fcs_rs::FcsError>(())
+//! ```
+//!
+//! ## Reading Only the Metadata of an FCS File
+//!
+//! ```rust,no_run
+//! use fcs_rs::{FcsFile, ParseMode};
+//!
+//! let fcs_file = FcsFile::open("path/to/file.fcs")?.with_parse_mode(ParseMode::Lenient);
+//! let metadata = fcs_file.read_metadata_only()?;
+//! println!("{:?} {:?} {:?}", metadata.file_name, metadata.date, metadata.problems);
+//! # Ok::<(), fcs_rs::FcsError>(())
+//! ```
+//!
+//! ## Scanning an FCS File in Chunks
+//!
+//! ```rust,no_run
+//! use fcs_rs::FcsFile;
+//!
+//! let fcs_file = FcsFile::open("path/to/large_file.fcs")?;
+//! let mut bright_events = 0;
+//! for chunk in fcs_file.chunks(100_000)? {
+//!     let fsc = chunk?.column("FSC-A").unwrap().f64().unwrap().clone();
+//!     bright_events += fsc.into_no_null_iter().filter(|&value| value > 100_000.0).count();
+//! }
+//! println!("{}", bright_events);
+//! # Ok::<(), fcs_rs::FcsError>(())
 //! ```
 //!
 //! ## Extracting Column Names
 //!
-//! ```rust
+//! ```rust,no_run
 //! use fcs_rs::FcsFile;
 //!
 //! let fcs_file = FcsFile::open("path/to/file.fcs")?;
 //! let flow_sample = fcs_file.read()?;
 //! let column_names = flow_sample.get_dataframe_columns();
 //! println!("{:?}", column_names);
+//! # Ok::<(), fcs_rs::FcsError>(())
 //! ```
 //!
 //! ## Applying Arcsinh Transformation
 //!
-//! ```rust
+//! ```rust,no_run
 //! use fcs_rs::FcsFile;
 //!
 //! let fcs_file = FcsFile::open("path/to/file.fcs")?;
@@ -75,6 +148,47 @@
 //! let column_names = flow_sample.get_dataframe_columns();
 //! flow_sample.arcsinh_transform(5.0, &column_names)?;
 //! println!("{:?}", flow_sample.data);
+//! # Ok::<(), fcs_rs::FcsError>(())
+//! ```
+//!
+//! ## Applying a Logicle Transformation
+//!
+//! ```rust,no_run
+//! use fcs_rs::FcsFile;
+//! use fcs_rs::data::Transform;
+//!
+//! let fcs_file = FcsFile::open("path/to/file.fcs")?;
+//! let mut flow_sample = fcs_file.read()?;
+//! let logicle = Transform::logicle(262144.0, 0.5, 4.5, 0.0)?;
+//! flow_sample.apply_transform(logicle, &["FL1-A".to_string()])?;
+//! for (channel, transform) in &flow_sample.transforms {
+//!     println!("{}: {}", channel, transform);
+//! }
+//! # Ok::<(), fcs_rs::FcsError>(())
+//! ```
+//!
+//! ## Compensating a Sample
+//!
+//! ```rust,no_run
+//! use fcs_rs::FcsFile;
+//!
+//! let fcs_file = FcsFile::open("path/to/file.fcs")?;
+//! let mut flow_sample = fcs_file.read()?;
+//! flow_sample.compensate()?;
+//! println!("{:?}", flow_sample.data);
+//! # Ok::<(), fcs_rs::FcsError>(())
+//! ```
+//!
+//! ## Writing an FCS File
+//!
+//! ```rust,no_run
+//! use fcs_rs::FcsFile;
+//! use fcs_rs::writer::{write_fcs_file, WriteOptions};
+//!
+//! let fcs_file = FcsFile::open("path/to/file.fcs")?;
+//! let flow_sample = fcs_file.read()?;
+//! write_fcs_file("path/to/copy.fcs", &flow_sample, &WriteOptions::default())?;
+//! # Ok::<(), fcs_rs::FcsError>(())
 //! ```
 //!
 //! ## Creating a DataFrame
@@ -87,6 +201,7 @@
 //! let data = vec![vec![1.0, 2.0, 3.0], vec![4.0, 5.0, 6.0]];
 //! let df = create_dataframe(&column_titles, &data)?;
 //! println!("{:?}", df);
+//! # Ok::<(), PolarsError>(())
 //! ```
 //!
 //! # Modules
@@ -94,36 +209,74 @@
 //! - **data**: Contains structures and functions for handling the data segments of FCS files, including parsing and transformation operations.
 //! - **header**: Includes methods for reading and validating the header segments of FCS files.
 //! - **text**: Provides functions for reading and validating the text segments of FCS files.
+//! - **metadata**: Provides the typed metadata of a dataset, with the standard keywords parsed.
+//! - **writer**: Provides functions for writing samples as FCS files.
+//! - **compensation**: Provides spillover matrices and the compensation of samples.
+//! - **crc**: Computes the CRC-16 checksums that FCS 3.1 stores after each dataset.
 //!
 //! # Constants
 //!
 //! - **VALID_FCS_VERSIONS**: A list of supported FCS versions.
-//! - **REQUIRED_KEYWORDS**: A list of required non-parameter indexed keywords for the FCS text segment.
+//! - **REQUIRED_KEYWORDS**: A list of required keywords for the FCS 3.0 and FCS 3.1 text segment.
+//! - **FCS2_0_RULES**, **FCS3_0_RULES**, **FCS3_2_RULES**: The required keywords of each FCS version,
+//!   looked up by version with `keyword_rules`.
 //!
 //! # Error Handling
 //!
 //! The `FcsError` enum defines various errors that can occur while processing FCS files, including I/O errors, 
 //! invalid headers, unsupported versions, metadata issues, missing required keywords, and invalid data segments.
+//! Errors returned by `FcsFile::read` are wrapped in `FcsError::SegmentError`, which names the segment that
+//! failed, its byte offset, and the file path.
 
+use memmap2::Mmap;
+use polars::prelude::DataFrame;
+use std::cell::RefCell;
 use std::collections::HashMap;
-use std::io::{self, Read, Seek};
+use std::io::{self, BufRead, Cursor, Read, Seek};
 use std::io::{BufReader, SeekFrom};
+use std::fmt;
 use std::fs::File;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
 use std::str;
-use byteorder::ReadBytesExt;
 use thiserror::Error;
 
+use crate::header::{check_offsets, resolve_offsets, Header};
+use crate::text::read_unvalidated_text_segments;
+
 pub use crate::header::read_header;
-pub use crate::text::{read_metadata, validate_text};
-pub use crate::data::{FlowSample, parse_data, read_events, create_dataframe};
+pub use crate::text::{
+    read_metadata, 
+    read_text, 
+    read_text_with_mode, 
+    read_text_segments, 
+    read_analysis, 
+    parse_text, 
+    validate_text, 
+    KeywordConflict, 
+    ParseMode, 
+    TextSegments, 
+    TextSource, 
+};
+pub use crate::data::{FlowSample, ReadOptions, ChannelSelector, parse_data, parse_data_segment, parse_data_bytes, read_events, decode_events, create_dataframe, ValueMode};
+pub use crate::writer::{write_fcs, write_fcs_file, WriteOptions};
+pub use crate::compensation::SpilloverMatrix;
+pub use crate::metadata::{Metadata, Parameter, DisplayScale};
 
+pub mod compensation;
+pub mod crc;
 pub mod data;
 pub mod header;
+pub mod metadata;
 pub mod text;
+pub mod writer;
+
+#[cfg(test)]
+mod test_utils;
 
-pub const VALID_FCS_VERSIONS: [&str; 2] = ["FCS3.0", "FCS3.1"];
+pub const VALID_FCS_VERSIONS: [&str; 4] = ["FCS2.0", "FCS3.0", "FCS3.1", "FCS3.2"];
 
-/// Required non-parameter indexed keywords for FCS text segment
+/// Required keywords for the FCS 3.0 and FCS 3.1 text segment
 pub const REQUIRED_KEYWORDS: [&str; 16] = [
     "$BEGINANALYSIS", // Byte-offset to the beginning of the ANALYSIS segment
     "$BEGINDATA", // Byte-offset to the beginning of the DATA segment
@@ -143,6 +296,67 @@ pub const REQUIRED_KEYWORDS: [&str; 16] = [
     "$PnR" // Range for parameter number n.
 ];
 
+/// The keywords a text segment must contain for one version of the FCS standard.
+///
+/// # Fields
+///
+/// * `required` - Keywords that must be present once.
+/// * `required_parameter` - Parameter keywords that must be present for every parameter,
+///   with `n` standing for the parameter index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeywordRules {
+    pub required: &'static [&'static str],
+    pub required_parameter: &'static [&'static str],
+}
+
+/// Required keywords for FCS 2.0. The data segment offsets are only stored in the header,
+/// and `$TOT` and `$PnE` are optional.
+pub const FCS2_0_RULES: KeywordRules = KeywordRules {
+    required: &["$BYTEORD", "$DATATYPE", "$MODE", "$NEXTDATA", "$PAR"],
+    required_parameter: &["$PnB", "$PnN", "$PnR"],
+};
+
+/// Required keywords for FCS 3.0 and FCS 3.1.
+pub const FCS3_0_RULES: KeywordRules = KeywordRules {
+    required: &[
+        "$BEGINANALYSIS", "$BEGINDATA", "$BEGINSTEXT", "$BYTEORD", "$DATATYPE", "$ENDANALYSIS",
+        "$ENDDATA", "$ENDSTEXT", "$MODE", "$NEXTDATA", "$PAR", "$TOT",
+    ],
+    required_parameter: &["$PnB", "$PnE", "$PnN", "$PnR"],
+};
+
+/// Required keywords for FCS 3.2. The analysis and supplemental text offsets and `$MODE`
+/// became optional, and `$CYT` became required.
+pub const FCS3_2_RULES: KeywordRules = KeywordRules {
+    required: &[
+        "$BEGINDATA", "$BYTEORD", "$CYT", "$DATATYPE", "$ENDDATA", "$NEXTDATA", "$PAR", "$TOT",
+    ],
+    required_parameter: &["$PnB", "$PnE", "$PnN", "$PnR"],
+};
+
+/// Returns the keyword rules for an FCS version string such as `FCS3.1`.
+///
+/// # Errors
+///
+/// Returns `FcsError::InvalidVersion` if the version is not one of `VALID_FCS_VERSIONS`.
+///
+/// # Examples
+///
+/// ```
+/// use fcs_rs::{keyword_rules, FCS3_2_RULES};
+///
+/// assert_eq!(keyword_rules("FCS3.2").unwrap(), FCS3_2_RULES);
+/// assert!(keyword_rules("FCS1.0").is_err());
+/// ```
+pub fn keyword_rules(version: &str) -> Result<KeywordRules, FcsError> {
+    match version {
+        "FCS2.0" => Ok(FCS2_0_RULES),
+        "FCS3.0" | "FCS3.1" => Ok(FCS3_0_RULES),
+        "FCS3.2" => Ok(FCS3_2_RULES),
+        _ => Err(FcsError::InvalidVersion(version.to_string())),
+    }
+}
+
 /// Represents errors that can occur while processing FCS (Flow Cytometry Standard) files.
 ///
 /// This enum covers various error types that might be encountered, including I/O errors,
@@ -155,31 +369,40 @@ pub const REQUIRED_KEYWORDS: [&str; 16] = [
 /// - `InvalidHeader`: Indicates that the FCS header is invalid, possibly due to file corruption
 ///   or the file not being a valid FCS file.
 /// - `InvalidVersion`: Indicates that the FCS version is not supported. The supported versions are
-///   FCS3.0 and FCS3.1.
+///   FCS2.0, FCS3.0, FCS3.1 and FCS3.2.
 /// - `InvalidMetadata`: Indicates that the FCS metadata is invalid.
 /// - `InvalidText`: Indicates that the FCS file is missing a required keyword in its TEXT section.
+/// - `MalformedText`: Indicates that the TEXT section does not follow the keyword/value syntax.
+/// - `InvalidKeyword`: Indicates that a keyword in the TEXT section has a malformed value.
 /// - `InvalidData`: Indicates that the FCS data segment is invalid, with an associated error message.
+/// - `MissingSpillover`: Indicates that a sample has no spillover matrix to compensate with.
+/// - `SingularMatrix`: Indicates that a spillover matrix cannot be inverted.
+/// - `UnmatchedChannel`: Indicates that a channel of a spillover matrix does not match any parameter.
+/// - `InvalidTransform`: Indicates that the parameters of a transform are invalid.
+/// - `UnknownChannel`: Indicates that a sample has no channel with the requested name.
+/// - `InvalidNextData`: Indicates that the `$NEXTDATA` offset of a dataset does not point to a
+///   new dataset within the file.
+/// - `ConflictingKeyword`: Indicates that the primary and supplemental TEXT segments give a
+///   keyword different values.
+/// - `OffsetMismatch`: Indicates that the header and TEXT give a segment different offsets.
+/// - `InvalidOffsets`: Indicates that a segment is reversed, overlaps the header or extends
+///   past the end of the file.
+/// - `CrcMismatch`: Indicates that the CRC stored after a dataset does not match its contents.
+/// - `SegmentError`: Wraps another error with the segment that failed, the byte offset of that
+///   segment, and the path of the file being read.
 ///
 /// # Examples
 ///
 /// ```
-/// use thiserror::Error;
-/// use std::io;
+/// use fcs_rs::{FcsFile, FcsError};
 ///
-/// #[derive(Debug, Error)]
-/// pub enum FcsError {
-///     #[error("IO Error: {0}")]
-///     IoError(#[from] io::Error),
-///     #[error("Invalid FCS Header. File may be corrupted or not a FCS file.")]
-///     InvalidHeader,
-///     #[error("FCS version `{0}` not supported. Must be either FCS3.0 or FCS3.1")]
-///     InvalidVersion(String),
-///     #[error("Invalid FCS Metadata")]
-///     InvalidMetadata,
-///     #[error("FCS file is corrupted. It is missing required keyword {0} in its TEXT section")]
-///     InvalidText(String),
-///     #[error("Invalid FCS Data: {0}")]
-///     InvalidData(String),
+/// let bytes = b"This is not an FCS file, but it is long enough to hold a header.";
+/// match FcsFile::from_bytes(bytes).read() {
+///     Err(FcsError::SegmentError { segment, source, .. }) => {
+///         assert!(matches!(*source, FcsError::InvalidHeader));
+///         println!("Could not read the {} segment: {}", segment, source);
+///     },
+///     other => panic!("Unexpected result {:?}", other),
 /// }
 /// ```
 #[derive(Debug, Error)]
@@ -188,23 +411,105 @@ pub enum FcsError {
     IoError(#[from] io::Error),
     #[error("Invalid FCS Header. File may be corrupted or not a FCS file.")]
     InvalidHeader,
-    #[error("FCS version `{0}` not supported. Must be either FCS3.0 or FCS3.1")]
+    #[error("FCS version `{0}` not supported. Must be FCS2.0, FCS3.0, FCS3.1 or FCS3.2")]
     InvalidVersion(String),
     #[error("Invalid FCS Metadata")]
     InvalidMetadata,
     #[error("FCS file is corrupted. It is missing required keyword {0} in its TEXT section")]
     InvalidText(String),
+    #[error("Malformed FCS TEXT segment: {0}")]
+    MalformedText(String),
+    #[error("Invalid value `{value}` for keyword {keyword}")]
+    InvalidKeyword {
+        keyword: String,
+        value: String,
+    },
     #[error("Invalid FCS Data: {0}")]
     InvalidData(String),
+    #[error("No spillover matrix found in $SPILLOVER, SPILL or $COMP")]
+    MissingSpillover,
+    #[error("Spillover matrix is singular and cannot be inverted")]
+    SingularMatrix,
+    #[error("Spillover channel {0} does not match any parameter")]
+    UnmatchedChannel(String),
+    #[error("Invalid transform: {0}")]
+    InvalidTransform(String),
+    #[error("Unknown channel {0}")]
+    UnknownChannel(String),
+    #[error("Invalid $NEXTDATA offset {offset}: {reason}")]
+    InvalidNextData {
+        offset: u64,
+        reason: String,
+    },
+    #[error("Keyword {keyword} is {primary:?} in the primary TEXT segment but {supplemental:?} in the supplemental TEXT segment")]
+    ConflictingKeyword {
+        keyword: String,
+        primary: String,
+        supplemental: String,
+    },
+    #[error("The HEADER gives the {segment} segment offsets {header:?} but TEXT gives {text:?}")]
+    OffsetMismatch {
+        segment: Segment,
+        header: RangeInclusive<usize>,
+        text: RangeInclusive<usize>,
+    },
+    #[error("Invalid {segment} segment offsets {offsets:?}: {reason}")]
+    InvalidOffsets {
+        segment: Segment,
+        offsets: RangeInclusive<usize>,
+        reason: String,
+    },
+    #[error("CRC mismatch: the file stores {stored} but the dataset has CRC {computed}")]
+    CrcMismatch {
+        stored: String,
+        computed: u16,
+    },
+    #[error("Failed to read {segment} segment of {path} at byte offset {offset}: {source}")]
+    SegmentError {
+        segment: Segment,
+        offset: u64,
+        path: String,
+        source: Box<FcsError>,
+    },
+}
+
+/// The segments of an FCS file, used to report where an error occurred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Segment {
+    Header,
+    Text,
+    SupplementalText,
+    Data,
+    Analysis,
+    Crc,
+}
+
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Segment::Header => "HEADER",
+            Segment::Text => "TEXT",
+            Segment::SupplementalText => "supplemental TEXT",
+            Segment::Data => "DATA",
+            Segment::Analysis => "ANALYSIS",
+            Segment::Crc => "CRC",
+        };
+        write!(f, "{}", name)
+    }
 }
 
 /// An object providing access to an FCS file.
 ///
-/// This struct wraps a file handle and provides methods to open the file and read
-/// metadata and parameter data from it.
+/// This struct wraps a file handle, or any other `Read + Seek` source such as an in-memory
+/// buffer, and provides methods to read metadata and parameter data from it. The source is
+/// always read from its start, so `read` can be called repeatedly.
 #[derive(Debug)]
-pub struct FcsFile {
-    inner: File,
+pub struct FcsFile<R = File> {
+    inner: RefCell<R>,
+    path: Option<PathBuf>,
+    check_crc: bool,
+    parse_mode: ParseMode,
+    map: Option<Mmap>,
 }
 
 impl FcsFile {
@@ -229,7 +534,44 @@ impl FcsFile {
     pub fn open(path: &str) -> Result<FcsFile, FcsError> {
         let file = File::open(path).map_err(FcsError::IoError)?;
 
-        Ok(Self { inner: file })
+        Ok(Self { inner: RefCell::new(file), path: Some(PathBuf::from(path)), check_crc: false, parse_mode: ParseMode::Strict, map: None })
+    }
+
+    /// Open an FCS file and memory-map it, so DATA segments are decoded in place.
+    ///
+    /// Reading a mapped file decodes each DATA segment directly from the mapping into one
+    /// buffer per column, which becomes the column of the DataFrame. This avoids reading the
+    /// segment into an intermediate buffer, which halves the peak memory used to read large
+    /// files. The other segments are read as with `open`.
+    ///
+    /// The file must not be modified or truncated while it is mapped. Doing so changes the
+    /// values read, and truncating it may terminate the process.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A string slice representing the path to the FCS file.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing an `FcsFile` object if the file is successfully opened and
+    /// mapped, or an `FcsError` if there is an issue opening or mapping the file.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fcs_rs::FcsFile;
+    /// 
+    /// let fcs_file = FcsFile::open_mapped("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+    /// let flow_sample = fcs_file.read().unwrap();
+    /// println!("{:?}", flow_sample.data);
+    /// ```
+    pub fn open_mapped(path: &str) -> Result<FcsFile, FcsError> {
+        let file = File::open(path).map_err(FcsError::IoError)?;
+        // SAFETY: the file is opened read-only and callers are told not to modify it while it
+        // is mapped
+        let map = unsafe { Mmap::map(&file) }.map_err(FcsError::IoError)?;
+
+        Ok(Self { inner: RefCell::new(file), path: Some(PathBuf::from(path)), check_crc: false, parse_mode: ParseMode::Strict, map: Some(map) })
     }
 
     /// Create an FcsFile from an existing File object.
@@ -244,7 +586,7 @@ impl FcsFile {
     ///
     /// # Examples
     ///
-    /// ```
+    /// ```no_run
     /// use fcs_rs::FcsFile;
     /// use std::fs::File;
     /// 
@@ -252,7 +594,170 @@ impl FcsFile {
     /// let fcs_file = FcsFile::from_file(file);
     /// ```
     pub fn from_file(file: File) -> Self {
-        Self { inner: file }
+        Self::from_reader(file)
+    }
+}
+
+impl<'a> FcsFile<Cursor<&'a [u8]>> {
+    /// Create an FcsFile that parses an FCS file held in memory.
+    ///
+    /// The bytes are borrowed, not copied, so a downloaded or uploaded file can be parsed
+    /// without writing it to disk first.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The complete contents of an FCS file.
+    ///
+    /// # Returns
+    ///
+    /// An `FcsFile` that reads from the provided bytes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fcs_rs::FcsFile;
+    /// 
+    /// let bytes = std::fs::read("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+    /// let fcs_file = FcsFile::from_bytes(&bytes);
+    /// let flow_sample = fcs_file.read().unwrap();
+    /// println!("{:?}", flow_sample.data);
+    /// ```
+    pub fn from_bytes(bytes: &'a [u8]) -> Self {
+        Self::from_reader(Cursor::new(bytes))
+    }
+}
+
+impl<R: Read + Seek> FcsFile<R> {
+    /// Create an FcsFile from any source that implements `Read` and `Seek`.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The source of the FCS file, positioned anywhere. Reading always starts
+    ///   from its beginning.
+    ///
+    /// # Returns
+    ///
+    /// An `FcsFile` that wraps the provided reader.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fcs_rs::FcsFile;
+    /// use std::io::Cursor;
+    /// 
+    /// let bytes = std::fs::read("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+    /// let fcs_file = FcsFile::from_reader(Cursor::new(bytes));
+    /// let flow_sample = fcs_file.read().unwrap();
+    /// println!("{:?}", flow_sample.parameters());
+    /// ```
+    pub fn from_reader(reader: R) -> Self {
+        Self { inner: RefCell::new(reader), path: None, check_crc: false, parse_mode: ParseMode::Strict, map: None }
+    }
+
+    /// Enables or disables CRC verification when reading datasets.
+    ///
+    /// When enabled, `read`, `read_raw`, `read_all`, `datasets` and `chunks` check the CRC of every
+    /// dataset they read, as `verify_crc` does, and fail with `FcsError::CrcMismatch` if it does
+    /// not match. Datasets that store no CRC are read as usual. Verification is disabled by
+    /// default.
+    ///
+    /// # Arguments
+    ///
+    /// * `check_crc` - Whether to verify the CRC of every dataset that is read.
+    ///
+    /// # Returns
+    ///
+    /// The `FcsFile` with CRC verification enabled or disabled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fcs_rs::FcsFile;
+    /// 
+    /// let fcs_file = FcsFile::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs")
+    ///     .unwrap()
+    ///     .with_crc_check(true);
+    /// let flow_sample = fcs_file.read().unwrap();
+    /// println!("{:?}", flow_sample.data);
+    /// ```
+    pub fn with_crc_check(mut self, check_crc: bool) -> Self {
+        self.check_crc = check_crc;
+        self
+    }
+
+    /// Sets how the TEXT segments are parsed.
+    ///
+    /// The mode is passed to `read_text_segments` by every method that reads TEXT. In
+    /// `ParseMode::Lenient`, `read_metadata_only` also reports the keywords that fail validation
+    /// as problems instead of failing. The default is `ParseMode::Strict`.
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - How the TEXT segments are parsed.
+    ///
+    /// # Returns
+    ///
+    /// The `FcsFile` using the given parse mode.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fcs_rs::{FcsFile, ParseMode};
+    /// 
+    /// let fcs_file = FcsFile::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs")
+    ///     .unwrap()
+    ///     .with_parse_mode(ParseMode::Lenient);
+    /// let metadata = fcs_file.read_metadata_only().unwrap();
+    /// assert!(metadata.problems.is_empty());
+    /// ```
+    pub fn with_parse_mode(mut self, mode: ParseMode) -> Self {
+        self.parse_mode = mode;
+        self
+    }
+
+    /// Verifies the CRC stored after the first dataset of the file.
+    ///
+    /// The CRC covers every byte from the start of the HEADER through the last byte of the
+    /// last segment of the dataset. See the `crc` module for how it is computed. Only the first
+    /// dataset is checked; use `with_crc_check` with `read_all` or `datasets` to check the CRC
+    /// of every dataset that `$NEXTDATA` links to.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing `true` if the stored CRC matches, `false` if the dataset stores no
+    /// CRC, or an `FcsError`. FCS 2.0 datasets never store one. Otherwise there is no CRC if it
+    /// is `00000000`, the file ends after the last segment, or the bytes after it are not
+    /// digits, e.g. padding or the HEADER of the next dataset.
+    ///
+    /// # Errors
+    ///
+    /// Every error is returned as an `FcsError::SegmentError`. It wraps
+    /// `FcsError::CrcMismatch` if the stored CRC does not match, and otherwise the error from
+    /// reading the HEADER or TEXT segment that locate the end of the dataset.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fcs_rs::FcsFile;
+    /// use fcs_rs::writer::{write_fcs, WriteOptions};
+    /// 
+    /// let fcs_file = FcsFile::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+    /// assert!(!fcs_file.verify_crc().unwrap());
+    ///
+    /// // Files written by this crate store a CRC
+    /// let mut bytes = Vec::new();
+    /// write_fcs(&mut bytes, &fcs_file.read().unwrap(), &WriteOptions::default()).unwrap();
+    /// assert!(FcsFile::from_bytes(&bytes).verify_crc().unwrap());
+    /// ```
+    pub fn verify_crc(&self) -> Result<bool, FcsError> {
+        let (version, end) = {
+            let mut inner = self.inner.borrow_mut();
+            let mut reader = BufReader::new(DatasetReader { inner: &mut *inner, base: 0 });
+            let (header, _, end) = self.read_layout(&mut reader, 0)?;
+            (header.version, end)
+        };
+
+        self.crc_matches(&version, 0, end as u64)
     }
 
     /// Read the FCS file and return metadata and parameter data in an `FlowSample` struct.
@@ -262,6 +767,12 @@ impl FcsFile {
     /// A `Result` containing an `FlowSample` struct if the file is successfully read,
     /// or an `FcsError` if there is an issue reading the metadata or parameter data.
     ///
+    /// # Errors
+    ///
+    /// Every error is returned as an `FcsError::SegmentError` that records the segment being
+    /// read, the byte offset where that segment starts, and the file path. The original error
+    /// is kept as its `source`.
+    ///
     /// # Examples
     ///
     /// ```
@@ -277,47 +788,1237 @@ impl FcsFile {
     /// let fcs_file = FcsFile::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
     /// let fcs_data = fcs_file.read().unwrap();
     /// println!("{:?}", fcs_data.data);
-    /// println!("{:?}", fcs_data.parameters);
+    /// println!("{:?}", fcs_data.parameters());
     /// ```
     pub fn read(&self) -> Result<FlowSample, FcsError> {
-        let mut reader = BufReader::new(&self.inner);
-        let metadata = match read_metadata(&mut reader) {
-            Ok(metadata) => metadata,
-            Err(err) => panic!("There was an error: {}", err)
-        };
-    
-        let flow_sample = match parse_data(&mut reader, &metadata) {
-            Ok(sample) => sample,
-            Err(err) => panic!("Error parsing Flow sample: {}", err)
-        };
-
-        Ok(flow_sample)
+        self.read_values(ValueMode::Scaled)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Read the FCS file like `read`, but keep the values exactly as they are stored in the
+    /// data segment.
+    ///
+    /// `read` masks integer values to `$PnR`, decodes `$PnE` log amplification and divides by
+    /// the `$PnG` gain. This method skips those steps, e.g. to inspect the values written by
+    /// the instrument.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing an `FlowSample` struct with raw values, or an `FcsError`.
+    ///
+    /// # Errors
+    ///
+    /// This method returns the same errors as `read`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fcs_rs::FcsFile;
+    /// 
+    /// let fcs_file = FcsFile::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+    /// let raw_sample = fcs_file.read_raw().unwrap();
+    /// println!("{:?}", raw_sample.data);
+    /// ```
+    pub fn read_raw(&self) -> Result<FlowSample, FcsError> {
+        self.read_values(ValueMode::Raw)
+    }
 
-    #[test]
-    fn test_fcs_open() {
-        let result = FcsFile::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs");
-        assert!(result.is_ok(), "FCS file open failed: {:?}", result.err());
+    /// Read selected channels and events of the FCS file.
+    ///
+    /// Channels that are not selected are skipped while decoding, and only the bytes from the
+    /// first to the last selected event are read, so previews and subsets of large files are
+    /// cheap. The DataFrame and `channels` of the sample hold the selected channels in the
+    /// order they were selected, while `metadata` still holds every keyword of the file.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - The channels and events to read, and how values are converted.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing an `FlowSample` struct with the selected data, or an `FcsError`.
+    ///
+    /// # Errors
+    ///
+    /// This method returns the same errors as `read`. A selected channel that does not exist
+    /// is reported as an `FcsError::UnknownChannel` and a step of 0 as an
+    /// `FcsError::InvalidData`, both wrapped in an `FcsError::SegmentError`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fcs_rs::{FcsFile, ReadOptions};
+    /// 
+    /// let fcs_file = FcsFile::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+    /// let options = ReadOptions::new().channels(["FSC-A", "SSC-A", "BL1-A"]).limit(10_000);
+    /// let preview = fcs_file.read_with(&options).unwrap();
+    /// assert_eq!(preview.get_dataframe_columns(), vec!["FSC-A", "SSC-A", "BL1-A"]);
+    /// assert_eq!(preview.data.height(), 8821);
+    /// ```
+    pub fn read_with(&self, options: &ReadOptions) -> Result<FlowSample, FcsError> {
+        self.read_dataset(0, options).map(|(flow_sample, _)| flow_sample)
     }
 
-    #[test]
-    fn test_fcs_open_non_existent_file() {
-        let result = FcsFile::open("./examples/non_existent.fcs");
-        assert!(result.is_err(), "Opening a non-existent file should fail");
+    /// Read the keyword/value pairs of the ANALYSIS segment of the FCS file.
+    ///
+    /// The ANALYSIS segment holds results computed from the data, such as gate statistics, in
+    /// the same delimited format as TEXT. `read` also stores them in `FlowSample::analysis`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a HashMap of the analysis key-value pairs, which is empty if the
+    /// file has no ANALYSIS segment, or an `FcsError`.
+    ///
+    /// # Errors
+    ///
+    /// Every error is returned as an `FcsError::SegmentError`, like the errors of `read`. The
+    /// segment offsets are checked against the file length as `read` checks them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fcs_rs::FcsFile;
+    /// 
+    /// let fcs_file = FcsFile::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+    /// let analysis = fcs_file.read_analysis().unwrap();
+    /// assert!(analysis.is_empty());
+    /// ```
+    pub fn read_analysis(&self) -> Result<HashMap<String, String>, FcsError> {
+        let mut inner = self.inner.borrow_mut();
+        let mut reader = BufReader::new(&mut *inner);
+        let (header, text, _) = self.read_layout(&mut reader, 0)?;
+
+        self.read_analysis_at(&mut reader, &header, &text.keywords, 0)
     }
 
-    #[test]
-    fn test_fcs_read() {
-        let fcs_file = FcsFile::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
-        let result = fcs_file.read();
+    /// Read the HEADER and TEXT segments of the FCS file, without reading its DATA segment.
+    ///
+    /// This is the fast way to catalogue files by their keywords: only the HEADER, TEXT and
+    /// supplemental TEXT segments of the first dataset are read, and the offsets of the DATA
+    /// segment are not checked. In `ParseMode::Lenient`, keywords that fail `validate_text` are
+    /// described in `Metadata::problems` instead of failing, so a partially broken file still
+    /// gives its keywords.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `Metadata` of the first dataset, or an `FcsError`.
+    ///
+    /// # Errors
+    ///
+    /// Every error is returned as an `FcsError::SegmentError` for the HEADER or TEXT segment.
+    /// In `ParseMode::Strict`, this includes the errors of `validate_text`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fcs_rs::FcsFile;
+    /// 
+    /// let fcs_file = FcsFile::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+    /// let metadata = fcs_file.read_metadata_only().unwrap();
+    /// assert_eq!(metadata.version, "FCS3.1");
+    /// println!("{} events of {:?}", metadata.n_events.unwrap(), metadata.channels);
+    /// ```
+    pub fn read_metadata_only(&self) -> Result<Metadata, FcsError> {
+        let mut inner = self.inner.borrow_mut();
+        let mut reader = BufReader::new(&mut *inner);
+        reader.seek(SeekFrom::Start(0))
+            .map_err(|err| self.segment_error(Segment::Header, 0, FcsError::IoError(err)))?;
+        let header = read_header(&mut reader)
+            .map_err(|err| self.segment_error(Segment::Header, 0, err))?;
+
+        let text_start = *header.text_offsets.start() as u64;
+        let text = read_unvalidated_text_segments(&mut reader, &header, self.parse_mode)
+            .map_err(|err| self.segment_error(Segment::Text, text_start, err))?;
+
+        let validation = validate_text(&text.keywords, &header.version);
+        let mut metadata = Metadata::from_text(&header.version, text);
+        match (validation, self.parse_mode) {
+            (Ok(()), _) => {}
+            (Err(err), ParseMode::Lenient) => metadata.problems.push(err.to_string()),
+            (Err(err), ParseMode::Strict) => return Err(self.segment_error(Segment::Text, text_start, err)),
+        }
+
+        Ok(metadata)
+    }
+
+    /// Read every dataset of the FCS file.
+    ///
+    /// Files may hold several datasets, each with its own HEADER, TEXT and DATA segments. The
+    /// `$NEXTDATA` keyword of each dataset holds the offset of the next one, relative to its own
+    /// start, or 0 for the last one.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing one `FlowSample` per dataset, in file order, or the first error.
+    ///
+    /// # Errors
+    ///
+    /// This method returns the errors of `read` for every dataset, and an
+    /// `FcsError::InvalidNextData` wrapped in an `FcsError::SegmentError` if a `$NEXTDATA`
+    /// offset points past the end of the file or back into a dataset that was already read.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fcs_rs::FcsFile;
+    /// 
+    /// let fcs_file = FcsFile::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+    /// let flow_samples = fcs_file.read_all().unwrap();
+    /// assert_eq!(flow_samples.len(), 1);
+    /// ```
+    pub fn read_all(&self) -> Result<Vec<FlowSample>, FcsError> {
+        self.datasets().collect()
+    }
+
+    /// Returns an iterator that reads the datasets of the FCS file one at a time.
+    ///
+    /// The iterator follows `$NEXTDATA` like `read_all`. It stops after the last dataset, or
+    /// after yielding the first error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fcs_rs::FcsFile;
+    /// 
+    /// let fcs_file = FcsFile::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+    /// for flow_sample in fcs_file.datasets() {
+    ///     println!("{}", flow_sample.unwrap());
+    /// }
+    /// ```
+    pub fn datasets(&self) -> Datasets<'_, R> {
+        Datasets {
+            file: self,
+            next: Some(Ok(0)),
+            visited: Vec::new(),
+        }
+    }
+
+    /// Returns an iterator that decodes the events of the first dataset in chunks of
+    /// `chunk_size` events.
+    ///
+    /// Each chunk is a DataFrame with the columns of `read` and `chunk_size` rows, except for
+    /// the last chunk, which may be smaller. A file can be scanned this way, e.g. to count or
+    /// histogram events, while only one chunk is held in memory. Dropping the iterator stops
+    /// decoding. The iterator stops after yielding the first error.
+    ///
+    /// # Arguments
+    ///
+    /// * `chunk_size` - The number of events in each chunk.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the iterator, or an `FcsError` if the HEADER or TEXT segment cannot
+    /// be read.
+    ///
+    /// # Errors
+    ///
+    /// Every error, from this method or the iterator, is returned as an `FcsError::SegmentError`
+    /// like the errors of `read`. A `chunk_size` of 0 is reported as an `FcsError::InvalidData`.
+    /// When CRC verification is enabled, the CRC is checked before the iterator is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fcs_rs::FcsFile;
+    /// 
+    /// let fcs_file = FcsFile::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+    /// let mut events = 0;
+    /// for chunk in fcs_file.chunks(1000).unwrap() {
+    ///     let chunk = chunk.unwrap();
+    ///     assert!(chunk.height() <= 1000);
+    ///     events += chunk.height();
+    /// }
+    /// assert_eq!(events, 8821);
+    /// ```
+    pub fn chunks(&self, chunk_size: usize) -> Result<EventChunks<'_, R>, FcsError> {
+        self.chunks_with(&ReadOptions::new(), chunk_size)
+    }
+
+    /// Returns an iterator that decodes the channels and events selected by `options` from
+    /// the first dataset in chunks of `chunk_size` events.
+    ///
+    /// Each chunk is a DataFrame with the columns of `read_with` and `chunk_size` rows, except
+    /// for the last chunk, which may be smaller. Only the bytes of each chunk are read, from the
+    /// memory map if the file was opened with `open_mapped`.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - The channels and events to decode, and how values are converted.
+    /// * `chunk_size` - The number of events in each chunk.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the iterator, or an `FcsError` if the HEADER or TEXT segment cannot
+    /// be read.
+    ///
+    /// # Errors
+    ///
+    /// This method returns the same errors as `chunks`. A selected channel that does not exist
+    /// is reported as an `FcsError::UnknownChannel` and a step of 0 as an
+    /// `FcsError::InvalidData`, both wrapped in an `FcsError::SegmentError`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fcs_rs::{FcsFile, ReadOptions, ValueMode};
+    /// 
+    /// let fcs_file = FcsFile::open_mapped("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+    /// let options = ReadOptions::new().channels(["FSC-A", "SSC-A"]).mode(ValueMode::Raw);
+    /// let mut events = 0;
+    /// for chunk in fcs_file.chunks_with(&options, 1000).unwrap() {
+    ///     let chunk = chunk.unwrap();
+    ///     assert_eq!(chunk.get_column_names(), vec!["FSC-A", "SSC-A"]);
+    ///     events += chunk.height();
+    /// }
+    /// assert_eq!(events, 8821);
+    /// ```
+    pub fn chunks_with(&self, options: &ReadOptions, chunk_size: usize) -> Result<EventChunks<'_, R>, FcsError> {
+        let (decoder, data_offsets, version, end) = {
+            let mut inner = self.inner.borrow_mut();
+            let mut reader = BufReader::new(DatasetReader { inner: &mut *inner, base: 0 });
+            let (header, text, end) = self.read_layout(&mut reader, 0)?;
+
+            let data_offsets = data::data_offsets(&header, &text.keywords);
+            let decoder = data::ChunkDecoder::new(&text.keywords, &data_offsets, options, chunk_size)
+                .map_err(|err| self.segment_error(Segment::Data, *data_offsets.start() as u64, err))?;
+            (decoder, data_offsets, header.version, end)
+        };
+
+        if self.check_crc {
+            self.crc_matches(&version, 0, end as u64)?;
+        }
+
+        Ok(EventChunks { file: self, decoder, data_offsets })
+    }
+
+    /// Reads the header, text and data segments, converting values as requested by `mode`.
+    fn read_values(&self, mode: ValueMode) -> Result<FlowSample, FcsError> {
+        self.read_with(&ReadOptions::new().mode(mode))
+    }
+
+    /// Reads the dataset that starts at byte `base` of the file.
+    ///
+    /// Returns the sample and the absolute offsets of the start of its TEXT segment and of the
+    /// last byte of any of its segments.
+    fn read_dataset(&self, base: u64, options: &ReadOptions) -> Result<(FlowSample, DatasetBounds), FcsError> {
+        let (flow_sample, version, bounds) = {
+            let mut inner = self.inner.borrow_mut();
+            let mut reader = BufReader::new(DatasetReader { inner: &mut *inner, base });
+            let (header, text, end) = self.read_layout(&mut reader, base)?;
+            let metadata = &text.keywords;
+
+            let data_offsets = data::data_offsets(&header, metadata);
+            let flow_sample = match &self.map {
+                Some(map) => mapped_segment(map, base, Segment::Data, &data_offsets)
+                    .and_then(|bytes| data::parse_data_bytes_with_options(bytes, metadata, options)),
+                None => data::parse_data_segment_with_options(&mut reader, metadata, &data_offsets, options),
+            };
+            let mut flow_sample = flow_sample.map_err(|err| self.segment_error(Segment::Data, base + *data_offsets.start() as u64, err))?;
+            flow_sample.analysis = self.read_analysis_at(&mut reader, &header, metadata, base)?;
+            flow_sample.metadata.version = header.version.clone();
+            flow_sample.metadata.sources = text.sources;
+            flow_sample.metadata.conflicts = text.conflicts;
+
+            let text_start = base + *header.text_offsets.start() as u64;
+            (flow_sample, header.version, DatasetBounds { text_start, end: base + end as u64 })
+        };
+
+        if self.check_crc {
+            self.crc_matches(&version, base, bounds.end)?;
+        }
+
+        Ok((flow_sample, bounds))
+    }
+
+    /// Reads the header and text segments of the dataset that `reader` starts at, which is
+    /// byte `base` of the file, and resolves its segment offsets with `resolve_offsets`.
+    ///
+    /// Returns the resolved header, the text segments and the offset of the last byte of any
+    /// segment of the dataset, relative to `base`.
+    fn read_layout<S: Read + Seek>(
+        &self, 
+        reader: &mut BufReader<S>, 
+        base: u64,
+    ) -> Result<(Header, TextSegments, usize), FcsError> {
+        let (header, text) = self.read_text_at(reader, base)?;
+        let metadata = &text.keywords;
+
+        let file_len = reader.seek(SeekFrom::End(0))
+            .map_err(|err| self.segment_error(Segment::Header, base, FcsError::IoError(err)))?;
+        let header = resolve_offsets(&header, metadata, file_len).map_err(|err| {
+            let (segment, offset) = match &err {
+                FcsError::InvalidOffsets { segment, offsets, .. } => (*segment, *offsets.start()),
+                FcsError::OffsetMismatch { segment, text, .. } => (*segment, *text.start()),
+                _ => (Segment::Text, *header.text_offsets.start()),
+            };
+            self.segment_error(segment, base + offset as u64, err)
+        })?;
+
+        let supplemental_end = metadata.get("$ENDSTEXT")
+            .and_then(|value| value.trim().parse::<usize>().ok())
+            .unwrap_or(0);
+        let end = [*header.text_offsets.end(), *header.data_offsets.end(), *header.analysis_offsets.end(), supplemental_end]
+            .into_iter()
+            .max()
+            .unwrap_or(0);
+
+        Ok((header, text, end))
+    }
+
+    /// Checks the CRC stored after the dataset of FCS version `version` that spans bytes `base`
+    /// to `end` of the file.
+    ///
+    /// Returns `false` if the dataset stores no CRC, which FCS 2.0 datasets never do.
+    fn crc_matches(&self, version: &str, base: u64, end: u64) -> Result<bool, FcsError> {
+        if version == "FCS2.0" {
+            return Ok(false);
+        }
+
+        let crc_start = end + 1;
+        let crc_error = |err| self.segment_error(Segment::Crc, crc_start, err);
+
+        let mut inner = self.inner.borrow_mut();
+        inner.seek(SeekFrom::Start(base)).map_err(|err| crc_error(FcsError::IoError(err)))?;
+        let mut reader = BufReader::new(&mut *inner);
+
+        let mut crc = crc::Crc16::new();
+        let mut remaining = crc_start - base;
+        while remaining > 0 {
+            let buffer = reader.fill_buf().map_err(|err| crc_error(FcsError::IoError(err)))?;
+            if buffer.is_empty() {
+                return Err(crc_error(FcsError::IoError(io::ErrorKind::UnexpectedEof.into())));
+            }
+            let len = buffer.len().min(remaining as usize);
+            crc.update(&buffer[..len]);
+            reader.consume(len);
+            remaining -= len as u64;
+        }
+
+        let mut stored = Vec::with_capacity(crc::CRC_LEN);
+        reader.take(crc::CRC_LEN as u64).read_to_end(&mut stored)
+            .map_err(|err| crc_error(FcsError::IoError(err)))?;
+
+        match crc::parse_crc(&stored) {
+            Ok(None) => Ok(false),
+            Ok(Some(value)) if value == crc.value() => Ok(true),
+            Ok(Some(value)) => Err(crc_error(FcsError::CrcMismatch { stored: crc::format_crc(value), computed: crc.value() })),
+            Err(stored) => Err(crc_error(FcsError::CrcMismatch { stored, computed: crc.value() })),
+        }
+    }
+
+    /// Reads the header and text segments of the dataset that `reader` starts at, which is
+    /// byte `base` of the file.
+    fn read_text_at<S: Read + Seek>(
+        &self, 
+        reader: &mut BufReader<S>, 
+        base: u64,
+    ) -> Result<(Header, TextSegments), FcsError> {
+        reader.seek(SeekFrom::Start(0))
+            .map_err(|err| self.segment_error(Segment::Header, base, FcsError::IoError(err)))?;
+        let header = read_header(reader)
+            .map_err(|err| self.segment_error(Segment::Header, base, err))?;
+
+        let text_start = base + *header.text_offsets.start() as u64;
+        let text = read_text_segments(reader, &header, self.parse_mode)
+            .map_err(|err| self.segment_error(Segment::Text, text_start, err))?;
+
+        Ok((header, text))
+    }
+
+    /// Reads the analysis segment of the dataset that `reader` starts at, which is byte `base`
+    /// of the file.
+    fn read_analysis_at<S: Read + Seek>(
+        &self, 
+        reader: &mut BufReader<S>, 
+        header: &Header, 
+        metadata: &HashMap<String, String>, 
+        base: u64,
+    ) -> Result<HashMap<String, String>, FcsError> {
+        let analysis_start = base + *text::analysis_offsets(header, metadata).start() as u64;
+        read_analysis(reader, header, metadata)
+            .map_err(|err| self.segment_error(Segment::Analysis, analysis_start, err))
+    }
+
+    /// Returns the start of the dataset after the one at `base`, or `None` if it is the last.
+    fn next_dataset(
+        &self, 
+        base: u64, 
+        bounds: &DatasetBounds, 
+        flow_sample: &FlowSample, 
+        visited: &[u64],
+    ) -> Result<Option<u64>, FcsError> {
+        let value = match flow_sample.metadata.keywords.get("$NEXTDATA") {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        let offset = value.trim().parse::<u64>().map_err(|_| self.segment_error(
+            Segment::Text,
+            bounds.text_start,
+            FcsError::InvalidKeyword { keyword: "$NEXTDATA".to_string(), value: value.clone() },
+        ))?;
+        if offset == 0 {
+            return Ok(None);
+        }
+
+        let next = base + offset;
+        let file_len = self.inner.borrow_mut().seek(SeekFrom::End(0))
+            .map_err(|err| self.segment_error(Segment::Header, next, FcsError::IoError(err)))?;
+        let reason = if next >= file_len {
+            Some(format!("it points past the end of the file at byte {}", file_len))
+        } else if next <= bounds.end || visited.contains(&next) {
+            Some("it points back into a dataset that was already read".to_string())
+        } else {
+            None
+        };
+
+        match reason {
+            Some(reason) => Err(self.segment_error(Segment::Header, next, FcsError::InvalidNextData { offset, reason })),
+            None => Ok(Some(next)),
+        }
+    }
+
+    /// Wraps an error with the segment and offset where it happened and the path of this file.
+    fn segment_error(&self, segment: Segment, offset: u64, err: FcsError) -> FcsError {
+        let path = match &self.path {
+            Some(path) => path.display().to_string(),
+            None => "<unknown path>".to_string(),
+        };
+
+        FcsError::SegmentError {
+            segment,
+            offset,
+            path,
+            source: Box::new(err),
+        }
+    }
+}
+
+/// An iterator over the datasets of an FCS file, created by `FcsFile::datasets`.
+#[derive(Debug)]
+pub struct Datasets<'a, R> {
+    file: &'a FcsFile<R>,
+    next: Option<Result<u64, FcsError>>,
+    visited: Vec<u64>,
+}
+
+impl<R: Read + Seek> Iterator for Datasets<'_, R> {
+    type Item = Result<FlowSample, FcsError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let base = match self.next.take()? {
+            Ok(base) => base,
+            Err(err) => return Some(Err(err)),
+        };
+        self.visited.push(base);
+
+        let (flow_sample, bounds) = match self.file.read_dataset(base, &ReadOptions::new()) {
+            Ok(dataset) => dataset,
+            Err(err) => return Some(Err(err)),
+        };
+        // An invalid $NEXTDATA is reported after the dataset that holds it
+        self.next = self.file.next_dataset(base, &bounds, &flow_sample, &self.visited).transpose();
+
+        Some(Ok(flow_sample))
+    }
+}
+
+/// An iterator over chunks of the events of an FCS file, created by `FcsFile::chunks`.
+///
+/// The iterator borrows the file only while it decodes a chunk, so the file can be read in
+/// other ways between chunks.
+#[derive(Debug)]
+pub struct EventChunks<'a, R> {
+    file: &'a FcsFile<R>,
+    decoder: data::ChunkDecoder,
+    data_offsets: RangeInclusive<usize>,
+}
+
+impl<R> EventChunks<'_, R> {
+    /// Returns the number of events that have not been yielded yet.
+    pub fn events_left(&self) -> usize {
+        self.decoder.events_left()
+    }
+}
+
+impl<R: Read + Seek> Iterator for EventChunks<'_, R> {
+    type Item = Result<DataFrame, FcsError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let chunk = match &self.file.map {
+            Some(map) => mapped_segment(map, 0, Segment::Data, &self.data_offsets)
+                .and_then(|bytes| self.decoder.decode_next(&mut Cursor::new(bytes), 0)),
+            None => {
+                let mut inner = self.file.inner.borrow_mut();
+                let mut reader = DatasetReader { inner: &mut *inner, base: 0 };
+                self.decoder.decode_next(&mut reader, *self.data_offsets.start() as u64)
+            },
+        };
+
+        chunk.map_err(|err| self.file.segment_error(Segment::Data, *self.data_offsets.start() as u64, err))
+            .transpose()
+    }
+}
+
+/// Returns the bytes of `segment`, at `offsets` of the dataset that starts at byte `base` of
+/// `map`. The offsets `0..=0` give an empty segment.
+///
+/// Returns `FcsError::InvalidOffsets` if the segment is reversed, overlaps the header or
+/// extends past the end of the file.
+fn mapped_segment<'a>(map: &'a [u8], base: u64, segment: Segment, offsets: &RangeInclusive<usize>) -> Result<&'a [u8], FcsError> {
+    if *offsets == (0..=0) {
+        return Ok(&[]);
+    }
+
+    let dataset = map.get(base as usize..).unwrap_or_default();
+    check_offsets(segment, offsets, dataset.len() as u64)?;
+    Ok(&dataset[offsets.clone()])
+}
+
+/// The absolute offsets of a dataset, used to check the `$NEXTDATA` offset that follows it.
+#[derive(Debug)]
+struct DatasetBounds {
+    text_start: u64,
+    end: u64,
+}
+
+/// A reader whose start is moved to byte `base` of `inner`.
+///
+/// The offsets in the HEADER and TEXT of a dataset are relative to the start of the dataset,
+/// so this lets the segment readers seek to them unchanged.
+struct DatasetReader<R> {
+    inner: R,
+    base: u64,
+}
+
+impl<R: Read> Read for DatasetReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<R: Seek> Seek for DatasetReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(offset) => SeekFrom::Start(self.base + offset),
+            other => other,
+        };
+        let position = self.inner.seek(pos)?;
+
+        position.checked_sub(self.base).ok_or_else(|| io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Cannot seek before the start of the dataset",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fcs_open() {
+        let result = FcsFile::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs");
+        assert!(result.is_ok(), "FCS file open failed: {:?}", result.err());
+    }
+
+    #[test]
+    fn test_fcs_open_non_existent_file() {
+        let result = FcsFile::open("./examples/non_existent.fcs");
+        assert!(result.is_err(), "Opening a non-existent file should fail");
+    }
+
+    #[test]
+    fn test_fcs_read() {
+        let fcs_file = FcsFile::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+        let result = fcs_file.read();
         assert!(result.is_ok(), "FCS file read failed: {:?}", result.err());
     }
 
+    const EXAMPLE_FILE: &str = "./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs";
+
+    /// Writes `bytes` to a temporary file and reads it back as an FCS file.
+    fn read_bytes(name: &str, bytes: &[u8]) -> Result<FlowSample, FcsError> {
+        let path = std::env::temp_dir().join(format!("fcs_rs_{}.fcs", name));
+        std::fs::write(&path, bytes).unwrap();
+        FcsFile::open(path.to_str().unwrap()).unwrap().read()
+    }
+
+    /// Splits a `SegmentError` into its segment, offset and source error.
+    fn unwrap_segment_error(err: FcsError) -> (Segment, u64, String, FcsError) {
+        match err {
+            FcsError::SegmentError { segment, offset, path, source } => (segment, offset, path, *source),
+            err => panic!("Expected a SegmentError, got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_fcs_read_garbage() {
+        let garbage: Vec<u8> = (0..512u32).map(|i| (i * 37 % 251) as u8).collect();
+        let (segment, offset, path, source) = unwrap_segment_error(read_bytes("garbage", &garbage).unwrap_err());
+
+        assert_eq!(segment, Segment::Header);
+        assert_eq!(offset, 0);
+        assert!(path.ends_with("fcs_rs_garbage.fcs"), "Unexpected path {}", path);
+        assert!(matches!(source, FcsError::InvalidHeader), "Unexpected error {:?}", source);
+
+        // Valid UTF-8 with a multi-byte character across the end of the version field
+        let mut bytes = std::fs::read(EXAMPLE_FILE).unwrap();
+        bytes[5..7].copy_from_slice("\u{e9}".as_bytes());
+        let (segment, _, _, source) = unwrap_segment_error(read_bytes("garbage_utf8", &bytes).unwrap_err());
+        assert_eq!(segment, Segment::Header);
+        assert!(matches!(source, FcsError::InvalidHeader), "Unexpected error {:?}", source);
+    }
+
+    #[test]
+    fn test_fcs_read_unsupported_version() {
+        let mut bytes = std::fs::read(EXAMPLE_FILE).unwrap();
+        bytes[..6].copy_from_slice(b"FCS9.9");
+        let (segment, _, _, source) = unwrap_segment_error(read_bytes("bad_version", &bytes).unwrap_err());
+
+        assert_eq!(segment, Segment::Header);
+        assert!(matches!(source, FcsError::InvalidVersion(ref version) if version == "FCS9.9"));
+    }
+
+    #[test]
+    fn test_fcs_read_truncated_header() {
+        let bytes = std::fs::read(EXAMPLE_FILE).unwrap();
+        let (segment, _, _, source) = unwrap_segment_error(read_bytes("truncated_header", &bytes[..30]).unwrap_err());
+
+        assert_eq!(segment, Segment::Header);
+        assert!(matches!(source, FcsError::IoError(_)), "Unexpected error {:?}", source);
+    }
+
+    #[test]
+    fn test_fcs_read_truncated_text() {
+        let bytes = std::fs::read(EXAMPLE_FILE).unwrap();
+        let (segment, offset, _, source) = unwrap_segment_error(read_bytes("truncated_text", &bytes[..1000]).unwrap_err());
+
+        assert_eq!(segment, Segment::Text);
+        assert_eq!(offset, 58);
+        assert!(matches!(source, FcsError::InvalidOffsets { segment: Segment::Text, .. }), "Unexpected error {:?}", source);
+    }
+
+    #[test]
+    fn test_fcs_read_missing_keyword() {
+        let mut bytes = std::fs::read(EXAMPLE_FILE).unwrap();
+        let position = bytes.windows(6).position(|window| window == b"/$TOT/").unwrap();
+        bytes[position + 1..position + 5].copy_from_slice(b"$XXX");
+        let (segment, _, _, source) = unwrap_segment_error(read_bytes("missing_keyword", &bytes).unwrap_err());
+
+        assert_eq!(segment, Segment::Text);
+        assert!(matches!(source, FcsError::InvalidText(ref keyword) if keyword == "$TOT"));
+    }
+
+    #[test]
+    fn test_fcs_read_truncated_data() {
+        let bytes = std::fs::read(EXAMPLE_FILE).unwrap();
+        let (segment, offset, _, source) = unwrap_segment_error(read_bytes("truncated_data", &bytes[..20000]).unwrap_err());
+
+        assert_eq!(segment, Segment::Data);
+        assert_eq!(offset, 8195);
+        assert!(matches!(source, FcsError::InvalidOffsets { segment: Segment::Data, .. }), "Unexpected error {:?}", source);
+    }
+
+    #[test]
+    fn test_fcs_read_offset_mismatch() {
+        let mut bytes = dataset(&[1, 2, 3], 0);
+        // Move $BEGINDATA in TEXT away from the header's DATA offset
+        let position = bytes.windows(12).position(|window| window == b"/$BEGINDATA/").unwrap() + 12;
+        bytes[position..position + 8].copy_from_slice(b"00000090");
+        let (segment, offset, _, source) = unwrap_segment_error(FcsFile::from_bytes(&bytes).read().unwrap_err());
+
+        assert_eq!(segment, Segment::Data);
+        assert_eq!(offset, 90);
+        assert!(matches!(source, FcsError::OffsetMismatch { segment: Segment::Data, .. }), "Unexpected error {:?}", source);
+    }
+
+    /// Writes the first dataset of the example file with this crate's writer, which stores a CRC.
+    fn written_example() -> Vec<u8> {
+        let flow_sample = FcsFile::open(EXAMPLE_FILE).unwrap().read().unwrap();
+        let mut bytes = Vec::new();
+        write_fcs(&mut bytes, &flow_sample, &WriteOptions::default()).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_fcs_verify_crc() {
+        // The example file ends after its DATA segment, without a CRC
+        assert!(!FcsFile::open(EXAMPLE_FILE).unwrap().verify_crc().unwrap());
+
+        let mut bytes = written_example();
+        assert!(FcsFile::from_bytes(&bytes).verify_crc().unwrap());
+
+        let crc_start = bytes.len() - 8;
+        bytes[crc_start..].copy_from_slice(b"00000000");
+        assert!(!FcsFile::from_bytes(&bytes).verify_crc().unwrap());
+    }
+
+    #[test]
+    fn test_fcs_verify_crc_mismatch() {
+        let mut bytes = written_example();
+        let crc_start = bytes.len() - 8;
+        let stored = String::from_utf8(bytes[crc_start..].to_vec()).unwrap();
+        // Corrupt one byte of the DATA segment
+        bytes[crc_start - 100] ^= 0x01;
+
+        let err = FcsFile::from_bytes(&bytes).verify_crc().unwrap_err();
+        let (segment, offset, _, source) = unwrap_segment_error(err);
+        assert_eq!(segment, Segment::Crc);
+        assert_eq!(offset, crc_start as u64);
+        assert!(matches!(source, FcsError::CrcMismatch { stored: ref s, .. } if *s == stored), "Unexpected error {:?}", source);
+
+        let crc_start = bytes.len() - 8;
+        bytes[crc_start..].copy_from_slice(b"00099999");
+        let (_, _, _, source) = unwrap_segment_error(FcsFile::from_bytes(&bytes).verify_crc().unwrap_err());
+        assert!(matches!(source, FcsError::CrcMismatch { ref stored, .. } if stored == "00099999"), "Unexpected error {:?}", source);
+
+        // Bytes that are not digits are not a CRC
+        bytes[crc_start..].copy_from_slice(b"CRC-XXXX");
+        assert!(!FcsFile::from_bytes(&bytes).verify_crc().unwrap());
+    }
+
+    #[test]
+    fn test_fcs_crc_check_without_crc() {
+        // FCS 2.0 defines no CRC, so the bytes after the dataset are not checked
+        let keywords = [
+            ("$BYTEORD", "1,2"),
+            ("$DATATYPE", "I"),
+            ("$MODE", "L"),
+            ("$NEXTDATA", "0"),
+            ("$PAR", "1"),
+            ("$TOT", "2"),
+            ("$P1N", "FSC"),
+            ("$P1B", "16"),
+            ("$P1R", "65536"),
+        ];
+        let mut bytes = test_utils::build_fcs("FCS2.0", &keywords, &[1, 0, 2, 0], false);
+        bytes.extend(b"00001234");
+        let fcs_file = FcsFile::from_bytes(&bytes).with_crc_check(true);
+        assert!(!fcs_file.verify_crc().unwrap());
+        assert_eq!(fsc(&fcs_file.read().unwrap()), vec![1.0, 2.0]);
+
+        // Datasets followed by the HEADER of the next one or by padding store no CRC
+        let bytes = chain_datasets(&[&[1], &[2]]);
+        let flow_samples = FcsFile::from_bytes(&bytes).with_crc_check(true).read_all().unwrap();
+        assert_eq!(flow_samples.len(), 2);
+        let mut bytes = dataset(&[1], 0);
+        bytes.extend(b"        ");
+        assert!(FcsFile::from_bytes(&bytes).with_crc_check(true).read().is_ok());
+    }
+
+    #[test]
+    fn test_fcs_read_with_crc_check() {
+        let mut bytes = written_example();
+        assert!(FcsFile::from_bytes(&bytes).with_crc_check(true).read().is_ok());
+
+        let crc_start = bytes.len() - 8;
+        bytes[crc_start - 100] ^= 0x01;
+        // CRC verification is opt-in
+        assert!(FcsFile::from_bytes(&bytes).read().is_ok());
+
+        let fcs_file = FcsFile::from_bytes(&bytes).with_crc_check(true);
+        let (segment, _, _, source) = unwrap_segment_error(fcs_file.read().unwrap_err());
+        assert_eq!(segment, Segment::Crc);
+        assert!(matches!(source, FcsError::CrcMismatch { .. }), "Unexpected error {:?}", source);
+        assert!(fcs_file.read_all().is_err());
+
+        // Files without a CRC are read as usual
+        assert!(FcsFile::open(EXAMPLE_FILE).unwrap().with_crc_check(true).read().is_ok());
+    }
+
+    #[test]
+    fn test_fcs_read_error_message() {
+        let bytes = std::fs::read(EXAMPLE_FILE).unwrap();
+        let err = read_bytes("error_message", &bytes[..20000]).unwrap_err();
+
+        let message = err.to_string();
+        assert!(message.starts_with("Failed to read DATA segment of "), "Unexpected message {}", message);
+        assert!(message.contains("fcs_rs_error_message.fcs at byte offset 8195"), "Unexpected message {}", message);
+    }
+
+    #[test]
+    fn test_fcs_from_bytes() {
+        let bytes = std::fs::read(EXAMPLE_FILE).unwrap();
+        let from_bytes = FcsFile::from_bytes(&bytes).read().unwrap();
+        let from_path = FcsFile::open(EXAMPLE_FILE).unwrap().read().unwrap();
+
+        assert_eq!(from_bytes.data, from_path.data);
+        assert_eq!(from_bytes.parameters(), from_path.parameters());
+        assert_eq!(from_bytes.channels, from_path.channels);
+    }
+
+    #[test]
+    fn test_fcs_from_reader() {
+        let bytes = std::fs::read(EXAMPLE_FILE).unwrap();
+        let fcs_file = FcsFile::from_reader(io::Cursor::new(bytes));
+
+        // The source is rewound on every read
+        let first = fcs_file.read().unwrap();
+        let second = fcs_file.read().unwrap();
+        assert_eq!(first.data.shape(), (8821, 10));
+        assert_eq!(first.data, second.data);
+    }
+
+    #[test]
+    fn test_fcs_from_bytes_error_path() {
+        let bytes = std::fs::read(EXAMPLE_FILE).unwrap();
+        let err = FcsFile::from_bytes(&bytes[..20000]).read().unwrap_err();
+        let (segment, _, path, source) = unwrap_segment_error(err);
+
+        assert_eq!(segment, Segment::Data);
+        assert_eq!(path, "<unknown path>");
+        assert!(matches!(source, FcsError::InvalidOffsets { segment: Segment::Data, .. }), "Unexpected error {:?}", source);
+    }
+
+    #[test]
+    fn test_fcs_read_analysis_error() {
+        let mut flow_sample = FcsFile::open(EXAMPLE_FILE).unwrap().read().unwrap();
+        flow_sample.analysis.insert("GATE1 COUNT".to_string(), "120".to_string());
+        let mut bytes = Vec::new();
+        write_fcs(&mut bytes, &flow_sample, &WriteOptions::default()).unwrap();
+
+        // Drop the final delimiter of the ANALYSIS segment, just before the CRC
+        let delimiter = bytes.len() - 9;
+        bytes.remove(delimiter);
+        let err = FcsFile::from_bytes(&bytes).read_raw().map(|_| ()).unwrap_err();
+        let (segment, offset, _, source) = unwrap_segment_error(err);
+        assert_eq!(segment, Segment::Analysis);
+        assert_eq!(offset, (delimiter - "/GATE1 COUNT/120".len()) as u64);
+        assert!(matches!(source, FcsError::MalformedText(_)), "Unexpected error {:?}", source);
+
+        let result = FcsFile::from_bytes(&bytes).read_analysis();
+        assert!(matches!(result, Err(FcsError::SegmentError { segment: Segment::Analysis, .. })));
+    }
+
+    #[test]
+    fn test_fcs_read_analysis_offsets_past_end() {
+        let keywords = [
+            ("$BEGINANALYSIS", "100"),
+            ("$ENDANALYSIS", "9000000000000000"),
+            ("$BEGINSTEXT", "0"),
+            ("$ENDSTEXT", "0"),
+            ("$BYTEORD", "1,2"),
+            ("$DATATYPE", "I"),
+            ("$MODE", "L"),
+            ("$NEXTDATA", "0"),
+            ("$PAR", "1"),
+            ("$TOT", "1"),
+            ("$P1N", "FSC"),
+            ("$P1B", "16"),
+            ("$P1E", "0,0"),
+            ("$P1R", "65536"),
+        ];
+        let bytes = test_utils::build_fcs("FCS3.1", &keywords, &[7, 0], true);
+
+        let err = FcsFile::from_bytes(&bytes).read_analysis().unwrap_err();
+        let (segment, _, _, source) = unwrap_segment_error(err);
+        assert_eq!(segment, Segment::Analysis);
+        assert!(matches!(source, FcsError::InvalidOffsets { segment: Segment::Analysis, .. }), "Unexpected error {:?}", source);
+    }
+
+    /// Builds a dataset with one 16 bit parameter named FSC and the given `$NEXTDATA` offset.
+    fn dataset(values: &[u16], next_data: u64) -> Vec<u8> {
+        let data: Vec<u8> = values.iter().flat_map(|value| value.to_le_bytes()).collect();
+        let (n_events, next_data) = (values.len().to_string(), format!("{:08}", next_data));
+        let keywords = [
+            ("$BEGINANALYSIS", "0"),
+            ("$ENDANALYSIS", "0"),
+            ("$BEGINSTEXT", "0"),
+            ("$ENDSTEXT", "0"),
+            ("$BYTEORD", "1,2"),
+            ("$DATATYPE", "I"),
+            ("$MODE", "L"),
+            ("$NEXTDATA", next_data.as_str()),
+            ("$PAR", "1"),
+            ("$TOT", n_events.as_str()),
+            ("$P1N", "FSC"),
+            ("$P1B", "16"),
+            ("$P1E", "0,0"),
+            ("$P1R", "65536"),
+        ];
+        test_utils::build_fcs("FCS3.1", &keywords, &data, true)
+    }
+
+    /// Concatenates datasets, pointing the `$NEXTDATA` of each one to the next.
+    fn chain_datasets(values: &[&[u16]]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for (i, events) in values.iter().enumerate() {
+            let len = dataset(events, 0).len() as u64;
+            let next_data = if i + 1 == values.len() { 0 } else { len };
+            bytes.extend(dataset(events, next_data));
+        }
+        bytes
+    }
+
+    fn fsc(flow_sample: &FlowSample) -> Vec<f64> {
+        flow_sample.data.column("FSC").unwrap().f64().unwrap().into_no_null_iter().collect()
+    }
+
+    #[test]
+    fn test_fcs_read_all() {
+        let bytes = chain_datasets(&[&[1, 2, 3], &[4, 5], &[6]]);
+        let fcs_file = FcsFile::from_bytes(&bytes);
+
+        let flow_samples = fcs_file.read_all().unwrap();
+        assert_eq!(flow_samples.len(), 3);
+        assert_eq!(fsc(&flow_samples[0]), vec![1.0, 2.0, 3.0]);
+        assert_eq!(fsc(&flow_samples[1]), vec![4.0, 5.0]);
+        assert_eq!(fsc(&flow_samples[2]), vec![6.0]);
+
+        // read only returns the first dataset
+        assert_eq!(fsc(&fcs_file.read().unwrap()), vec![1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn test_fcs_open_mapped() {
+        let flow_sample = FcsFile::open(EXAMPLE_FILE).unwrap().read().unwrap();
+        let mapped = FcsFile::open_mapped(EXAMPLE_FILE).unwrap();
+        assert!(mapped.read().unwrap().data.equals(&flow_sample.data));
+
+        let raw = FcsFile::open(EXAMPLE_FILE).unwrap().read_raw().unwrap();
+        assert!(mapped.read_raw().unwrap().data.equals(&raw.data));
+
+        assert!(FcsFile::open_mapped("./examples/non_existent.fcs").is_err());
+    }
+
+    #[test]
+    fn test_fcs_open_mapped_datasets() {
+        let path = std::env::temp_dir().join("fcs_rs_mapped_datasets.fcs");
+        std::fs::write(&path, chain_datasets(&[&[1, 2, 3], &[4, 5], &[6]])).unwrap();
+
+        let flow_samples = FcsFile::open_mapped(path.to_str().unwrap()).unwrap().read_all().unwrap();
+        assert_eq!(flow_samples.len(), 3);
+        assert_eq!(fsc(&flow_samples[0]), vec![1.0, 2.0, 3.0]);
+        assert_eq!(fsc(&flow_samples[1]), vec![4.0, 5.0]);
+        assert_eq!(fsc(&flow_samples[2]), vec![6.0]);
+    }
+
+    #[test]
+    fn test_mapped_segment() {
+        let map = (0..100).collect::<Vec<u8>>();
+        assert_eq!(mapped_segment(&map, 0, Segment::Data, &(60..=62)).unwrap(), &[60, 61, 62]);
+        assert_eq!(mapped_segment(&map, 2, Segment::Data, &(60..=62)).unwrap(), &[62, 63, 64]);
+        assert_eq!(mapped_segment(&map, 40, Segment::Data, &(0..=0)).unwrap(), &[] as &[u8]);
+        assert_eq!(mapped_segment(&map, 200, Segment::Data, &(0..=0)).unwrap(), &[] as &[u8]);
+
+        // An end offset of 0 does not run on into later datasets, and offsets past the end of
+        // the file are reported rather than cut short
+        for (base, offsets) in [(2, RangeInclusive::new(60, 0)), (4, 60..=96), (0, 100..=102), (200, 60..=62)] {
+            let err = mapped_segment(&map, base, Segment::Data, &offsets).unwrap_err();
+            assert!(matches!(err, FcsError::InvalidOffsets { segment: Segment::Data, .. }), "{:?}: {:?}", offsets, err);
+        }
+    }
+
+    #[test]
+    fn test_fcs_read_with() {
+        let fcs_file = FcsFile::open(EXAMPLE_FILE).unwrap();
+        let flow_sample = fcs_file.read().unwrap();
+        assert!(fcs_file.read_with(&ReadOptions::new()).unwrap().data.equals(&flow_sample.data));
+
+        let options = ReadOptions::new().channels(["SSC-A", "FSC-A"]).events(100..8000).step(7).limit(500);
+        let column = |flow_sample: &FlowSample, name: &str| -> Vec<f64> {
+            flow_sample.data.column(name).unwrap().f64().unwrap().into_no_null_iter().collect()
+        };
+        let expected = column(&flow_sample, "SSC-A").into_iter().skip(100).step_by(7).take(500).collect::<Vec<_>>();
+        for fcs_file in [FcsFile::open(EXAMPLE_FILE).unwrap(), FcsFile::open_mapped(EXAMPLE_FILE).unwrap()] {
+            let subset = fcs_file.read_with(&options).unwrap();
+            assert_eq!(subset.get_dataframe_columns(), vec!["SSC-A", "FSC-A"]);
+            assert_eq!(column(&subset, "SSC-A"), expected);
+            assert_eq!(subset.channels.iter().map(|channel| channel.index).collect::<Vec<_>>(), vec![3, 2]);
+            assert_eq!(subset.get_label("SSC-A"), Some("SSC-SSC-A"));
+        }
+
+        let raw = fcs_file.read_raw().unwrap();
+        let subset = fcs_file.read_with(&ReadOptions::new().channels(["FSC-A"]).limit(3).mode(ValueMode::Raw)).unwrap();
+        assert_eq!(column(&subset, "FSC-A"), column(&raw, "FSC-A")[..3]);
+    }
+
+    #[test]
+    fn test_fcs_read_with_unknown_channel() {
+        let err = FcsFile::open(EXAMPLE_FILE).unwrap().read_with(&ReadOptions::new().channels(["CD4"])).unwrap_err();
+        let (segment, _, _, source) = unwrap_segment_error(err);
+        assert_eq!(segment, Segment::Data);
+        assert!(matches!(source, FcsError::UnknownChannel(ref channel) if channel == "CD4"), "{:?}", source);
+    }
+
+    #[test]
+    fn test_fcs_read_metadata_only() {
+        let fcs_file = FcsFile::open(EXAMPLE_FILE).unwrap();
+        let metadata = fcs_file.read_metadata_only().unwrap();
+        let flow_sample = fcs_file.read().unwrap();
+
+        assert_eq!(&metadata.keywords, flow_sample.parameters());
+        assert_eq!(metadata.channels, flow_sample.channels);
+        assert_eq!(flow_sample.metadata, metadata);
+        assert_eq!(metadata.n_events, Some(flow_sample.data.height()));
+        assert!(metadata.problems.is_empty());
+
+        // The DATA segment is not read, so a file cut after its TEXT segment still gives its keywords
+        let bytes = std::fs::read(EXAMPLE_FILE).unwrap();
+        let header = read_header(&mut BufReader::new(Cursor::new(&bytes))).unwrap();
+        let text_end = *header.text_offsets.end() + 1;
+        let truncated = FcsFile::from_bytes(&bytes[..text_end]);
+        assert_eq!(truncated.read_metadata_only().unwrap(), metadata);
+        assert!(truncated.read().is_err());
+    }
+
+    #[test]
+    fn test_fcs_read_metadata_only_lenient() {
+        let mut bytes = std::fs::read(EXAMPLE_FILE).unwrap();
+        let position = bytes.windows(6).position(|window| window == b"/$TOT/").unwrap();
+        bytes[position + 1..position + 5].copy_from_slice(b"$XXX");
+
+        let (segment, _, _, source) = unwrap_segment_error(FcsFile::from_bytes(&bytes).read_metadata_only().unwrap_err());
+        assert_eq!(segment, Segment::Text);
+        assert!(matches!(source, FcsError::InvalidText(ref keyword) if keyword == "$TOT"));
+
+        let metadata = FcsFile::from_bytes(&bytes).with_parse_mode(ParseMode::Lenient).read_metadata_only().unwrap();
+        assert_eq!(metadata.n_events, None);
+        assert_eq!(metadata.n_params, Some(10));
+        assert!(metadata.keywords.contains_key("$XXX"));
+        assert_eq!(metadata.problems, vec![FcsError::InvalidText("$TOT".to_string()).to_string()]);
+
+        // Errors in the HEADER are not recoverable
+        let (segment, _, _, _) = unwrap_segment_error(
+            FcsFile::from_bytes(&bytes[..30]).with_parse_mode(ParseMode::Lenient).read_metadata_only().unwrap_err()
+        );
+        assert_eq!(segment, Segment::Header);
+    }
+
+    #[test]
+    fn test_fcs_chunks() {
+        let fcs_file = FcsFile::open(EXAMPLE_FILE).unwrap();
+        let flow_sample = fcs_file.read().unwrap();
+
+        let mut chunks = fcs_file.chunks(4000).unwrap();
+        assert_eq!(chunks.events_left(), 8821);
+        let mut data = chunks.next().unwrap().unwrap();
+        assert_eq!(chunks.events_left(), 4821);
+        for chunk in chunks.by_ref() {
+            data.vstack_mut(&chunk.unwrap()).unwrap();
+        }
+        assert_eq!(chunks.events_left(), 0);
+        assert!(chunks.next().is_none());
+        assert!(data.equals(&flow_sample.data));
+
+        let heights = fcs_file.chunks(4000).unwrap().map(|chunk| chunk.unwrap().height()).collect::<Vec<_>>();
+        assert_eq!(heights, vec![4000, 4000, 821]);
+    }
+
+    #[test]
+    fn test_fcs_chunks_with() {
+        let options = ReadOptions::new().channels(["SSC-A", "Time"]).events(100..5000).step(3).mode(ValueMode::Raw);
+        for fcs_file in [FcsFile::open(EXAMPLE_FILE).unwrap(), FcsFile::open_mapped(EXAMPLE_FILE).unwrap()] {
+            let flow_sample = fcs_file.read_with(&options).unwrap();
+
+            let chunks = fcs_file.chunks_with(&options, 500).unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+            assert_eq!(chunks.iter().map(|chunk| chunk.height()).collect::<Vec<_>>(), vec![500, 500, 500, 134]);
+            let mut data = chunks[0].clone();
+            for chunk in &chunks[1..] {
+                data.vstack_mut(chunk).unwrap();
+            }
+            assert!(data.equals(&flow_sample.data));
+        }
+
+        let err = FcsFile::open_mapped(EXAMPLE_FILE).unwrap().chunks_with(&ReadOptions::new().channels(["X"]), 10).unwrap_err();
+        let (segment, _, _, source) = unwrap_segment_error(err);
+        assert_eq!(segment, Segment::Data);
+        assert!(matches!(source, FcsError::UnknownChannel(ref channel) if channel == "X"), "{:?}", source);
+    }
+
+    #[test]
+    fn test_fcs_chunks_early_termination() {
+        let fcs_file = FcsFile::open(EXAMPLE_FILE).unwrap();
+        let flow_sample = fcs_file.read().unwrap();
+
+        let mut chunks = fcs_file.chunks(10);
+        let first = chunks.as_mut().unwrap().next().unwrap().unwrap();
+        assert!(first.equals(&flow_sample.data.slice(0, 10)));
+
+        // The file can be read between chunks and after the iterator is dropped
+        assert!(fcs_file.read().unwrap().data.equals(&flow_sample.data));
+        let second = chunks.unwrap().next().unwrap().unwrap();
+        assert!(second.equals(&flow_sample.data.slice(10, 10)));
+        assert_eq!(fcs_file.chunks(100).unwrap().take(3).count(), 3);
+    }
+
+    #[test]
+    fn test_fcs_chunks_with_crc_check() {
+        let mut bytes = written_example();
+        assert_eq!(FcsFile::from_bytes(&bytes).with_crc_check(true).chunks(1000).unwrap().count(), 9);
+
+        let len = bytes.len();
+        bytes[len - 1] = if bytes[len - 1] == b'9' { b'8' } else { b'9' };
+        let err = FcsFile::from_bytes(&bytes).with_crc_check(true).chunks(1000).unwrap_err();
+        let (segment, _, _, source) = unwrap_segment_error(err);
+        assert_eq!(segment, Segment::Crc);
+        assert!(matches!(source, FcsError::CrcMismatch { .. }), "{:?}", source);
+    }
+
+    #[test]
+    fn test_fcs_chunks_zero_size() {
+        let err = FcsFile::open(EXAMPLE_FILE).unwrap().chunks(0).map(|_| ()).unwrap_err();
+        let (segment, _, _, source) = unwrap_segment_error(err);
+        assert_eq!(segment, Segment::Data);
+        assert!(matches!(source, FcsError::InvalidData(ref message) if message == "Chunk size must be non-zero"), "{:?}", source);
+    }
+
+    #[test]
+    fn test_fcs_datasets_iterator() {
+        let bytes = chain_datasets(&[&[1], &[2], &[3], &[4]]);
+        let fcs_file = FcsFile::from_bytes(&bytes);
+
+        let first_two = fcs_file.datasets().take(2).map(|flow_sample| fsc(&flow_sample.unwrap())).collect::<Vec<_>>();
+        assert_eq!(first_two, vec![vec![1.0], vec![2.0]]);
+        assert_eq!(fcs_file.datasets().count(), 4);
+        assert_eq!(FcsFile::open(EXAMPLE_FILE).unwrap().datasets().count(), 1);
+    }
+
+    #[test]
+    fn test_fcs_datasets_out_of_bounds() {
+        let bytes = [dataset(&[1, 2], 100_000), dataset(&[3], 0)].concat();
+        let fcs_file = FcsFile::from_bytes(&bytes);
+        let mut datasets = fcs_file.datasets();
+
+        assert_eq!(fsc(&datasets.next().unwrap().unwrap()), vec![1.0, 2.0]);
+        let (segment, offset, _, source) = unwrap_segment_error(datasets.next().unwrap().unwrap_err());
+        assert_eq!(segment, Segment::Header);
+        assert_eq!(offset, 100_000);
+        assert!(matches!(source, FcsError::InvalidNextData { offset: 100_000, .. }), "Unexpected error {:?}", source);
+        assert!(datasets.next().is_none());
+    }
+
+    #[test]
+    fn test_fcs_datasets_loop() {
+        // The second dataset points back into itself, and the first points into its own TEXT
+        let first_len = dataset(&[1], 0).len() as u64;
+        let bytes = [dataset(&[1], first_len), dataset(&[2], 20)].concat();
+        let result = FcsFile::from_bytes(&bytes).read_all();
+        let (_, offset, _, source) = unwrap_segment_error(result.unwrap_err());
+        assert_eq!(offset, first_len + 20);
+        assert!(matches!(source, FcsError::InvalidNextData { offset: 20, .. }), "Unexpected error {:?}", source);
+
+        let bytes = dataset(&[1], 30);
+        let fcs_file = FcsFile::from_bytes(&bytes);
+        let mut datasets = fcs_file.datasets();
+        assert!(datasets.next().unwrap().is_ok());
+        assert!(datasets.next().unwrap().is_err());
+        assert!(datasets.next().is_none());
+    }
+
+    #[test]
+    fn test_fcs_datasets_invalid_next_data() {
+        let bytes = dataset(&[1], 0);
+        let position = bytes.windows(10).position(|window| window == b"/$NEXTDATA").unwrap();
+        let mut bytes = bytes;
+        bytes[position + 11..position + 19].copy_from_slice(b"0000000x");
+        let result = FcsFile::from_bytes(&bytes).read_all();
+
+        let (segment, offset, _, source) = unwrap_segment_error(result.unwrap_err());
+        assert_eq!(segment, Segment::Text);
+        assert_eq!(offset, 58);
+        assert!(matches!(source, FcsError::InvalidKeyword { ref keyword, .. } if keyword == "$NEXTDATA"));
+    }
+
     #[test]
     fn test_create_dataframe() {
         let column_titles = vec!["FSC-H".to_string(), "APC-A".to_string()];
@@ -0,0 +1,530 @@
+//! Typed metadata of FCS datasets.
+//!
+//! `Metadata` holds the keywords of the TEXT and supplemental TEXT segments of a dataset, with
+//! the standard keywords parsed into typed fields: counts and ranges as numbers, dates and times
+//! as `chrono` values, `$DATATYPE` and `$BYTEORD` as enums, and the `$Pn*` keywords as one
+//! `Parameter` per parameter. It is returned by `FcsFile::read_metadata_only`, which does not
+//! read the DATA segment, and stored in `FlowSample::metadata` by the other read methods.
+
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use crate::data::{Amplification, ByteOrder, Channel, DataType, Scale};
+use crate::text::{KeywordConflict, TextSegments, TextSource};
+use crate::HashMap;
+
+/// The keywords of a dataset with the standard keywords parsed.
+///
+/// Typed fields are `None` (or empty) when their keyword is absent, blank, `NA` or cannot be
+/// parsed. Each keyword that cannot be parsed, and each failed validation check in
+/// `ParseMode::Lenient`, is described in `problems`, so a partially broken file still gives its
+/// keywords.
+///
+/// # Fields
+///
+/// * `version` - The FCS version from the header, e.g. `FCS3.1`. Empty for metadata built from
+///   keywords alone, which do not record the version.
+/// * `n_params` - The number of parameters from `$PAR`.
+/// * `n_events` - The number of events from `$TOT`.
+/// * `data_type` - The type of the stored values from `$DATATYPE`.
+/// * `byte_order` - The byte order of the stored values from `$BYTEORD`.
+/// * `cytometer` - The instrument from `$CYT`.
+/// * `cytometer_serial` - The serial number of the instrument from `$CYTSN`.
+/// * `file_name` - The name of the file from `$FIL`.
+/// * `source` - The source of the specimen from `$SRC`.
+/// * `operator` - The operator of the instrument from `$OP`.
+/// * `institution` - The institution from `$INST`.
+/// * `experimenter` - The experimenter from `$EXP`.
+/// * `project` - The project from `$PROJ`.
+/// * `comment` - The comment from `$COM`.
+/// * `date` - The acquisition date from `$DATE`, written `dd-mmm-yyyy` (or `dd-mmm-yy` in FCS
+///   2.0).
+/// * `begin_time` - The time acquisition began from `$BTIM`.
+/// * `end_time` - The time acquisition ended from `$ETIM`.
+/// * `begin_datetime` - The date-time acquisition began from `$BEGINDATETIME` (FCS 3.2).
+///   Date-times without a time zone are read as UTC.
+/// * `end_datetime` - The date-time acquisition ended from `$ENDDATETIME` (FCS 3.2).
+/// * `volume` - The volume run in nanoliters from `$VOL`.
+/// * `timestep` - The time step of the time parameter in seconds from `$TIMESTEP`.
+/// * `aborted_events` - The number of events aborted by the electronics from `$ABRT`.
+/// * `lost_events` - The number of events lost to dead time from `$LOST`.
+/// * `next_data` - The offset of the next dataset from `$NEXTDATA`, or 0 for the last one.
+/// * `channels` - The channels from `$PnN` and `$PnS`, in parameter order. Parameters without
+///   a `$PnN` keyword are left out.
+/// * `parameters` - The parameters from the `$Pn*` keywords, in the same order as `channels`.
+/// * `extra` - The non-standard keywords, which do not start with `$`, sorted by keyword.
+/// * `keywords` - Every keyword/value pair of both text segments, including those above.
+/// * `sources` - The segment each keyword of `keywords` was read from.
+/// * `conflicts` - The keywords whose supplemental value differs from the primary one.
+/// * `problems` - Descriptions of the keywords that could not be parsed or validated.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metadata {
+    pub version: String,
+    pub n_params: Option<usize>,
+    pub n_events: Option<usize>,
+    pub data_type: Option<DataType>,
+    pub byte_order: Option<ByteOrder>,
+    pub cytometer: Option<String>,
+    pub cytometer_serial: Option<String>,
+    pub file_name: Option<String>,
+    pub source: Option<String>,
+    pub operator: Option<String>,
+    pub institution: Option<String>,
+    pub experimenter: Option<String>,
+    pub project: Option<String>,
+    pub comment: Option<String>,
+    pub date: Option<NaiveDate>,
+    pub begin_time: Option<NaiveTime>,
+    pub end_time: Option<NaiveTime>,
+    pub begin_datetime: Option<DateTime<FixedOffset>>,
+    pub end_datetime: Option<DateTime<FixedOffset>>,
+    pub volume: Option<f64>,
+    pub timestep: Option<f64>,
+    pub aborted_events: Option<usize>,
+    pub lost_events: Option<usize>,
+    pub next_data: Option<usize>,
+    pub channels: Vec<Channel>,
+    pub parameters: Vec<Parameter>,
+    pub extra: BTreeMap<String, String>,
+    pub keywords: HashMap<String, String>,
+    pub sources: HashMap<String, TextSource>,
+    pub conflicts: Vec<KeywordConflict>,
+    pub problems: Vec<String>,
+}
+
+/// A parameter of a dataset, described by its `$Pn*` keywords.
+///
+/// # Fields
+///
+/// * `index` - The one-based parameter index `n`.
+/// * `name` - The short name from `$PnN`.
+/// * `label` - The label from `$PnS`, e.g. the marker or fluorochrome.
+/// * `bits` - The number of bits per value from `$PnB`, or `None` for delimited ASCII (`*`).
+/// * `data_type` - The type of the stored values from `$PnDATATYPE` (FCS 3.2), which overrides
+///   `$DATATYPE`.
+/// * `range` - The range from `$PnR`.
+/// * `amplification` - The amplification applied when raw values are converted to channel
+///   values, read from `$PnE` and `$PnG` like `Scale::from_metadata` reads them. `None` if the
+///   data type of the parameter is unknown or the keywords are invalid.
+/// * `gain` - The amplifier gain from `$PnG`.
+/// * `voltage` - The detector voltage from `$PnV`.
+/// * `filter` - The optical filter from `$PnF`.
+/// * `laser_wavelengths` - The excitation wavelengths in nanometers from `$PnL`.
+/// * `detector_type` - The detector type from `$PnT`.
+/// * `display` - The suggested display scale from `$PnD`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameter {
+    pub index: usize,
+    pub name: String,
+    pub label: Option<String>,
+    pub bits: Option<usize>,
+    pub data_type: Option<DataType>,
+    pub range: Option<f64>,
+    pub amplification: Option<Amplification>,
+    pub gain: Option<f64>,
+    pub voltage: Option<f64>,
+    pub filter: Option<String>,
+    pub laser_wavelengths: Vec<f64>,
+    pub detector_type: Option<String>,
+    pub display: Option<DisplayScale>,
+}
+
+impl Parameter {
+    /// Returns the channel of the parameter, as listed in `Metadata::channels`.
+    pub fn channel(&self) -> Channel {
+        Channel { index: self.index, name: self.name.clone(), label: self.label.clone() }
+    }
+}
+
+/// The scale suggested for displaying a parameter, given by `$PnD`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DisplayScale {
+    /// `$PnD/Linear,lower,upper/`: a linear scale from `lower` to `upper`.
+    Linear { lower: f64, upper: f64 },
+    /// `$PnD/Logarithmic,decades,offset/`: a logarithmic scale over `decades` decades starting
+    /// at `offset`.
+    Logarithmic { decades: f64, offset: f64 },
+}
+
+impl DisplayScale {
+    /// Parses a `$PnD` value. The scale name is case-insensitive.
+    pub fn from_keyword(value: &str) -> Option<Self> {
+        let mut parts = value.split(',').map(str::trim);
+        let (scale, first, second) = (parts.next()?, parts.next()?, parts.next()?);
+        if parts.next().is_some() {
+            return None;
+        }
+        let (first, second) = (first.parse::<f64>().ok()?, second.parse::<f64>().ok()?);
+
+        match scale.to_ascii_lowercase().as_str() {
+            "linear" => Some(DisplayScale::Linear { lower: first, upper: second }),
+            "logarithmic" => Some(DisplayScale::Logarithmic { decades: first, offset: second }),
+            _ => None,
+        }
+    }
+}
+
+impl Metadata {
+    /// Builds the metadata of a dataset from its FCS version and text segments.
+    ///
+    /// Keywords that cannot be parsed leave their field empty and are described in `problems`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fcs_rs::metadata::Metadata;
+    /// use fcs_rs::text::{parse_text, ParseMode, TextSegments};
+    ///
+    /// let keywords = parse_text(b"/$PAR/2/$TOT/x/$P1N/FSC-A/$P2N/SSC-A/$P2S/Granularity/", ParseMode::Strict).unwrap();
+    /// let metadata = Metadata::from_text("FCS3.1", TextSegments { keywords, ..Default::default() });
+    ///
+    /// assert_eq!(metadata.n_params, Some(2));
+    /// assert_eq!(metadata.n_events, None);
+    /// assert_eq!(metadata.parameters[1].label.as_deref(), Some("Granularity"));
+    /// assert_eq!(metadata.problems, vec!["Invalid value `x` for keyword $TOT"]);
+    /// ```
+    pub fn from_text(version: &str, text: TextSegments) -> Self {
+        let mut metadata = Self::from_keywords(text.keywords);
+        metadata.version = version.to_string();
+        metadata.sources = text.sources;
+        metadata.conflicts = text.conflicts;
+        metadata
+    }
+
+    /// Builds the metadata of a dataset from its keywords alone.
+    ///
+    /// Keywords that cannot be parsed leave their field empty and are described in `problems`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use chrono::NaiveDate;
+    /// use fcs_rs::metadata::Metadata;
+    ///
+    /// let keywords = HashMap::from([
+    ///     ("$DATE".to_string(), "24-JUN-2020".to_string()),
+    ///     ("$VOL".to_string(), "45.5".to_string()),
+    ///     ("CREATOR".to_string(), "Attune".to_string()),
+    /// ]);
+    /// let metadata = Metadata::from_keywords(keywords);
+    ///
+    /// assert_eq!(metadata.date, NaiveDate::from_ymd_opt(2020, 6, 24));
+    /// assert_eq!(metadata.volume, Some(45.5));
+    /// assert_eq!(metadata.extra["CREATOR"], "Attune");
+    /// ```
+    pub fn from_keywords(keywords: HashMap<String, String>) -> Self {
+        let mut fields = Fields { keywords: &keywords, problems: Vec::new() };
+
+        let n_params = fields.number("$PAR");
+        let data_type = fields.parse("$DATATYPE", |value| DataType::from_keyword(value).ok());
+        let mut parameters = Vec::new();
+        for index in 1..=n_params.unwrap_or(0) {
+            match fields.parameter(index, data_type) {
+                Some(parameter) => parameters.push(parameter),
+                None => fields.problems.push(format!("Missing $P{}N", index)),
+            }
+        }
+
+        let extra = keywords.iter()
+            .filter(|(keyword, _)| !keyword.starts_with('$'))
+            .map(|(keyword, value)| (keyword.clone(), value.clone()))
+            .collect();
+
+        let metadata = Self {
+            version: String::new(),
+            n_params,
+            n_events: fields.number("$TOT"),
+            data_type,
+            byte_order: fields.parse("$BYTEORD", |value| ByteOrder::from_keyword(value).ok()),
+            cytometer: fields.string("$CYT"),
+            cytometer_serial: fields.string("$CYTSN"),
+            file_name: fields.string("$FIL"),
+            source: fields.string("$SRC"),
+            operator: fields.string("$OP"),
+            institution: fields.string("$INST"),
+            experimenter: fields.string("$EXP"),
+            project: fields.string("$PROJ"),
+            comment: fields.string("$COM"),
+            date: fields.parse("$DATE", parse_date),
+            begin_time: fields.parse("$BTIM", parse_time),
+            end_time: fields.parse("$ETIM", parse_time),
+            begin_datetime: fields.parse("$BEGINDATETIME", parse_datetime),
+            end_datetime: fields.parse("$ENDDATETIME", parse_datetime),
+            volume: fields.number("$VOL"),
+            timestep: fields.number("$TIMESTEP"),
+            aborted_events: fields.number("$ABRT"),
+            lost_events: fields.number("$LOST"),
+            next_data: fields.number("$NEXTDATA"),
+            channels: parameters.iter().map(Parameter::channel).collect(),
+            parameters,
+            extra,
+            keywords: HashMap::new(),
+            sources: HashMap::new(),
+            conflicts: Vec::new(),
+            problems: fields.problems,
+        };
+
+        Self { keywords, ..metadata }
+    }
+}
+
+/// Parses the values of keywords, recording the values that cannot be parsed.
+struct Fields<'a> {
+    keywords: &'a HashMap<String, String>,
+    problems: Vec<String>,
+}
+
+impl Fields<'_> {
+    /// Parses the trimmed value of `keyword` with `parse`, recording a problem if it fails.
+    /// Blank and `NA` values, which instruments write for optional keywords they do not fill
+    /// in, are treated as absent.
+    fn parse<T>(&mut self, keyword: &str, parse: impl Fn(&str) -> Option<T>) -> Option<T> {
+        let value = self.string(keyword)?;
+        let parsed = parse(&value);
+        if parsed.is_none() {
+            self.problems.push(format!("Invalid value `{}` for keyword {}", self.keywords[keyword], keyword));
+        }
+        parsed
+    }
+
+    fn number<T: FromStr>(&mut self, keyword: &str) -> Option<T> {
+        self.parse(keyword, |value| value.parse::<T>().ok())
+    }
+
+    /// Returns the trimmed value of `keyword`, or `None` if it is absent, blank or `NA`.
+    fn string(&self, keyword: &str) -> Option<String> {
+        self.keywords.get(keyword)
+            .map(|value| value.trim())
+            .filter(|value| !value.is_empty() && *value != "NA")
+            .map(str::to_string)
+    }
+
+    /// Records `problem`, unless the same problem has been recorded already.
+    fn problem(&mut self, problem: String) {
+        if !self.problems.contains(&problem) {
+            self.problems.push(problem);
+        }
+    }
+
+    /// Reads the `$Pn*` keywords of parameter `index`, whose values are of type `default_type`
+    /// unless `$PnDATATYPE` says otherwise, or returns `None` if it has no `$PnN`.
+    fn parameter(&mut self, index: usize, default_type: Option<DataType>) -> Option<Parameter> {
+        let keyword = |suffix: &str| format!("$P{}{}", index, suffix);
+        let name = self.keywords.get(&keyword("N"))?.to_owned();
+
+        let bits = self.parse(&keyword("B"), |value| match value {
+            "*" => Some(None),
+            _ => value.parse::<usize>().ok().map(Some),
+        }).flatten();
+        let data_type = self.parse(&keyword("DATATYPE"), |value| DataType::from_keyword(value).ok());
+        let range = self.number(&keyword("R"));
+        let gain = self.number(&keyword("G"));
+        let amplification = match data_type.or(default_type) {
+            Some(data_type) => match Scale::from_metadata(self.keywords, index, data_type) {
+                Ok(scale) => Some(scale.amplification),
+                Err(err) => {
+                    self.problem(err.to_string());
+                    None
+                },
+            },
+            None => None,
+        };
+
+        Some(Parameter {
+            index,
+            name,
+            label: self.string(&keyword("S")),
+            bits,
+            data_type,
+            range,
+            amplification,
+            gain,
+            voltage: self.number(&keyword("V")),
+            filter: self.string(&keyword("F")),
+            laser_wavelengths: self.parse(&keyword("L"), |value| {
+                value.split(',').map(|wavelength| wavelength.trim().parse::<f64>().ok()).collect()
+            }).unwrap_or_default(),
+            detector_type: self.string(&keyword("T")),
+            display: self.parse(&keyword("D"), DisplayScale::from_keyword),
+        })
+    }
+}
+
+/// Parses a `$DATE` value, `dd-mmm-yyyy` or `dd-mmm-yy` with a case-insensitive month name.
+fn parse_date(value: &str) -> Option<NaiveDate> {
+    let year = value.rsplit('-').next()?;
+    let format = if year.len() == 2 { "%d-%b-%y" } else { "%d-%b-%Y" };
+    NaiveDate::parse_from_str(value, format).ok()
+}
+
+/// Parses a `$BTIM` or `$ETIM` value: `hh:mm:ss[.cc]` (FCS 3.1), or `hh:mm:ss[:tt]` where `tt`
+/// counts sixtieths of a second (FCS 2.0 and 3.0).
+fn parse_time(value: &str) -> Option<NaiveTime> {
+    let parts = value.split(':').collect::<Vec<_>>();
+    if parts.len() == 4 {
+        let time = NaiveTime::parse_from_str(&parts[..3].join(":"), "%H:%M:%S").ok()?;
+        let sixtieths = parts[3].parse::<u32>().ok().filter(|&sixtieths| sixtieths < 60)?;
+        return time.with_nanosecond((u64::from(sixtieths) * 1_000_000_000 / 60) as u32);
+    }
+    NaiveTime::parse_from_str(value, "%H:%M:%S%.f").ok()
+}
+
+/// Parses a `$BEGINDATETIME` or `$ENDDATETIME` value in ISO 8601 form, reading date-times
+/// without a time zone as UTC.
+fn parse_datetime(value: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(value).ok().or_else(|| {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f").ok()
+            .map(|datetime| datetime.and_utc().fixed_offset())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text::{parse_text, ParseMode};
+
+    fn metadata(text: &[u8]) -> Metadata {
+        let keywords = parse_text(text, ParseMode::Strict).unwrap();
+        Metadata::from_text("FCS3.1", TextSegments { keywords, ..Default::default() })
+    }
+
+    #[test]
+    fn test_metadata_from_text() {
+        let metadata = metadata(b"/$PAR/2/$TOT/ 100 /$DATATYPE/F/$BYTEORD/4,3,2,1/$CYT/ Attune NxT /$OP/ /\
+            $DATE/24-JUN-2020/$BTIM/10:31:05.25/$ETIM/10:40:00/$VOL/45.5/$NEXTDATA/0/\
+            $P1N/FSC-A/$P1S/ /$P1B/32/$P1R/1048576/$P1E/0,0/$P1G/2/$P1V/340/\
+            $P2N/CD4/$P2S/FITC/$P2B/*/$P2DATATYPE/A/$P2R/1024/$P2E/4,0/$P2F/530//30/$P2L/488,561/$P2T/PMT/$P2D/Logarithmic,4,0.1/\
+            CREATOR/Attune/P2DISPLAY/LOG/");
+
+        assert_eq!(metadata.version, "FCS3.1");
+        assert_eq!(metadata.n_params, Some(2));
+        assert_eq!(metadata.n_events, Some(100));
+        assert_eq!(metadata.data_type, Some(DataType::Float));
+        assert_eq!(metadata.byte_order, Some(ByteOrder::BigEndian));
+        assert_eq!(metadata.cytometer.as_deref(), Some("Attune NxT"));
+        assert_eq!(metadata.operator, None);
+        assert_eq!(metadata.date, NaiveDate::from_ymd_opt(2020, 6, 24));
+        assert_eq!(metadata.begin_time, NaiveTime::from_hms_milli_opt(10, 31, 5, 250));
+        assert_eq!(metadata.end_time, NaiveTime::from_hms_opt(10, 40, 0));
+        assert_eq!(metadata.volume, Some(45.5));
+        assert_eq!(metadata.next_data, Some(0));
+        assert_eq!(metadata.parameters, vec![
+            Parameter {
+                index: 1,
+                name: "FSC-A".to_string(),
+                label: None,
+                bits: Some(32),
+                data_type: None,
+                range: Some(1048576.0),
+                amplification: Some(Amplification::Linear { gain: 2.0 }),
+                gain: Some(2.0),
+                voltage: Some(340.0),
+                filter: None,
+                laser_wavelengths: vec![],
+                detector_type: None,
+                display: None,
+            },
+            Parameter {
+                index: 2,
+                name: "CD4".to_string(),
+                label: Some("FITC".to_string()),
+                bits: None,
+                data_type: Some(DataType::Ascii),
+                range: Some(1024.0),
+                amplification: Some(Amplification::Logarithmic { decades: 4.0, offset: 1.0 }),
+                gain: None,
+                voltage: None,
+                filter: Some("530/30".to_string()),
+                laser_wavelengths: vec![488.0, 561.0],
+                detector_type: Some("PMT".to_string()),
+                display: Some(DisplayScale::Logarithmic { decades: 4.0, offset: 0.1 }),
+            },
+        ]);
+        assert_eq!(metadata.channels, vec![
+            Channel { index: 1, name: "FSC-A".to_string(), label: None },
+            Channel { index: 2, name: "CD4".to_string(), label: Some("FITC".to_string()) },
+        ]);
+        assert_eq!(metadata.extra.keys().collect::<Vec<_>>(), vec!["CREATOR", "P2DISPLAY"]);
+        assert_eq!(metadata.keywords.len(), 30);
+        assert!(metadata.problems.is_empty(), "{:?}", metadata.problems);
+    }
+
+    #[test]
+    fn test_metadata_from_broken_text() {
+        let metadata = metadata(b"/$PAR/3/$DATATYPE/Q/$BYTEORD/1,1/$DATE/2022-01-01/$P1N/FSC-A/$P1R/big/$P3N/SSC-A/$P3D/Log,1/");
+
+        assert_eq!(metadata.n_params, Some(3));
+        assert_eq!(metadata.n_events, None);
+        assert_eq!(metadata.data_type, None);
+        assert_eq!(metadata.byte_order, None);
+        assert_eq!(metadata.date, None);
+        assert_eq!(metadata.parameters.iter().map(|parameter| parameter.index).collect::<Vec<_>>(), vec![1, 3]);
+        assert_eq!(metadata.parameters[0].range, None);
+        assert_eq!(metadata.problems, vec![
+            "Invalid value `Q` for keyword $DATATYPE",
+            "Invalid value `big` for keyword $P1R",
+            "Missing $P2N",
+            "Invalid value `Log,1` for keyword $P3D",
+            "Invalid value `1,1` for keyword $BYTEORD",
+            "Invalid value `2022-01-01` for keyword $DATE",
+        ]);
+
+        let metadata = self::metadata(b"/$PAR/many/$P1N/FSC-A/");
+        assert_eq!(metadata.n_params, None);
+        assert!(metadata.parameters.is_empty());
+    }
+
+    #[test]
+    fn test_metadata_unset_values() {
+        // Instruments write blank or `NA` values for optional keywords they do not fill in
+        let metadata = metadata(b"/$PAR/1/$DATATYPE/F/$P1N/A/$P1V/NA/$P1L/ NA /$P1F/ /$OP/NA/$VOL/ /");
+        assert_eq!(metadata.parameters[0].voltage, None);
+        assert!(metadata.parameters[0].laser_wavelengths.is_empty());
+        assert_eq!(metadata.parameters[0].filter, None);
+        assert_eq!(metadata.operator, None);
+        assert_eq!(metadata.volume, None);
+        assert!(metadata.problems.is_empty(), "{:?}", metadata.problems);
+    }
+
+    #[test]
+    fn test_parameter_amplification() {
+        // The amplification is the one applied when values are converted, so logarithmic
+        // amplification only applies to integer and ASCII parameters
+        let metadata = metadata(b"/$PAR/3/$DATATYPE/F/$P1N/A/$P1E/4,1/$P1R/1024/\
+            $P2N/B/$P2DATATYPE/I/$P2E/4,1/$P2R/1024/$P3N/C/$P3DATATYPE/I/$P3E/4,1/");
+        let amplifications = metadata.parameters.iter().map(|parameter| parameter.amplification).collect::<Vec<_>>();
+        assert_eq!(amplifications, vec![
+            Some(Amplification::Linear { gain: 1.0 }),
+            Some(Amplification::Logarithmic { decades: 4.0, offset: 1.0 }),
+            None,
+        ]);
+        assert_eq!(metadata.problems, vec!["Invalid value `` for keyword $P3R"]);
+
+        // Without a data type the amplification is unknown
+        let metadata = self::metadata(b"/$PAR/1/$P1N/A/$P1E/0,0/");
+        assert_eq!(metadata.parameters[0].amplification, None);
+        assert!(metadata.problems.is_empty());
+    }
+
+    #[test]
+    fn test_parse_dates_and_times() {
+        assert_eq!(parse_date("01-jan-2021"), NaiveDate::from_ymd_opt(2021, 1, 1));
+        assert_eq!(parse_date("15-Dec-98"), NaiveDate::from_ymd_opt(1998, 12, 15));
+        assert_eq!(parse_date("2021-01-01"), None);
+
+        assert_eq!(parse_time("09:05:30"), NaiveTime::from_hms_opt(9, 5, 30));
+        assert_eq!(parse_time("09:05:30.50"), NaiveTime::from_hms_milli_opt(9, 5, 30, 500));
+        assert_eq!(parse_time("09:05:30:30"), NaiveTime::from_hms_milli_opt(9, 5, 30, 500));
+        assert_eq!(parse_time("09:05:30:60"), None);
+        assert_eq!(parse_time("10:00"), None);
+
+        let offset = FixedOffset::east_opt(2 * 3600).unwrap();
+        let expected = NaiveDate::from_ymd_opt(2023, 4, 1).unwrap().and_hms_milli_opt(9, 30, 0, 500).unwrap();
+        assert_eq!(parse_datetime("2023-04-01T09:30:00.5+02:00"), Some(expected.and_local_timezone(offset).unwrap()));
+        assert_eq!(parse_datetime("2023-04-01T07:30:00.5Z"), Some(expected.and_local_timezone(offset).unwrap()));
+        assert_eq!(parse_datetime("2023-04-01T07:30:00.5"), Some(expected.and_local_timezone(offset).unwrap()));
+        assert_eq!(parse_datetime("24-May-2017 14:30"), None);
+    }
+}
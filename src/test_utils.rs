@@ -0,0 +1,39 @@
+//! Helpers for building synthetic FCS files in unit tests.
+
+use crate::header::Header;
+
+/// Builds an FCS file in memory from TEXT keywords and a raw DATA segment.
+///
+/// The TEXT segment starts directly after the header and uses `/` as its delimiter; keywords
+/// and values are written as given, without escaping. When `data_keywords` is set, `$BEGINDATA`
+/// and `$ENDDATA` are appended to TEXT with fixed-width values so they can point past it.
+pub(crate) fn build_fcs(version: &str, keywords: &[(&str, &str)], data: &[u8], data_keywords: bool) -> Vec<u8> {
+    let mut text = String::new();
+    for (keyword, value) in keywords {
+        text.push_str(&format!("/{}/{}", keyword, value));
+    }
+
+    let offsets_len = if data_keywords { "/$BEGINDATA/00000000/$ENDDATA/00000000".len() } else { 0 };
+    let text_end = 58 + text.len() + offsets_len;
+    let (data_start, data_end) = if data.is_empty() {
+        (0, 0)
+    } else {
+        (text_end + 1, text_end + data.len())
+    };
+    if data_keywords {
+        text.push_str(&format!("/$BEGINDATA/{:08}/$ENDDATA/{:08}", data_start, data_end));
+    }
+    text.push('/');
+
+    let header = Header {
+        version: version.to_string(),
+        text_offsets: 58..=text_end,
+        data_offsets: data_start..=data_end,
+        analysis_offsets: 0..=0,
+    };
+
+    let mut bytes = header.to_string().into_bytes();
+    bytes.extend(text.into_bytes());
+    bytes.extend(data);
+    bytes
+}
@@ -6,19 +6,35 @@ use crate::{
     read_header, 
     Seek, 
     Read, 
-    REQUIRED_KEYWORDS, 
-    ReadBytesExt,
-    File
+    keyword_rules, 
+    Segment, 
 };
+use crate::header::{check_offsets, resolve_offsets, Header};
+use crate::data::keyword_offset;
+use std::ops::RangeInclusive;
+
+/// How strictly a text segment is checked against the FCS standard.
+///
+/// * `Strict` - Any deviation from FCS 3.1 §3.2 is an error.
+/// * `Lenient` - Common deviations are tolerated so a partially broken file can still be read.
+///
+/// See `parse_text` for the deviations each mode accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParseMode {
+    #[default]
+    Strict,
+    Lenient,
+}
 
 /// Reads the text segment of the FCS file and returns a HashMap containing metadata.
 ///
 /// The text segment contains key-value pairs of metadata information about the FCS file.
-/// This function reads the text segment, parses it, and validates the extracted metadata.
+/// This function reads the text segment, merges in the keywords of the supplemental text
+/// segment if there is one, and validates the extracted metadata.
 ///
 /// # Arguments
 ///
-/// * `reader` - A mutable reference to a BufReader wrapping the FCS file.
+/// * `reader` - A mutable reference to a BufReader wrapping the FCS file or any other `Read + Seek` source.
 ///
 /// # Returns
 ///
@@ -30,6 +46,8 @@ use crate::{
 /// - There is an I/O error during reading.
 /// - The text segment cannot be converted to a UTF-8 string.
 /// - The metadata validation fails.
+/// - The segment offsets in the header and TEXT disagree or lie outside the file (see
+///   `resolve_offsets`).
 ///
 /// # Examples
 ///
@@ -43,46 +61,485 @@ use crate::{
 /// let metadata = read_metadata(&mut reader).unwrap();
 /// println!("{:?}", metadata);
 /// ```
-pub fn read_metadata(reader: &mut BufReader<&File>) -> Result<HashMap<String, String>, FcsError> {
+pub fn read_metadata<R: Read + Seek>(reader: &mut BufReader<R>) -> Result<HashMap<String, String>, FcsError> {
     let header = read_header(reader)?;
-    let text_offset = header.text_offsets;
-    let mut metadata: HashMap<String, String> = HashMap::new();
+    let metadata = read_text(reader, &header)?;
+
+    let file_len = reader.seek(SeekFrom::End(0)).map_err(FcsError::IoError)?;
+    resolve_offsets(&header, &metadata, file_len)?;
+
+    Ok(metadata)
+}
+
+/// Reads the text segment located by an already parsed header and returns its metadata.
+///
+/// This is the second half of `read_metadata`, for callers that need to handle the header
+/// and text segments separately. The text segments are parsed in `ParseMode::Strict`, so a
+/// keyword with different values in the primary and supplemental segments is an error.
+///
+/// # Arguments
+///
+/// * `reader` - A mutable reference to a BufReader wrapping the FCS file or any other `Read + Seek` source.
+/// * `header` - The header of the FCS file, which holds the text segment offsets.
+///
+/// # Returns
+///
+/// A Result containing a HashMap of metadata key-value pairs or an FcsError.
+///
+/// # Errors
+///
+/// This function will return an FcsError if:
+/// - The text segment offsets in the header are empty or reversed.
+/// - There is an I/O error during reading.
+/// - The text segment is malformed or cannot be converted to a UTF-8 string.
+/// - The metadata validation fails.
+///
+/// # Examples
+///
+/// ```
+/// use fcs_rs::header::read_header;
+/// use fcs_rs::text::read_text;
+/// use std::fs::File;
+/// use std::io::BufReader;
+/// 
+/// let file = File::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+/// let mut reader = BufReader::new(&file);
+/// let header = read_header(&mut reader).unwrap();
+/// let metadata = read_text(&mut reader, &header).unwrap();
+/// println!("{:?}", metadata);
+/// ```
+pub fn read_text<R: Read + Seek>(reader: &mut BufReader<R>, header: &Header) -> Result<HashMap<String, String>, FcsError> {
+    read_text_with_mode(reader, header, ParseMode::Strict)
+}
+
+/// Reads the text segment located by an already parsed header using the given parse mode.
+///
+/// Keywords of the supplemental text segment are merged in as described in
+/// `read_text_segments`.
+///
+/// # Arguments
+///
+/// * `reader` - A mutable reference to a BufReader wrapping the FCS file or any other `Read + Seek` source.
+/// * `header` - The header of the FCS file, which holds the text segment offsets.
+/// * `mode` - How strictly the text segment is checked. See `parse_text`.
+///
+/// # Returns
+///
+/// A Result containing a HashMap of metadata key-value pairs or an FcsError.
+///
+/// # Errors
+///
+/// This function returns the same errors as `read_text_segments`.
+pub fn read_text_with_mode<R: Read + Seek>(
+    reader: &mut BufReader<R>, 
+    header: &Header, 
+    mode: ParseMode,
+) -> Result<HashMap<String, String>, FcsError> {
+    read_text_segments(reader, header, mode).map(|text| text.keywords)
+}
+
+/// The text segment a keyword was read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextSource {
+    Primary,
+    Supplemental,
+}
+
+/// A keyword that the primary and supplemental text segments give different values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeywordConflict {
+    pub keyword: String,
+    pub primary: String,
+    pub supplemental: String,
+}
+
+/// The keywords of the primary and supplemental text segments of a dataset.
+///
+/// # Fields
+///
+/// * `keywords` - The merged keyword/value pairs of both segments.
+/// * `sources` - The segment each keyword of `keywords` was read from.
+/// * `conflicts` - The keywords whose supplemental value differs from the primary one, sorted
+///   by keyword. Only `ParseMode::Lenient` records conflicts; strict mode rejects them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TextSegments {
+    pub keywords: HashMap<String, String>,
+    pub sources: HashMap<String, TextSource>,
+    pub conflicts: Vec<KeywordConflict>,
+}
+
+/// Reads the primary text segment and the supplemental text segment of a dataset.
+///
+/// The supplemental segment is located by the `$BEGINSTEXT` and `$ENDSTEXT` keywords of the
+/// primary segment and is skipped when they are absent or zero, or when they point at the
+/// primary segment itself. It uses the delimiter of the primary segment. A keyword that both
+/// segments give the same value counts as primary. The merged keywords are validated with
+/// `validate_text`.
+///
+/// In `ParseMode::Lenient` a supplemental segment that does not start with the delimiter is read
+/// as if it did, unreadable `$BEGINSTEXT` or `$ENDSTEXT` values are ignored, and a keyword with
+/// different values keeps its primary value and is recorded in `conflicts`.
+///
+/// # Arguments
+///
+/// * `reader` - A mutable reference to a BufReader wrapping the FCS file or any other `Read + Seek` source.
+/// * `header` - The header of the FCS file, which holds the text segment offsets.
+/// * `mode` - How strictly the text segments are checked. See `parse_text`.
+///
+/// # Returns
+///
+/// A Result containing the merged keywords with their sources, or an FcsError.
+///
+/// # Errors
+///
+/// This function will return an FcsError if:
+/// - The text segment offsets in the header are empty or reversed.
+/// - A text segment extends past the end of the file (`FcsError::InvalidOffsets`).
+/// - There is an I/O error during reading.
+/// - Either text segment is malformed or cannot be converted to a UTF-8 string.
+/// - In strict mode, `$BEGINSTEXT` or `$ENDSTEXT` cannot be read (`FcsError::InvalidKeyword`)
+///   or a keyword has different values in the two segments (`FcsError::ConflictingKeyword`).
+/// - The metadata validation fails.
+///
+/// # Examples
+///
+/// ```
+/// use fcs_rs::header::read_header;
+/// use fcs_rs::text::{read_text_segments, ParseMode, TextSource};
+/// use std::fs::File;
+/// use std::io::BufReader;
+/// 
+/// let file = File::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+/// let mut reader = BufReader::new(&file);
+/// let header = read_header(&mut reader).unwrap();
+/// let text = read_text_segments(&mut reader, &header, ParseMode::Strict).unwrap();
+/// assert_eq!(text.sources["$PAR"], TextSource::Primary);
+/// assert!(text.conflicts.is_empty());
+/// ```
+pub fn read_text_segments<R: Read + Seek>(
+    reader: &mut BufReader<R>, 
+    header: &Header, 
+    mode: ParseMode,
+) -> Result<TextSegments, FcsError> {
+    let text = read_unvalidated_text_segments(reader, header, mode)?;
+    validate_text(&text.keywords, &header.version)?;
+    Ok(text)
+}
+
+/// Reads the text segments like `read_text_segments`, without checking the keywords with
+/// `validate_text`.
+pub(crate) fn read_unvalidated_text_segments<R: Read + Seek>(
+    reader: &mut BufReader<R>, 
+    header: &Header, 
+    mode: ParseMode,
+) -> Result<TextSegments, FcsError> {
+    let text_offset = &header.text_offsets;
+
+    if text_offset.end() <= text_offset.start() {
+        return Err(FcsError::InvalidHeader);
+    }
+    let file_len = reader.seek(SeekFrom::End(0)).map_err(FcsError::IoError)?;
+    check_offsets(Segment::Text, text_offset, file_len)?;
+
+    let primary = read_segment(reader, text_offset)?;
+    let keywords = parse_text(&primary, mode)?;
+    let sources = keywords.keys().map(|keyword| (keyword.clone(), TextSource::Primary)).collect();
+    let mut text = TextSegments { keywords, sources, conflicts: Vec::new() };
 
-    let bytes_to_read = text_offset.end() - text_offset.start() - 1;
-    let mut buffer = vec![0u8; bytes_to_read];
-    reader.seek(SeekFrom::Start(*text_offset.start() as u64)).map_err(FcsError::IoError)?;
+    if let Some(offsets) = supplemental_offsets(&text.keywords, mode)? {
+        if offsets != *text_offset {
+            check_offsets(Segment::SupplementalText, &offsets, file_len)?;
+            let mut supplemental = read_segment(reader, &offsets)?;
+            if supplemental.first() != Some(&primary[0]) {
+                if mode == ParseMode::Strict {
+                    return Err(FcsError::MalformedText(
+                        "The supplemental TEXT segment does not start with the delimiter".to_string(),
+                    ));
+                }
+                supplemental.insert(0, primary[0]);
+            }
+            merge_supplemental(&mut text, parse_text(&supplemental, mode)?, mode)?;
+        }
+    }
+
+    Ok(text)
+}
 
-    let delimiter = reader.read_u8().map_err(FcsError::IoError)? as char;
+/// Reads the bytes of a segment, including its last byte.
+fn read_segment<R: Read + Seek>(reader: &mut BufReader<R>, offsets: &RangeInclusive<usize>) -> Result<Vec<u8>, FcsError> {
+    let mut buffer = vec![0u8; offsets.end() - offsets.start() + 1];
+    reader.seek(SeekFrom::Start(*offsets.start() as u64)).map_err(FcsError::IoError)?;
     reader.read_exact(&mut buffer).map_err(FcsError::IoError)?;
+    Ok(buffer)
+}
+
+/// Returns the offsets of the supplemental text segment from `$BEGINSTEXT` and `$ENDSTEXT`, or
+/// `None` if there is none.
+fn supplemental_offsets(
+    keywords: &HashMap<String, String>, 
+    mode: ParseMode,
+) -> Result<Option<RangeInclusive<usize>>, FcsError> {
+    let offset = |keyword: &str| -> Result<Option<usize>, FcsError> {
+        match keywords.get(keyword) {
+            None => Ok(None),
+            Some(value) => match value.trim().parse::<usize>() {
+                Ok(offset) => Ok(Some(offset)),
+                Err(_) if mode == ParseMode::Lenient => Ok(None),
+                Err(_) => Err(FcsError::InvalidKeyword { keyword: keyword.to_string(), value: value.clone() }),
+            },
+        }
+    };
+
+    match (offset("$BEGINSTEXT")?, offset("$ENDSTEXT")?) {
+        (Some(start), Some(end)) if end > 0 && end >= start => Ok(Some(start..=end)),
+        (Some(_), Some(end)) if end > 0 && mode == ParseMode::Strict => Err(FcsError::InvalidKeyword {
+            keyword: "$ENDSTEXT".to_string(),
+            value: keywords["$ENDSTEXT"].clone(),
+        }),
+        _ => Ok(None),
+    }
+}
+
+/// Adds the keywords of the supplemental text segment to `text`, keeping primary values.
+fn merge_supplemental(
+    text: &mut TextSegments, 
+    supplemental: HashMap<String, String>, 
+    mode: ParseMode,
+) -> Result<(), FcsError> {
+    let mut supplemental: Vec<(String, String)> = supplemental.into_iter().collect();
+    supplemental.sort();
+
+    for (keyword, value) in supplemental {
+        match text.keywords.get(&keyword) {
+            None => {
+                text.sources.insert(keyword.clone(), TextSource::Supplemental);
+                text.keywords.insert(keyword, value);
+            },
+            Some(primary) if *primary == value => {},
+            Some(primary) => {
+                let conflict = KeywordConflict { keyword, primary: primary.clone(), supplemental: value };
+                if mode == ParseMode::Strict {
+                    return Err(FcsError::ConflictingKeyword {
+                        keyword: conflict.keyword,
+                        primary: conflict.primary,
+                        supplemental: conflict.supplemental,
+                    });
+                }
+                text.conflicts.push(conflict);
+            },
+        }
+    }
 
-    let text = String::from_utf8(buffer).map_err(|_| FcsError::InvalidMetadata)?;
+    Ok(())
+}
 
-    let mut keyword = String::new();
-    let mut value = String::new();
-    let kv_pairs = text.split(delimiter);
+/// Returns the byte offsets of the analysis segment.
+///
+/// Like `data_offsets`, the `$BEGINANALYSIS` and `$ENDANALYSIS` keywords are used when they are
+/// present and non-zero, and the header offsets otherwise. An end offset of zero means the
+/// dataset has no analysis segment.
+///
+/// # Arguments
+///
+/// * `header` - The header of the FCS file.
+/// * `metadata` - A reference to a HashMap containing metadata from the FCS file.
+///
+/// # Returns
+///
+/// The offsets of the first and last byte of the analysis segment.
+pub fn analysis_offsets(header: &Header, metadata: &HashMap<String, String>) -> RangeInclusive<usize> {
+    match (keyword_offset(metadata, "$BEGINANALYSIS"), keyword_offset(metadata, "$ENDANALYSIS")) {
+        (Some(start), Some(end)) if end > 0 => start..=end,
+        _ => header.analysis_offsets.clone(),
+    }
+}
 
-    for kv in kv_pairs {
-        if kv.starts_with('$') {
-            keyword = kv.to_string();
-            value.clear();
+/// Reads the keyword/value pairs of the analysis segment.
+///
+/// The analysis segment uses the same delimited format as the text segment and is parsed with
+/// `parse_text` in `ParseMode::Strict`. Its location comes from `analysis_offsets`.
+///
+/// # Arguments
+///
+/// * `reader` - A mutable reference to a BufReader wrapping the FCS file or any other `Read + Seek` source.
+/// * `header` - The header of the FCS file.
+/// * `metadata` - A reference to a HashMap containing metadata from the FCS file.
+///
+/// # Returns
+///
+/// A Result containing a HashMap of the analysis key-value pairs, which is empty if the dataset
+/// has no analysis segment, or an FcsError.
+///
+/// # Errors
+///
+/// This function will return an FcsError if there is an I/O error during reading, if the
+/// segment does not lie within the file, or if the segment is malformed or cannot be converted
+/// to a UTF-8 string.
+///
+/// # Examples
+///
+/// ```
+/// use fcs_rs::header::read_header;
+/// use fcs_rs::text::{read_analysis, read_text};
+/// use std::fs::File;
+/// use std::io::BufReader;
+/// 
+/// let file = File::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+/// let mut reader = BufReader::new(&file);
+/// let header = read_header(&mut reader).unwrap();
+/// let metadata = read_text(&mut reader, &header).unwrap();
+/// let analysis = read_analysis(&mut reader, &header, &metadata).unwrap();
+/// assert!(analysis.is_empty());
+/// ```
+pub fn read_analysis<R: Read + Seek>(
+    reader: &mut BufReader<R>, 
+    header: &Header, 
+    metadata: &HashMap<String, String>,
+) -> Result<HashMap<String, String>, FcsError> {
+    let offsets = analysis_offsets(header, metadata);
+    if *offsets.end() == 0 {
+        return Ok(HashMap::new());
+    }
+    if offsets.end() < offsets.start() {
+        return Err(FcsError::InvalidKeyword {
+            keyword: "$ENDANALYSIS".to_string(),
+            value: offsets.end().to_string(),
+        });
+    }
+    let file_len = reader.seek(SeekFrom::End(0)).map_err(FcsError::IoError)?;
+    check_offsets(Segment::Analysis, &offsets, file_len)?;
+
+    parse_text(&read_segment(reader, &offsets)?, ParseMode::Strict)
+}
+
+/// Splits a text segment into its keyword/value pairs following FCS 3.1 §3.2.
+///
+/// The first byte of the segment is the delimiter. Keywords and values alternate, each followed
+/// by the delimiter, and a delimiter inside a keyword or value is escaped by doubling it. The
+/// segment is split on bytes, so the delimiter may be any ASCII character, including a backslash,
+/// and may sit next to multi-byte UTF-8 characters. Keywords are case insensitive and are
+/// returned in upper case; values are returned as they are.
+///
+/// In `ParseMode::Strict` the following are errors. In `ParseMode::Lenient` they are tolerated
+/// as described:
+/// - A doubled delimiter directly after a keyword. Leniently read as an empty value rather than
+///   an escaped delimiter, as written by some instruments.
+/// - A keyword without a value at the end of the segment. Leniently dropped.
+/// - An empty keyword. Leniently skipped along with its value.
+/// - A segment that does not end with the delimiter. Leniently read up to its last byte.
+/// - A keyword that appears more than once. Leniently, the last value wins.
+/// - Bytes that are not valid UTF-8. Leniently replaced with U+FFFD.
+///
+/// # Arguments
+///
+/// * `text` - The raw text segment, starting with the delimiter.
+/// * `mode` - How strictly the segment is checked.
+///
+/// # Returns
+///
+/// A Result containing a HashMap of metadata key-value pairs or an FcsError.
+///
+/// # Errors
+///
+/// Returns `FcsError::MalformedText` if the segment is empty or, in strict mode, for any of the
+/// deviations above, except invalid UTF-8 which returns `FcsError::InvalidMetadata`.
+///
+/// # Examples
+///
+/// ```
+/// use fcs_rs::text::{parse_text, ParseMode};
+///
+/// let metadata = parse_text(b"\\$p1n\\FSC-A\\$FIL\\C:\\\\data\\", ParseMode::Strict).unwrap();
+/// assert_eq!(metadata["$P1N"], "FSC-A");
+/// assert_eq!(metadata["$FIL"], "C:\\data");
+/// ```
+pub fn parse_text(text: &[u8], mode: ParseMode) -> Result<HashMap<String, String>, FcsError> {
+    let lenient = mode == ParseMode::Lenient;
+    let (&delimiter, body) = text.split_first()
+        .ok_or_else(|| FcsError::MalformedText("The TEXT segment is empty".to_string()))?;
+
+    let mut tokens: Vec<Vec<u8>> = Vec::new();
+    let mut current: Vec<u8> = Vec::new();
+    let mut i = 0;
+    while i < body.len() {
+        if body[i] != delimiter {
+            current.push(body[i]);
+            i += 1;
+            continue;
+        }
+
+        let doubled = body.get(i + 1) == Some(&delimiter);
+        let reading_keyword = tokens.len().is_multiple_of(2);
+        if doubled && lenient && reading_keyword && !current.is_empty() {
+            // The keyword ends here and is followed by an empty value
+            tokens.push(std::mem::take(&mut current));
+            tokens.push(Vec::new());
+            i += 2;
+        } else if doubled {
+            current.push(delimiter);
+            i += 2;
         } else {
-            value.push_str(kv);
-            metadata.insert(keyword.clone(), value.clone());
+            tokens.push(std::mem::take(&mut current));
+            i += 1;
+        }
+    }
+
+    if !current.is_empty() {
+        if !lenient {
+            return Err(FcsError::MalformedText("The TEXT segment does not end with its delimiter".to_string()));
+        }
+        tokens.push(current);
+    }
+
+    if tokens.len() % 2 == 1 {
+        if !lenient {
+            let keyword = String::from_utf8_lossy(&tokens[tokens.len() - 1]).into_owned();
+            return Err(FcsError::MalformedText(format!("Keyword {} has no value", keyword)));
+        }
+        tokens.pop();
+    }
+
+    let mut metadata: HashMap<String, String> = HashMap::new();
+    for pair in tokens.chunks_exact(2) {
+        let keyword = decode_token(&pair[0], lenient)?.to_ascii_uppercase();
+        let value = decode_token(&pair[1], lenient)?;
+
+        let keyword = if lenient { keyword.trim().to_string() } else { keyword };
+        if keyword.trim().is_empty() {
+            if lenient {
+                continue;
+            }
+            return Err(FcsError::MalformedText(format!("Empty keyword with value {}", value)));
+        }
+
+        if metadata.insert(keyword.clone(), value).is_some() && !lenient {
+            return Err(FcsError::MalformedText(format!("Keyword {} appears more than once", keyword)));
         }
     }
 
-    validate_text(&metadata)?;
     Ok(metadata)
 }
 
+/// Converts a keyword or value to a string, replacing invalid UTF-8 in lenient mode.
+fn decode_token(token: &[u8], lenient: bool) -> Result<String, FcsError> {
+    if lenient {
+        Ok(String::from_utf8_lossy(token).into_owned())
+    } else {
+        String::from_utf8(token.to_vec()).map_err(|_| FcsError::InvalidMetadata)
+    }
+}
+
 /// Validates that the required keys are present in the text segment of the FCS file.
 ///
-/// This function checks for the presence of required metadata keys in the text segment.
-/// If any required key is missing, it returns an `FcsError::InvalidText`.
+/// This function checks for the presence of the metadata keys that the given FCS version
+/// requires, as described by `keyword_rules`. If any required key is missing, it returns an
+/// `FcsError::InvalidText`. For FCS 3.2 it also checks that `$BEGINDATETIME` and
+/// `$ENDDATETIME`, when present, hold ISO 8601 date-times.
 ///
 /// # Arguments
 ///
 /// * `text` - A reference to a HashMap containing metadata key-value pairs from the FCS file.
+/// * `version` - The FCS version from the header, e.g. `FCS3.1`.
 ///
 /// # Returns
 ///
@@ -91,7 +548,9 @@ pub fn read_metadata(reader: &mut BufReader<&File>) -> Result<HashMap<String, St
 /// # Errors
 ///
 /// This function will return an `FcsError::InvalidText` if any required metadata key is missing.
-/// It will also return `FcsError::InvalidText` if the `$PAR` key cannot be parsed or is missing.
+/// It will also return `FcsError::InvalidText` if the `$PAR` key cannot be parsed or is missing,
+/// `FcsError::InvalidVersion` if the version is not supported, and `FcsError::InvalidKeyword`
+/// if a FCS 3.2 date-time keyword is malformed.
 ///
 /// # Examples
 ///
@@ -108,21 +567,23 @@ pub fn read_metadata(reader: &mut BufReader<&File>) -> Result<HashMap<String, St
 /// let file = File::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
 /// let mut reader = BufReader::new(&file);
 /// let metadata: HashMap<String, String> = read_metadata(&mut reader).unwrap();
-/// validate_text(&metadata).unwrap();
+/// validate_text(&metadata, "FCS3.1").unwrap();
 /// ```
-pub fn validate_text(text: &HashMap<String, String>) -> Result<(), FcsError> {
+pub fn validate_text(text: &HashMap<String, String>, version: &str) -> Result<(), FcsError> {
+    let rules = keyword_rules(version)?;
     let n_params: u32 = text.get("$PAR")
         .ok_or_else(|| FcsError::InvalidText("$PAR".to_string()))?
+        .trim()
         .parse()
         .map_err(|_| FcsError::InvalidText("$PAR".to_string()))?;
 
-    for &non_param in &REQUIRED_KEYWORDS[..12] {
+    for &non_param in rules.required {
         if text.get(non_param).is_none() {
             return Err(FcsError::InvalidText(non_param.to_string()));
         }
     }
 
-    for &param in &REQUIRED_KEYWORDS[12..] {
+    for &param in rules.required_parameter {
         for i in 1..=n_params {
             if text.get(&param.replace('n', &i.to_string())).is_none() {
                 return Err(FcsError::InvalidText(param.to_string()));
@@ -130,13 +591,70 @@ pub fn validate_text(text: &HashMap<String, String>) -> Result<(), FcsError> {
         }
     }
 
+    if version == "FCS3.2" {
+        for keyword in ["$BEGINDATETIME", "$ENDDATETIME"] {
+            if let Some(value) = text.get(keyword) {
+                if !is_iso_datetime(value.trim()) {
+                    return Err(FcsError::InvalidKeyword {
+                        keyword: keyword.to_string(),
+                        value: value.to_string(),
+                    });
+                }
+            }
+        }
+    }
+
     Ok(())
 }
 
+/// Checks that a value has the `YYYY-MM-DDThh:mm:ss[.fff][Z|+hh:mm|-hh:mm]` form FCS 3.2
+/// uses for `$BEGINDATETIME` and `$ENDDATETIME`.
+fn is_iso_datetime(value: &str) -> bool {
+    fn digits(part: &str, len: usize) -> bool {
+        part.len() == len && part.bytes().all(|b| b.is_ascii_digit())
+    }
+
+    let Some((date, time)) = value.split_once('T') else {
+        return false;
+    };
+
+    let date_parts: Vec<&str> = date.split('-').collect();
+    if date_parts.len() != 3
+        || !digits(date_parts[0], 4)
+        || !digits(date_parts[1], 2)
+        || !digits(date_parts[2], 2)
+    {
+        return false;
+    }
+
+    let (time, zone) = match time.find(['Z', '+', '-']) {
+        Some(position) => time.split_at(position),
+        None => (time, ""),
+    };
+    let (time, fraction) = time.split_once('.').unwrap_or((time, "0"));
+    let time_parts: Vec<&str> = time.split(':').collect();
+    if time_parts.len() != 3
+        || !time_parts.iter().all(|part| digits(part, 2))
+        || fraction.is_empty()
+        || !fraction.bytes().all(|b| b.is_ascii_digit())
+    {
+        return false;
+    }
+
+    match zone {
+        "" | "Z" => true,
+        _ => {
+            let offset = &zone[1..];
+            offset.split(':').count() == 2 && offset.split(':').all(|part| digits(part, 2))
+        },
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::FcsFile;
+    use std::fs::File;
     use std::io::BufReader;
 
     #[test]
@@ -161,10 +679,373 @@ mod tests {
         assert!(result.is_ok(), "Metadata read failed: {:?}", result.err());
         let metadata = result.unwrap();
 
-        let result = validate_text(&metadata);
+        let result = validate_text(&metadata, "FCS3.1");
         assert!(result.is_ok(), "Text validation failed: {:?}", result.err());
     }
 
+    fn keywords(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_validate_text_fcs2_0() {
+        let text = keywords(&[
+            ("$BYTEORD", "1,2"),
+            ("$DATATYPE", "I"),
+            ("$MODE", "L"),
+            ("$NEXTDATA", "0"),
+            ("$PAR", "1"),
+            ("$P1B", "16"),
+            ("$P1N", "FSC"),
+            ("$P1R", "1024"),
+        ]);
+
+        assert!(validate_text(&text, "FCS2.0").is_ok());
+        // FCS 3.0 and 3.1 require the segment offsets and $TOT
+        assert!(matches!(validate_text(&text, "FCS3.1"), Err(FcsError::InvalidText(_))));
+    }
+
+    #[test]
+    fn test_validate_text_fcs3_2() {
+        let mut text = keywords(&[
+            ("$BEGINDATA", "100"),
+            ("$BYTEORD", "1,2,3,4"),
+            ("$DATATYPE", "F"),
+            ("$ENDDATA", "107"),
+            ("$NEXTDATA", "0"),
+            ("$PAR", "1"),
+            ("$TOT", "2"),
+            ("$P1B", "32"),
+            ("$P1E", "0,0"),
+            ("$P1N", "FSC"),
+            ("$P1R", "1024"),
+        ]);
+
+        let result = validate_text(&text, "FCS3.2");
+        assert!(matches!(result, Err(FcsError::InvalidText(ref keyword)) if keyword == "$CYT"));
+
+        text.insert("$CYT".to_string(), "Test Cytometer".to_string());
+        assert!(validate_text(&text, "FCS3.2").is_ok());
+
+        text.insert("$BEGINDATETIME".to_string(), "24-May-2017 14:30".to_string());
+        let result = validate_text(&text, "FCS3.2");
+        assert!(matches!(result, Err(FcsError::InvalidKeyword { ref keyword, .. }) if keyword == "$BEGINDATETIME"));
+    }
+
+    #[test]
+    fn test_validate_text_unsupported_version() {
+        let text = keywords(&[("$PAR", "0")]);
+        assert!(matches!(validate_text(&text, "FCS1.0"), Err(FcsError::InvalidVersion(_))));
+    }
+
+    #[test]
+    fn test_is_iso_datetime() {
+        assert!(is_iso_datetime("2017-05-24T14:30:12"));
+        assert!(is_iso_datetime("2017-05-24T14:30:12.345"));
+        assert!(is_iso_datetime("2017-05-24T14:30:12Z"));
+        assert!(is_iso_datetime("2017-05-24T14:30:12.5-05:00"));
+        assert!(!is_iso_datetime("2017-05-24"));
+        assert!(!is_iso_datetime("24-MAY-2017T14:30:12"));
+        assert!(!is_iso_datetime("2017-05-24T14:30"));
+        assert!(!is_iso_datetime("2017-05-24T14:30:12+0500"));
+    }
+
+    fn strict(text: &[u8]) -> Result<HashMap<String, String>, FcsError> {
+        parse_text(text, ParseMode::Strict)
+    }
+
+    fn lenient(text: &[u8]) -> HashMap<String, String> {
+        parse_text(text, ParseMode::Lenient).unwrap()
+    }
+
+    #[test]
+    fn test_parse_text_simple() {
+        let metadata = strict(b"/$PAR/2/$TOT/100/").unwrap();
+        assert_eq!(metadata.len(), 2);
+        assert_eq!(metadata["$PAR"], "2");
+        assert_eq!(metadata["$TOT"], "100");
+    }
+
+    #[test]
+    fn test_parse_text_escaped_delimiters() {
+        let metadata = strict(b"/$P3F/488//10/$FIL/a//b//c/$COM/x////y/").unwrap();
+        assert_eq!(metadata["$P3F"], "488/10");
+        assert_eq!(metadata["$FIL"], "a/b/c");
+        assert_eq!(metadata["$COM"], "x//y");
+    }
+
+    #[test]
+    fn test_parse_text_escaped_delimiter_at_end_of_value() {
+        let metadata = strict(b"/$FIL/dir///$TOT/3/").unwrap();
+        assert_eq!(metadata["$FIL"], "dir/");
+        assert_eq!(metadata["$TOT"], "3");
+    }
+
+    #[test]
+    fn test_parse_text_escaped_delimiter_in_keyword() {
+        let metadata = strict(b"/A//B/1/").unwrap();
+        assert_eq!(metadata["A/B"], "1");
+    }
+
+    #[test]
+    fn test_parse_text_non_dollar_keywords() {
+        let metadata = strict(b"/$P1N/FSC-A/SPILL/2,FL1,FL2,1,0.1,0,1/CREATOR/FlowJo/P1DISPLAY/LOG/GUID/abc-123/$TOT/5/").unwrap();
+        assert_eq!(metadata["$P1N"], "FSC-A");
+        assert_eq!(metadata["SPILL"], "2,FL1,FL2,1,0.1,0,1");
+        assert_eq!(metadata["CREATOR"], "FlowJo");
+        assert_eq!(metadata["P1DISPLAY"], "LOG");
+        assert_eq!(metadata["GUID"], "abc-123");
+        assert_eq!(metadata["$TOT"], "5");
+    }
+
+    #[test]
+    fn test_parse_text_case_insensitive_keywords() {
+        let metadata = strict(b"/$p1n/fsc-a/$Tot/5/#p1Label/CD3/").unwrap();
+        assert_eq!(metadata["$P1N"], "fsc-a");
+        assert_eq!(metadata["$TOT"], "5");
+        assert_eq!(metadata["#P1LABEL"], "CD3");
+    }
+
+    #[test]
+    fn test_parse_text_backslash_delimiter() {
+        let metadata = strict(b"\\$FIL\\C:\\\\data\\$TOT\\7\\").unwrap();
+        assert_eq!(metadata["$FIL"], "C:\\data");
+        assert_eq!(metadata["$TOT"], "7");
+    }
+
+    #[test]
+    fn test_parse_text_unusual_delimiters() {
+        let metadata = strict(b"|$PAR|1|$P1N|FSC||SSC|").unwrap();
+        assert_eq!(metadata["$P1N"], "FSC|SSC");
+
+        let metadata = strict(b"\x0c$PAR\x0c1\x0c$P1N\x0cFSC\x0c").unwrap();
+        assert_eq!(metadata["$P1N"], "FSC");
+    }
+
+    #[test]
+    fn test_parse_text_multi_byte_adjacent() {
+        let text = "/$P1S/CD3 µm/$OP/Zoë/$COM/µ/λ/x/".as_bytes();
+        let metadata = strict(text).unwrap();
+        assert_eq!(metadata["$P1S"], "CD3 µm");
+        assert_eq!(metadata["$OP"], "Zoë");
+        assert_eq!(metadata["$COM"], "µ");
+        assert_eq!(metadata["λ"], "x");
+    }
+
+    #[test]
+    fn test_parse_text_multi_byte_adjacent_to_escape() {
+        let metadata = strict("/$COM/µ//λ/".as_bytes()).unwrap();
+        assert_eq!(metadata["$COM"], "µ/λ");
+    }
+
+    #[test]
+    fn test_parse_text_empty_value_strict() {
+        // A doubled delimiter is an escape, so the keyword swallows the next one
+        let metadata = strict(b"/$BTIM//$ETIM/10:00/").unwrap();
+        assert_eq!(metadata["$BTIM/$ETIM"], "10:00");
+        assert!(!metadata.contains_key("$ETIM"));
+    }
+
+    #[test]
+    fn test_parse_text_empty_value_lenient() {
+        let metadata = lenient(b"/$BTIM//$ETIM/10:00/$P3F/488//10/");
+        assert_eq!(metadata["$BTIM"], "");
+        assert_eq!(metadata["$ETIM"], "10:00");
+        assert_eq!(metadata["$P3F"], "488/10");
+    }
+
+    #[test]
+    fn test_parse_text_space_value() {
+        let metadata = strict(b"/$BTIM/ /$ETIM/10:00/").unwrap();
+        assert_eq!(metadata["$BTIM"], " ");
+        assert_eq!(metadata["$ETIM"], "10:00");
+    }
+
+    #[test]
+    fn test_parse_text_missing_value() {
+        let result = strict(b"/$PAR/2/$TOT/");
+        assert!(matches!(result, Err(FcsError::MalformedText(_))), "Unexpected result {:?}", result);
+
+        let metadata = lenient(b"/$PAR/2/$TOT/");
+        assert_eq!(metadata.len(), 1);
+        assert_eq!(metadata["$PAR"], "2");
+    }
+
+    #[test]
+    fn test_parse_text_missing_final_delimiter() {
+        let result = strict(b"/$PAR/2/$TOT/100");
+        assert!(matches!(result, Err(FcsError::MalformedText(_))), "Unexpected result {:?}", result);
+
+        let metadata = lenient(b"/$PAR/2/$TOT/100");
+        assert_eq!(metadata["$TOT"], "100");
+    }
+
+    #[test]
+    fn test_parse_text_empty_keyword() {
+        let result = strict(b"/$PAR/2/ /x/");
+        assert!(matches!(result, Err(FcsError::MalformedText(_))), "Unexpected result {:?}", result);
+
+        let metadata = lenient(b"/$PAR/2/ /x/");
+        assert_eq!(metadata.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_text_duplicate_keyword() {
+        let result = strict(b"/$PAR/2/$par/3/");
+        assert!(matches!(result, Err(FcsError::MalformedText(_))), "Unexpected result {:?}", result);
+
+        let metadata = lenient(b"/$PAR/2/$par/3/");
+        assert_eq!(metadata["$PAR"], "3");
+    }
+
+    #[test]
+    fn test_parse_text_invalid_utf8() {
+        let result = strict(b"/$COM/\xff\xfe/");
+        assert!(matches!(result, Err(FcsError::InvalidMetadata)), "Unexpected result {:?}", result);
+
+        let metadata = lenient(b"/$COM/\xff\xfe/");
+        assert_eq!(metadata["$COM"], "\u{FFFD}\u{FFFD}");
+    }
+
+    #[test]
+    fn test_parse_text_empty_segment() {
+        assert!(matches!(strict(b""), Err(FcsError::MalformedText(_))));
+        assert!(strict(b"/").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_read_metadata_escaped_values() {
+        let file = File::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+        let mut reader = BufReader::new(&file);
+        let metadata = read_metadata(&mut reader).unwrap();
+
+        assert_eq!(metadata["$P3F"], "488/10");
+        assert_eq!(metadata["$P4F"], "530/30");
+        assert_eq!(metadata["#FLOWRATE"], "100");
+        assert_eq!(metadata["#P4LABEL"], "FITC");
+        assert_eq!(metadata["$ENDANALYSIS"], "000000000000");
+    }
+
+    /// Builds an FCS 3.1 file whose supplemental text segment follows its one-event data segment.
+    fn supplemental_fcs(primary: &[(&str, &str)], supplemental: &[u8]) -> Vec<u8> {
+        let build = |stext: (&str, &str)| {
+            let mut keywords = vec![
+                ("$BEGINANALYSIS", "0"),
+                ("$ENDANALYSIS", "0"),
+                ("$BEGINSTEXT", stext.0),
+                ("$ENDSTEXT", stext.1),
+                ("$BYTEORD", "1,2"),
+                ("$DATATYPE", "I"),
+                ("$MODE", "L"),
+                ("$NEXTDATA", "0"),
+                ("$PAR", "1"),
+                ("$TOT", "1"),
+                ("$P1N", "FSC"),
+                ("$P1B", "16"),
+                ("$P1E", "0,0"),
+                ("$P1R", "65536"),
+            ];
+            keywords.extend_from_slice(primary);
+            crate::test_utils::build_fcs("FCS3.1", &keywords, &[7, 0], true)
+        };
+
+        let start = build(("00000000", "00000000")).len();
+        let end = start + supplemental.len() - 1;
+        let mut bytes = build((&format!("{:08}", start), &format!("{:08}", end)));
+        bytes.extend_from_slice(supplemental);
+        bytes
+    }
+
+    fn read_segments(bytes: &[u8], mode: ParseMode) -> Result<TextSegments, FcsError> {
+        let mut reader = BufReader::new(std::io::Cursor::new(bytes));
+        let header = read_header(&mut reader).unwrap();
+        read_text_segments(&mut reader, &header, mode)
+    }
+
+    #[test]
+    fn test_read_text_segments_supplemental() {
+        let bytes = supplemental_fcs(&[("$COM", "primary")], b"/SPILL/1,FSC,1/$p1s/CD3/");
+
+        let text = read_segments(&bytes, ParseMode::Strict).unwrap();
+        assert_eq!(text.keywords["SPILL"], "1,FSC,1");
+        assert_eq!(text.keywords["$P1S"], "CD3");
+        assert_eq!(text.sources["SPILL"], TextSource::Supplemental);
+        assert_eq!(text.sources["$COM"], TextSource::Primary);
+        assert_eq!(text.sources.len(), text.keywords.len());
+        assert!(text.conflicts.is_empty());
+
+        let metadata = read_metadata(&mut BufReader::new(std::io::Cursor::new(&bytes))).unwrap();
+        assert_eq!(metadata["SPILL"], "1,FSC,1");
+
+        let flow_sample = FcsFile::from_bytes(&bytes).read().unwrap();
+        assert_eq!(flow_sample.parameters()["$P1S"], "CD3");
+        assert_eq!(flow_sample.metadata.sources["$P1S"], TextSource::Supplemental);
+        assert_eq!(flow_sample.metadata.sources["$P1N"], TextSource::Primary);
+    }
+
+    #[test]
+    fn test_read_text_segments_conflict() {
+        // $PAR has the same value in both segments, so only $COM conflicts
+        let bytes = supplemental_fcs(&[("$COM", "primary")], b"/$PAR/1/$COM/supplemental/");
+
+        let result = read_segments(&bytes, ParseMode::Strict);
+        assert!(
+            matches!(
+                result,
+                Err(FcsError::ConflictingKeyword { ref keyword, ref primary, ref supplemental })
+                    if keyword == "$COM" && primary == "primary" && supplemental == "supplemental"
+            ),
+            "Unexpected result {:?}",
+            result,
+        );
+
+        let text = read_segments(&bytes, ParseMode::Lenient).unwrap();
+        assert_eq!(text.keywords["$COM"], "primary");
+        assert_eq!(text.sources["$PAR"], TextSource::Primary);
+        assert_eq!(text.conflicts, vec![KeywordConflict {
+            keyword: "$COM".to_string(),
+            primary: "primary".to_string(),
+            supplemental: "supplemental".to_string(),
+        }]);
+    }
+
+    #[test]
+    fn test_read_text_segments_missing_delimiter() {
+        let bytes = supplemental_fcs(&[], b"$COM/x/");
+
+        let result = read_segments(&bytes, ParseMode::Strict);
+        assert!(matches!(result, Err(FcsError::MalformedText(_))), "Unexpected result {:?}", result);
+
+        let text = read_segments(&bytes, ParseMode::Lenient).unwrap();
+        assert_eq!(text.keywords["$COM"], "x");
+    }
+
+    #[test]
+    fn test_supplemental_offsets() {
+        let offsets = |begin: &str, end: &str, mode| {
+            supplemental_offsets(&keywords(&[("$BEGINSTEXT", begin), ("$ENDSTEXT", end)]), mode)
+        };
+
+        assert_eq!(offsets("100", "199", ParseMode::Strict).unwrap(), Some(100..=199));
+        assert_eq!(offsets(" 0", "0 ", ParseMode::Strict).unwrap(), None);
+        assert_eq!(supplemental_offsets(&HashMap::new(), ParseMode::Strict).unwrap(), None);
+        assert!(matches!(offsets("200", "100", ParseMode::Strict), Err(FcsError::InvalidKeyword { .. })));
+        assert!(matches!(offsets("abc", "100", ParseMode::Strict), Err(FcsError::InvalidKeyword { .. })));
+        assert_eq!(offsets("200", "100", ParseMode::Lenient).unwrap(), None);
+        assert_eq!(offsets("abc", "100", ParseMode::Lenient).unwrap(), None);
+    }
+
+    #[test]
+    fn test_read_analysis_offsets_past_end() {
+        let bytes = supplemental_fcs(&[], b"/SPILL/1,FSC,1/");
+        let mut reader = BufReader::new(std::io::Cursor::new(&bytes));
+        let header = read_header(&mut reader).unwrap();
+        let metadata = keywords(&[("$BEGINANALYSIS", "100"), ("$ENDANALYSIS", "9000000000000000")]);
+
+        let result = read_analysis(&mut reader, &header, &metadata);
+        assert!(matches!(result, Err(FcsError::InvalidOffsets { segment: Segment::Analysis, .. })), "Unexpected result {:?}", result);
+    }
+
     #[test]
     fn test_dataframes_columns() {
         let fcs_file = FcsFile::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
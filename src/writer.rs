@@ -0,0 +1,723 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use byteorder::{LittleEndian, WriteBytesExt};
+use polars::prelude::DataFrame;
+use crate::{FcsError, HashMap};
+use crate::compensation::SPILLOVER_KEYWORDS;
+use crate::crc::{format_crc, Crc16};
+use crate::data::{ByteOrder, DataType, FlowSample};
+use crate::data::transform::{transform_keyword, transform_keyword_index};
+use crate::header::Header;
+
+/// Byte offset of the TEXT segment, which directly follows the 58 byte HEADER.
+const TEXT_START: usize = 58;
+
+/// Number of digits used for the offsets written to TEXT. Zero-padding them to a fixed
+/// width makes the TEXT length independent of the offsets it contains.
+const OFFSET_WIDTH: usize = 20;
+
+/// Largest offset that fits in the 8 byte HEADER fields. Larger offsets are only written to TEXT.
+const MAX_HEADER_OFFSET: usize = 99_999_999;
+
+/// Delimiter used between keywords and values in the written TEXT segment.
+const DELIMITER: char = '/';
+
+/// Keywords describing the layout of the file. They are always recomputed by the writer.
+const LAYOUT_KEYWORDS: [&str; 12] = [
+    "$BEGINANALYSIS",
+    "$BEGINDATA",
+    "$BEGINSTEXT",
+    "$BYTEORD",
+    "$DATATYPE",
+    "$ENDANALYSIS",
+    "$ENDDATA",
+    "$ENDSTEXT",
+    "$MODE",
+    "$NEXTDATA",
+    "$PAR",
+    "$TOT",
+];
+
+/// Options controlling how a `FlowSample` is written to an FCS file.
+///
+/// # Fields
+///
+/// * `data_type` - The `$DATATYPE` of the written data segment. Defaults to `DataType::Float`.
+/// * `byte_order` - The `$BYTEORD` of the written data segment. Defaults to `ByteOrder::LittleEndian`.
+/// * `delimited_ascii` - Whether `DataType::Ascii` data is written as delimited values with a
+///   `$PnB` of `*` rather than as fixed-width values. Defaults to `false`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WriteOptions {
+    pub data_type: DataType,
+    pub byte_order: ByteOrder,
+    pub delimited_ascii: bool,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        Self {
+            data_type: DataType::Float,
+            byte_order: ByteOrder::LittleEndian,
+            delimited_ascii: false,
+        }
+    }
+}
+
+/// The `$PnB` and `$PnR` values of a written column. For ASCII data, `bits` is the number of
+/// characters of each value, or 0 for delimited values.
+struct ColumnFormat {
+    bits: usize,
+    range: String,
+}
+
+/// Writes a `FlowSample` as an FCS 3.1 file with HEADER, TEXT and DATA segments, followed by an
+/// ANALYSIS segment holding `FlowSample::analysis` when it is not empty, and the CRC of all of
+/// them (see the `crc` module).
+///
+/// One parameter is written per DataFrame column, in column order. `$PnN` is the column name,
+/// and `$PnS` and the other `$Pn*` keywords are copied from the channel with that name. The
+/// keywords describing the file layout (`$BEGINDATA`, `$ENDDATA`, `$TOT`, `$PAR`, `$PnB`,
+/// `$PnR`, ...) are recomputed; all other keywords in `metadata.keywords` are copied as they are.
+/// The transform of each transformed column in `FlowSample::transforms` is written to a
+/// `PnTRANSFORM` keyword in its `Display` form, which `FcsFile::read` loads back into
+/// `transforms`.
+///
+/// The spillover keywords in `SPILLOVER_KEYWORDS` are left out if the sample was compensated,
+/// so the written data is not compensated again. `$COMP` applies to parameters 1 to `n` by
+/// position, so it is also left out unless the columns are the original parameters in order.
+///
+/// Integer and ASCII data is written unsigned: values are rounded, and negative values are written
+/// as 0. Each integer column uses the smallest of 16, 32 or 64 bits that fits its maximum, and each
+/// fixed-width ASCII column uses as many digits as its maximum. Delimited ASCII values are
+/// separated by a space.
+///
+/// # Arguments
+///
+/// * `writer` - The destination of the FCS file.
+/// * `sample` - The sample to write.
+/// * `options` - The data type and byte order of the data segment.
+///
+/// # Returns
+///
+/// A Result indicating success or an FcsError.
+///
+/// # Errors
+///
+/// This function will return an FcsError if:
+/// - A DataFrame column cannot be converted to f64 or contains null values.
+/// - There is an I/O error during writing.
+///
+/// # Examples
+///
+/// ```
+/// use fcs_rs::FcsFile;
+/// use fcs_rs::data::{ByteOrder, DataType};
+/// use fcs_rs::writer::{write_fcs, WriteOptions};
+///
+/// let fcs_file = FcsFile::open("./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs").unwrap();
+/// let flow_sample = fcs_file.read().unwrap();
+///
+/// let options = WriteOptions { data_type: DataType::Double, byte_order: ByteOrder::BigEndian, ..Default::default() };
+/// let mut bytes = Vec::new();
+/// write_fcs(&mut bytes, &flow_sample, &options).unwrap();
+///
+/// let written = FcsFile::from_bytes(&bytes).read().unwrap();
+/// assert_eq!(written.data, flow_sample.data);
+/// ```
+pub fn write_fcs<W: Write>(
+    writer: &mut W,
+    sample: &FlowSample,
+    options: &WriteOptions,
+) -> Result<(), FcsError> {
+    let columns = dataframe_columns(&sample.data)?;
+    let formats = columns.iter()
+        .map(|(name, values)| column_format(sample, name, values, options))
+        .collect::<Vec<_>>();
+
+    let data = encode_events(&columns, &formats, options)?;
+
+    let mut text = String::new();
+    for (keyword, value) in text_keywords(sample, &columns, &formats, options) {
+        push_keyword(&mut text, &keyword, &value);
+    }
+
+    let analysis = analysis_segment(&sample.analysis);
+
+    let offsets_len = ["$BEGINDATA", "$ENDDATA", "$BEGINANALYSIS", "$ENDANALYSIS"].iter()
+        .map(|keyword| keyword.len() + OFFSET_WIDTH + 2)
+        .sum::<usize>();
+    let text_end = TEXT_START + text.len() + offsets_len;
+    let (data_start, data_end) = if data.is_empty() {
+        (0, 0)
+    } else {
+        (text_end + 1, text_end + data.len())
+    };
+    let (analysis_start, analysis_end) = if analysis.is_empty() {
+        (0, 0)
+    } else {
+        let start = data_end.max(text_end) + 1;
+        (start, start + analysis.len() - 1)
+    };
+    push_keyword(&mut text, "$BEGINDATA", &format!("{:0width$}", data_start, width = OFFSET_WIDTH));
+    push_keyword(&mut text, "$ENDDATA", &format!("{:0width$}", data_end, width = OFFSET_WIDTH));
+    push_keyword(&mut text, "$BEGINANALYSIS", &format!("{:0width$}", analysis_start, width = OFFSET_WIDTH));
+    push_keyword(&mut text, "$ENDANALYSIS", &format!("{:0width$}", analysis_end, width = OFFSET_WIDTH));
+    text.push(DELIMITER);
+
+    let header = Header {
+        version: "FCS3.1".to_string(),
+        text_offsets: header_offsets(TEXT_START, text_end),
+        data_offsets: header_offsets(data_start, data_end),
+        analysis_offsets: header_offsets(analysis_start, analysis_end),
+    };
+
+    let header = header.to_string();
+    let mut crc = Crc16::new();
+    for segment in [header.as_bytes(), text.as_bytes(), &data, analysis.as_bytes()] {
+        writer.write_all(segment)?;
+        crc.update(segment);
+    }
+    writer.write_all(format_crc(crc.value()).as_bytes())?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Writes a `FlowSample` to the file at `path`, replacing it if it exists.
+///
+/// See `write_fcs` for how the file is built.
+///
+/// # Arguments
+///
+/// * `path` - A string slice representing the path of the FCS file to create.
+/// * `sample` - The sample to write.
+/// * `options` - The data type and byte order of the data segment.
+///
+/// # Returns
+///
+/// A Result indicating success or an FcsError.
+pub fn write_fcs_file(path: &str, sample: &FlowSample, options: &WriteOptions) -> Result<(), FcsError> {
+    let file = File::create(path)?;
+    let mut writer = BufWriter::new(file);
+    write_fcs(&mut writer, sample, options)
+}
+
+/// Appends a keyword and its value to a TEXT segment, each preceded by the delimiter.
+///
+/// Delimiters inside keywords and values are escaped by doubling them, and empty values
+/// are written as a single space because FCS 3.1 does not allow empty values.
+fn push_keyword(text: &mut String, keyword: &str, value: &str) {
+    let escaped_delimiter = DELIMITER.to_string().repeat(2);
+    let value = if value.is_empty() { " " } else { value };
+
+    text.push(DELIMITER);
+    text.push_str(&keyword.replace(DELIMITER, &escaped_delimiter));
+    text.push(DELIMITER);
+    text.push_str(&value.replace(DELIMITER, &escaped_delimiter));
+}
+
+/// Builds the ANALYSIS segment holding `analysis`, sorted by keyword, or an empty string if
+/// there is nothing to write.
+fn analysis_segment(analysis: &HashMap<String, String>) -> String {
+    if analysis.is_empty() {
+        return String::new();
+    }
+
+    let mut keywords = analysis.iter().collect::<Vec<_>>();
+    keywords.sort();
+
+    let mut segment = String::new();
+    for (keyword, value) in keywords {
+        push_keyword(&mut segment, keyword, value);
+    }
+    segment.push(DELIMITER);
+    segment
+}
+
+/// Returns the offsets to write to the HEADER, which are zero if they do not fit its 8 byte fields.
+fn header_offsets(start: usize, end: usize) -> std::ops::RangeInclusive<usize> {
+    if end > MAX_HEADER_OFFSET {
+        0..=0
+    } else {
+        start..=end
+    }
+}
+
+/// Splits a `$Pn*` keyword into its parameter index and suffix, e.g. `$P12N` into `(12, "N")`.
+///
+/// Returns `None` for keywords such as `$PAR` that are not indexed by parameter.
+pub(crate) fn parameter_keyword(keyword: &str) -> Option<(usize, &str)> {
+    let rest = keyword.strip_prefix("$P")?;
+    let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+    let index = rest[..digits].parse::<usize>().ok()?;
+    let suffix = &rest[digits..];
+
+    if suffix.is_empty() {
+        None
+    } else {
+        Some((index, suffix))
+    }
+}
+
+/// Extracts every DataFrame column as a name and a vector of f64 values.
+fn dataframe_columns(data: &DataFrame) -> Result<Vec<(String, Vec<f64>)>, FcsError> {
+    let mut columns = Vec::with_capacity(data.width());
+
+    for series in data.get_columns() {
+        let name = series.name().to_string();
+        let values = series.cast(&polars::prelude::DataType::Float64)
+            .map_err(|err| FcsError::InvalidData(format!("Column {} is not numeric: {}", name, err)))?;
+        let values = values.f64()
+            .map_err(|err| FcsError::InvalidData(format!("Column {} is not numeric: {}", name, err)))?
+            .into_iter()
+            .map(|value| value.ok_or_else(|| FcsError::InvalidData(format!("Column {} contains null values", name))))
+            .collect::<Result<Vec<f64>, FcsError>>()?;
+        columns.push((name, values));
+    }
+
+    Ok(columns)
+}
+
+/// Computes `$PnB` and `$PnR` for a column.
+///
+/// Floating point columns keep the original `$PnR` unless their values exceed it. Integer and
+/// ASCII columns use a range just above their maximum and the smallest width that can hold it.
+/// Values too large for 64 bits are written as `u64::MAX`, so the range saturates there.
+fn column_format(sample: &FlowSample, name: &str, values: &[f64], options: &WriteOptions) -> ColumnFormat {
+    let max = values.iter().cloned().filter(|value| value.is_finite()).fold(0.0, f64::max);
+    let data_type = options.data_type;
+
+    match data_type {
+        DataType::Float | DataType::Double => {
+            let original_range = sample.metadata.parameters.iter()
+                .find(|parameter| parameter.name == name)
+                .and_then(|parameter| parameter.range)
+                .unwrap_or(0.0);
+            let bits = if data_type == DataType::Float { 32 } else { 64 };
+
+            ColumnFormat {
+                bits,
+                range: original_range.max(max.ceil()).max(1.0).to_string(),
+            }
+        },
+        DataType::Integer => {
+            let range = (max.round() as u64).saturating_add(1);
+            let bits = if range <= 1 << 16 {
+                16
+            } else if range <= 1 << 32 {
+                32
+            } else {
+                64
+            };
+
+            ColumnFormat {
+                bits,
+                range: range.to_string(),
+            }
+        },
+        DataType::Ascii => {
+            let max = max.round() as u64;
+            let bits = if options.delimited_ascii { 0 } else { max.to_string().len() };
+
+            ColumnFormat {
+                bits,
+                range: max.saturating_add(1).to_string(),
+            }
+        },
+    }
+}
+
+/// Encodes the columns as list-mode events, writing the values of every parameter for the
+/// first event, followed by the values for the second event, and so on.
+///
+/// Binary values are encoded least significant byte first and then reordered to
+/// `options.byte_order`.
+fn encode_events(
+    columns: &[(String, Vec<f64>)],
+    formats: &[ColumnFormat],
+    options: &WriteOptions,
+) -> Result<Vec<u8>, FcsError> {
+    let data_type = options.data_type;
+    let n_events = columns.first().map_or(0, |(_, values)| values.len());
+    let event_size = formats.iter().map(|format| format.bits / 8).sum::<usize>();
+    let mut data = Vec::with_capacity(n_events * event_size);
+
+    for event in 0..n_events {
+        for (i, ((_, values), format)) in columns.iter().zip(formats).enumerate() {
+            let value = values[event];
+            let start = data.len();
+            match (data_type, format.bits) {
+                (DataType::Ascii, 0) => {
+                    if event > 0 || i > 0 {
+                        data.push(b' ');
+                    }
+                    write!(data, "{}", value.round().max(0.0) as u64)?;
+                },
+                (DataType::Ascii, width) => write!(data, "{:0width$}", value.round().max(0.0) as u64, width = width)?,
+                (DataType::Float, _) => data.write_f32::<LittleEndian>(value as f32)?,
+                (DataType::Double, _) => data.write_f64::<LittleEndian>(value)?,
+                (DataType::Integer, 16) => data.write_u16::<LittleEndian>(value.round().clamp(0.0, u16::MAX as f64) as u16)?,
+                (DataType::Integer, 32) => data.write_u32::<LittleEndian>(value.round().clamp(0.0, u32::MAX as f64) as u32)?,
+                (DataType::Integer, _) => data.write_u64::<LittleEndian>(value.round().clamp(0.0, u64::MAX as f64) as u64)?,
+            }
+            if data_type != DataType::Ascii {
+                options.byte_order.from_little_endian(&mut data[start..])?;
+            }
+        }
+    }
+
+    Ok(data)
+}
+
+/// Builds the ordered TEXT keywords of the written file, except the DATA and ANALYSIS offsets.
+///
+/// Layout keywords come first, then the parameter keywords in parameter order, then every other
+/// keyword of the sample sorted by name so the output is deterministic.
+fn text_keywords(
+    sample: &FlowSample,
+    columns: &[(String, Vec<f64>)],
+    formats: &[ColumnFormat],
+    options: &WriteOptions,
+) -> Vec<(String, String)> {
+    let n_events = columns.first().map_or(0, |(_, values)| values.len());
+    let mut keywords = vec![
+        ("$BYTEORD".to_string(), options.byte_order.keyword()),
+        ("$DATATYPE".to_string(), options.data_type.keyword().to_string()),
+        ("$MODE".to_string(), "L".to_string()),
+        ("$PAR".to_string(), columns.len().to_string()),
+        ("$TOT".to_string(), n_events.to_string()),
+        ("$NEXTDATA".to_string(), "0".to_string()),
+        ("$BEGINSTEXT".to_string(), "0".to_string()),
+        ("$ENDSTEXT".to_string(), "0".to_string()),
+    ];
+
+    // Keywords of the original parameters, grouped by parameter index
+    let mut parameter_keywords: HashMap<usize, Vec<(&str, &String)>> = HashMap::new();
+    for (keyword, value) in &sample.metadata.keywords {
+        if let Some((index, suffix)) = parameter_keyword(keyword) {
+            parameter_keywords.entry(index).or_default().push((suffix, value));
+        }
+    }
+
+    for (i, ((name, _), format)) in columns.iter().zip(formats).enumerate() {
+        let n = i + 1;
+        let channel = sample.channels.iter().find(|channel| &channel.name == name);
+
+        keywords.push((format!("$P{}N", n), name.clone()));
+        if let Some(label) = channel.and_then(|channel| channel.label.as_ref()) {
+            keywords.push((format!("$P{}S", n), label.clone()));
+        }
+        let bits = if options.data_type == DataType::Ascii && format.bits == 0 {
+            "*".to_string()
+        } else {
+            format.bits.to_string()
+        };
+        keywords.push((format!("$P{}B", n), bits));
+        // Values are written on a linear scale, so neither log amplification nor gain applies
+        keywords.push((format!("$P{}E", n), "0,0".to_string()));
+        keywords.push((format!("$P{}R", n), format.range.clone()));
+        if let Some(transform) = sample.transforms.get(name) {
+            keywords.push((transform_keyword(n), transform.to_string()));
+        }
+
+        let mut copied = channel
+            .and_then(|channel| parameter_keywords.get(&channel.index))
+            .map(|keywords| keywords.iter()
+                .filter(|(suffix, _)| !["N", "S", "B", "E", "R", "G", "DATATYPE"].contains(suffix))
+                .map(|(suffix, value)| (format!("$P{}{}", n, suffix), (*value).clone()))
+                .collect::<Vec<_>>())
+            .unwrap_or_default();
+        copied.sort();
+        keywords.extend(copied);
+    }
+
+    let in_order = columns.len() == sample.channels.len()
+        && columns.iter().zip(&sample.channels).all(|((name, _), channel)| name == &channel.name);
+    let spillover_dropped = |keyword: &str| SPILLOVER_KEYWORDS.contains(&keyword)
+        && (sample.compensation.is_some() || (keyword == "$COMP" && !in_order));
+
+    let mut other = sample.metadata.keywords.iter()
+        .filter(|(keyword, _)| !LAYOUT_KEYWORDS.contains(&keyword.as_str()))
+        .filter(|(keyword, _)| !spillover_dropped(keyword))
+        .filter(|(keyword, _)| parameter_keyword(keyword).is_none())
+        .filter(|(keyword, _)| transform_keyword_index(keyword).is_none())
+        .map(|(keyword, value)| (keyword.clone(), value.clone()))
+        .collect::<Vec<_>>();
+    other.sort();
+    keywords.extend(other);
+
+    keywords
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FcsFile;
+    use crate::data::{create_dataframe, Channel, Transform};
+    use crate::metadata::Metadata;
+
+    const EXAMPLE_FILE: &str = "./examples/20200624 LEGENDplex_20200808 CMVMRC5 NY3 pDC.813537.fcs";
+
+    fn write_to_bytes(sample: &FlowSample, options: &WriteOptions) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_fcs(&mut bytes, sample, options).unwrap();
+        bytes
+    }
+
+    fn integer_sample() -> FlowSample {
+        let column_titles = vec!["FSC-A".to_string(), "Time".to_string()];
+        let data = vec![vec![0.0, 1.5, 65535.0], vec![65536.0, 2.5, -3.0]];
+
+        let mut parameters = HashMap::new();
+        parameters.insert("$P1N".to_string(), "FSC-A".to_string());
+        parameters.insert("$P1V".to_string(), "340".to_string());
+        parameters.insert("$P2N".to_string(), "Time".to_string());
+        parameters.insert("$CYT".to_string(), "Test/Cytometer".to_string());
+
+        FlowSample {
+            data: create_dataframe(&column_titles, &data).unwrap(),
+            metadata: Metadata::from_keywords(parameters),
+            channels: vec![
+                Channel { index: 1, name: "FSC-A".to_string(), label: Some("Forward".to_string()) },
+                Channel { index: 2, name: "Time".to_string(), label: None },
+            ],
+            transforms: HashMap::new(),
+            compensation: None,
+            analysis: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_parameter_keyword() {
+        assert_eq!(parameter_keyword("$P12N"), Some((12, "N")));
+        assert_eq!(parameter_keyword("$P1DISPLAY"), Some((1, "DISPLAY")));
+        assert_eq!(parameter_keyword("$PAR"), None);
+        assert_eq!(parameter_keyword("$PROJ"), None);
+        assert_eq!(parameter_keyword("$P3"), None);
+    }
+
+    #[test]
+    fn test_write_roundtrip_all_formats() {
+        let flow_sample = FcsFile::open(EXAMPLE_FILE).unwrap().read().unwrap();
+
+        for data_type in [DataType::Float, DataType::Double] {
+            let permutation = match data_type {
+                DataType::Float => vec![3, 4, 1, 2],
+                _ => vec![5, 6, 7, 8, 1, 2, 3, 4],
+            };
+            for byte_order in [ByteOrder::LittleEndian, ByteOrder::BigEndian, ByteOrder::Permutation(permutation)] {
+                let options = WriteOptions { data_type, byte_order, ..Default::default() };
+                let bytes = write_to_bytes(&flow_sample, &options);
+                let written = FcsFile::from_bytes(&bytes).read().unwrap();
+
+                assert_eq!(written.data, flow_sample.data, "Data mismatch for {:?}", options);
+                assert_eq!(written.channels, flow_sample.channels, "Channel mismatch for {:?}", options);
+                assert_eq!(written.parameters()["$DATATYPE"], data_type.keyword());
+                assert_eq!(written.parameters()["$BYTEORD"], options.byte_order.keyword());
+                assert_eq!(written.parameters()["$TOT"], "8821");
+                assert_eq!(written.parameters()["$PAR"], "10");
+                assert_eq!(written.parameters()["$P6V"], "260");
+                assert_eq!(written.parameters()["$P6R"], "1048576");
+                assert_eq!(written.parameters()["$CYT"], flow_sample.parameters()["$CYT"]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_write_integer() {
+        let sample = integer_sample();
+
+        for byte_order in [ByteOrder::LittleEndian, ByteOrder::BigEndian] {
+            let options = WriteOptions { data_type: DataType::Integer, byte_order, ..Default::default() };
+            let bytes = write_to_bytes(&sample, &options);
+            let written = FcsFile::from_bytes(&bytes).read().unwrap();
+
+            let fsc: Vec<f64> = written.data.column("FSC-A").unwrap().f64().unwrap().into_no_null_iter().collect();
+            let time: Vec<f64> = written.data.column("Time").unwrap().f64().unwrap().into_no_null_iter().collect();
+            assert_eq!(fsc, vec![0.0, 2.0, 65535.0]);
+            assert_eq!(time, vec![65536.0, 3.0, 0.0]);
+
+            assert_eq!(written.parameters()["$P1B"], "16");
+            assert_eq!(written.parameters()["$P1R"], "65536");
+            assert_eq!(written.parameters()["$P2B"], "32");
+            assert_eq!(written.parameters()["$P2R"], "65537");
+            assert_eq!(written.parameters()["$P1V"], "340");
+            assert_eq!(written.get_label("FSC-A"), Some("Forward"));
+        }
+    }
+
+    #[test]
+    fn test_write_ascii() {
+        let sample = integer_sample();
+
+        for delimited_ascii in [false, true] {
+            let options = WriteOptions { data_type: DataType::Ascii, delimited_ascii, ..Default::default() };
+            let bytes = write_to_bytes(&sample, &options);
+            let written = FcsFile::from_bytes(&bytes).read().unwrap();
+
+            let fsc: Vec<f64> = written.data.column("FSC-A").unwrap().f64().unwrap().into_no_null_iter().collect();
+            let time: Vec<f64> = written.data.column("Time").unwrap().f64().unwrap().into_no_null_iter().collect();
+            assert_eq!(fsc, vec![0.0, 2.0, 65535.0]);
+            assert_eq!(time, vec![65536.0, 3.0, 0.0]);
+
+            assert_eq!(written.parameters()["$DATATYPE"], "A");
+            assert_eq!(written.parameters()["$P1B"], if delimited_ascii { "*" } else { "5" });
+            assert_eq!(written.parameters()["$P2B"], if delimited_ascii { "*" } else { "5" });
+            assert_eq!(written.parameters()["$P2R"], "65537");
+        }
+
+        let options = WriteOptions { data_type: DataType::Ascii, delimited_ascii: true, ..Default::default() };
+        let bytes = write_to_bytes(&sample, &options);
+        assert!(bytes[..bytes.len() - 8].ends_with(b"0 65536 2 3 65535 0"), "Data followed by the CRC");
+        let options = WriteOptions { data_type: DataType::Ascii, ..Default::default() };
+        let bytes = write_to_bytes(&sample, &options);
+        assert!(bytes[..bytes.len() - 8].ends_with(b"000006553600002000036553500000"), "Data followed by the CRC");
+    }
+
+    #[test]
+    fn test_write_values_beyond_u64() {
+        let mut sample = integer_sample();
+        sample.data = create_dataframe(&["FSC-A".to_string(), "Time".to_string()], &[vec![1e20, 1.0], vec![2.0, 3.0]]).unwrap();
+
+        for data_type in [DataType::Integer, DataType::Ascii] {
+            let options = WriteOptions { data_type, ..Default::default() };
+            let bytes = write_to_bytes(&sample, &options);
+            let written = FcsFile::from_bytes(&bytes).read_raw().unwrap();
+
+            let fsc: Vec<f64> = written.data.column("FSC-A").unwrap().f64().unwrap().into_no_null_iter().collect();
+            assert_eq!(fsc, vec![u64::MAX as f64, 1.0], "Data mismatch for {:?}", data_type);
+            assert_eq!(written.parameters()["$P1R"], u64::MAX.to_string());
+        }
+    }
+
+    #[test]
+    fn test_write_transforms() {
+        let mut sample = FcsFile::open(EXAMPLE_FILE).unwrap().read().unwrap();
+        let logicle = Transform::logicle(262144.0, 0.5, 4.5, 0.0).unwrap();
+        sample.apply_transform(logicle, &["RL1-A".to_string()]).unwrap();
+        sample.arcsinh_transform(150.0, &["BL1-A".to_string()]).unwrap();
+        sample.data = sample.data.select(["RL1-A", "FSC-A", "BL1-A"]).unwrap();
+
+        let bytes = write_to_bytes(&sample, &WriteOptions { data_type: DataType::Double, ..Default::default() });
+        let text = String::from_utf8_lossy(&bytes);
+        assert!(text.contains("/P1TRANSFORM/logicle(T=262144, W=0.5, M=4.5, A=0)/"));
+        assert!(text.contains("/P3TRANSFORM/arcsinh(cofactor=150)/"));
+        assert!(!text.contains("P2TRANSFORM"));
+
+        let mut written = FcsFile::from_bytes(&bytes).read().unwrap();
+        assert_eq!(written.transforms, sample.transforms);
+        assert_eq!(written.data, sample.data);
+
+        // Written again, the recorded transforms follow their columns
+        written.data = written.data.select(["BL1-A", "FSC-A"]).unwrap();
+        let bytes = write_to_bytes(&written, &WriteOptions { data_type: DataType::Double, ..Default::default() });
+        let rewritten = FcsFile::from_bytes(&bytes).read().unwrap();
+        assert_eq!(rewritten.transforms, HashMap::from([("BL1-A".to_string(), Transform::arcsinh(150.0).unwrap())]));
+
+        written.invert_transform(&["BL1-A".to_string()]).unwrap();
+        let bytes = write_to_bytes(&written, &WriteOptions { data_type: DataType::Double, ..Default::default() });
+        assert!(FcsFile::from_bytes(&bytes).read().unwrap().transforms.is_empty());
+    }
+
+    #[test]
+    fn test_write_spillover() {
+        let mut sample = integer_sample();
+        let mut keywords = sample.metadata.keywords.clone();
+        keywords.insert("SPILL".to_string(), "2,FSC-A,Time,1,0.1,0,1".to_string());
+        keywords.insert("$COMP".to_string(), "2,1,0.1,0,1".to_string());
+        sample.metadata = Metadata::from_keywords(keywords);
+        let written = |sample: &FlowSample| FcsFile::from_bytes(&write_to_bytes(sample, &WriteOptions::default())).read().unwrap().metadata.keywords;
+
+        let keywords = written(&sample);
+        assert_eq!(keywords["SPILL"], "2,FSC-A,Time,1,0.1,0,1");
+        assert_eq!(keywords["$COMP"], "2,1,0.1,0,1");
+
+        // $COMP would apply to the wrong parameters once the columns are reordered
+        let mut reordered = integer_sample();
+        reordered.metadata = sample.metadata.clone();
+        reordered.data = reordered.data.select(["Time", "FSC-A"]).unwrap();
+        let keywords = written(&reordered);
+        assert!(keywords.contains_key("SPILL"));
+        assert!(!keywords.contains_key("$COMP"));
+
+        // Compensated data is not compensated again when read back
+        sample.compensate().unwrap();
+        let keywords = written(&sample);
+        assert!(!keywords.contains_key("SPILL"));
+        assert!(!keywords.contains_key("$COMP"));
+    }
+
+    #[test]
+    fn test_write_analysis() {
+        let mut sample = integer_sample();
+        sample.analysis.insert("GATE1/COUNT".to_string(), "2".to_string());
+        sample.analysis.insert("GATE1 %PARENT".to_string(), "66.7".to_string());
+        let bytes = write_to_bytes(&sample, &WriteOptions::default());
+
+        let header = Header::try_from(std::str::from_utf8(&bytes[..58]).unwrap()).unwrap();
+        assert_eq!(*header.analysis_offsets.start(), header.data_offsets.end() + 1);
+        assert_eq!(bytes[*header.analysis_offsets.start()], b'/');
+        let crc = crate::crc::crc16(&bytes[..=*header.analysis_offsets.end()]);
+        assert_eq!(&bytes[header.analysis_offsets.end() + 1..], crate::crc::format_crc(crc).as_bytes());
+
+        let fcs_file = FcsFile::from_bytes(&bytes);
+        assert_eq!(fcs_file.read_analysis().unwrap(), sample.analysis);
+        let written = fcs_file.read().unwrap();
+        assert_eq!(written.analysis, sample.analysis);
+        let begin_analysis = written.parameters()["$BEGINANALYSIS"].parse::<usize>().unwrap();
+        let end_analysis = written.parameters()["$ENDANALYSIS"].parse::<usize>().unwrap();
+        assert_eq!(begin_analysis..=end_analysis, header.analysis_offsets);
+
+        // Without analysis keywords no segment is written
+        let bytes = write_to_bytes(&integer_sample(), &WriteOptions::default());
+        let header = Header::try_from(std::str::from_utf8(&bytes[..58]).unwrap()).unwrap();
+        assert_eq!(header.analysis_offsets, 0..=0);
+        assert!(FcsFile::from_bytes(&bytes).read_analysis().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_write_header_and_offsets() {
+        let sample = integer_sample();
+        let bytes = write_to_bytes(&sample, &WriteOptions::default());
+
+        let header = Header::try_from(std::str::from_utf8(&bytes[..58]).unwrap()).unwrap();
+        assert_eq!(header.version, "FCS3.1");
+        assert_eq!(*header.text_offsets.start(), 58);
+        assert_eq!(bytes[58], b'/');
+        assert_eq!(bytes[*header.text_offsets.end()], b'/');
+
+        // Two 32-bit floats per event, followed by the CRC of everything before it
+        assert_eq!(*header.data_offsets.start(), header.text_offsets.end() + 1);
+        assert_eq!(header.data_offsets.end() - header.data_offsets.start() + 1, 3 * 2 * 4);
+        let crc = crate::crc::crc16(&bytes[..=*header.data_offsets.end()]);
+        assert_eq!(&bytes[header.data_offsets.end() + 1..], crate::crc::format_crc(crc).as_bytes());
+        assert!(FcsFile::from_bytes(&bytes).verify_crc().unwrap());
+
+        let written = FcsFile::from_bytes(&bytes).read().unwrap();
+        let begin_data = written.parameters()["$BEGINDATA"].parse::<usize>().unwrap();
+        let end_data = written.parameters()["$ENDDATA"].parse::<usize>().unwrap();
+        assert_eq!(begin_data..=end_data, header.data_offsets);
+    }
+
+    #[test]
+    fn test_write_escapes_delimiters() {
+        let sample = integer_sample();
+        let bytes = write_to_bytes(&sample, &WriteOptions::default());
+        let text = String::from_utf8_lossy(&bytes);
+
+        assert!(text.contains("/$CYT/Test//Cytometer/"));
+
+        let written = FcsFile::from_bytes(&bytes).read().unwrap();
+        assert_eq!(written.parameters()["$CYT"], "Test/Cytometer");
+    }
+
+    #[test]
+    fn test_write_fcs_file() {
+        let sample = integer_sample();
+        let path = std::env::temp_dir().join("fcs_rs_write_fcs_file.fcs");
+        write_fcs_file(path.to_str().unwrap(), &sample, &WriteOptions::default()).unwrap();
+
+        let written = FcsFile::open(path.to_str().unwrap()).unwrap().read().unwrap();
+        assert_eq!(written.data, sample.data);
+    }
+}